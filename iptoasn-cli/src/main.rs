@@ -0,0 +1,348 @@
+mod output;
+
+use clap::{Parser, Subcommand};
+use iptoasn_core::{
+    AppError, AsnStore, DataVersion, Database, DatabaseDiff, DatabaseFetcher, EnrichOptions,
+    Enricher, IpField, OverrideSet, Result,
+};
+use output::Format;
+use serde_json::json;
+use std::collections::BTreeSet;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter};
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/**
+ * Command-line tool for IP to ASN lookups and database maintenance
+ */
+#[derive(Parser)]
+#[command(name = "iptoasn", version, about)]
+struct Cli {
+    /// Output format
+    #[arg(long, short, global = true, value_enum, default_value_t = Format::Human)]
+    format: Format,
+
+    /// Database file to read (gzipped or plain TSV) instead of the cached copy
+    #[arg(long, global = true)]
+    file: Option<PathBuf>,
+
+    /// Directory holding the cached database
+    #[arg(long, global = true, env = "CACHE_DIR", default_value = "./cache")]
+    cache_dir: String,
+
+    /// Database URL (HTTP/HTTPS or file://)
+    #[arg(
+        long,
+        global = true,
+        env = "DATABASE_URL",
+        default_value = "https://iptoasn.com/data/ip2asn-combined.tsv.gz"
+    )]
+    url: String,
+
+    /// Overrides file (JSON or iptoasn TSV) whose records take priority in lookups
+    #[arg(long, global = true)]
+    overrides: Option<PathBuf>,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Look up IP addresses (reads one per line from stdin when none are given)
+    Lookup {
+        /// IPv4 or IPv6 addresses
+        ips: Vec<String>,
+    },
+    /// Download the database into the cache directory if it changed
+    Fetch,
+    /// Parse a database file and report problems
+    ///
+    /// Exits with status 1 when lines had to be skipped.
+    Validate {
+        /// Database file (gzipped or plain TSV)
+        file: PathBuf,
+    },
+    /// Show statistics about the database
+    Stats,
+    /// List the ranges announced by an ASN
+    Asn {
+        /// AS number, with or without the "AS" prefix
+        #[arg(value_parser = asn_arg)]
+        number: u32,
+    },
+    /// Compare two database files (gzipped or plain TSV)
+    ///
+    /// Exits with status 1 when the files differ, like diff(1).
+    Diff {
+        /// Old database file
+        old: PathBuf,
+        /// New database file
+        new: PathBuf,
+        /// Only print the number of changes
+        #[arg(long)]
+        summary: bool,
+    },
+    /// Append AS number, country and description columns to delimited records
+    ///
+    /// Reads from stdin and writes to stdout unless files are given.
+    Enrich {
+        /// Zero-based column holding the IP address
+        #[arg(long, conflicts_with = "regex", required_unless_present = "regex")]
+        field: Option<usize>,
+        /// Pattern locating the IP address (first capture group, or the whole match)
+        #[arg(long)]
+        regex: Option<String>,
+        /// Column delimiter
+        #[arg(long, short, default_value_t = ',')]
+        delimiter: char,
+        /// Treat the first line as a header
+        #[arg(long)]
+        header: bool,
+        /// Output file
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+        /// Input file
+        input: Option<PathBuf>,
+    },
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+
+    let result = match &cli.command {
+        Command::Lookup { ips } => lookup(&cli, ips),
+        Command::Fetch => fetch(&cli),
+        Command::Validate { file } => validate(cli.format, file),
+        Command::Stats => stats(&cli),
+        Command::Asn { number } => asn(&cli, *number),
+        Command::Diff { old, new, summary } => diff(cli.format, old, new, *summary),
+        Command::Enrich {
+            field,
+            regex,
+            delimiter,
+            header,
+            output,
+            input,
+        } => enrich(&cli, *field, regex.as_deref(), *delimiter, *header, input, output),
+    };
+
+    match result {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            ExitCode::from(2)
+        }
+    }
+}
+
+// Load the database from --file or the cache directory, with its version when cached
+fn open_database(cli: &Cli) -> Result<(Database, Option<DataVersion>)> {
+    if let Some(file) = &cli.file {
+        return Ok((Database::load_file(file)?, None));
+    }
+
+    let mut fetcher = DatabaseFetcher::new(cli.url.clone(), &cli.cache_dir)?;
+    let data = fetcher.load_from_cache().inspect_err(|e| {
+        if matches!(e, AppError::DatabaseNotLoaded) {
+            eprintln!("No cached database in {}; run `iptoasn fetch` first", cli.cache_dir);
+        }
+    })?;
+
+    Ok((Database::parse(data)?, Some(fetcher.version())))
+}
+
+// Build a lookup store, applying --overrides
+fn open_store(cli: &Cli) -> Result<AsnStore> {
+    let (database, _) = open_database(cli)?;
+    let store = AsnStore::new(database);
+    match &cli.overrides {
+        Some(path) => Ok(store.with_overrides(Arc::new(OverrideSet::load_file(path)?))),
+        None => Ok(store),
+    }
+}
+
+fn unix_secs(time: Option<SystemTime>) -> Option<u64> {
+    time.and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_secs())
+}
+
+fn lookup(cli: &Cli, ips: &[String]) -> Result<ExitCode> {
+    let store = open_store(cli)?;
+    let mut invalid = false;
+
+    let mut lookup_one = |ip: &str| {
+        let ip = ip.trim();
+        if ip.is_empty() {
+            return;
+        }
+        match ip.parse::<IpAddr>() {
+            Ok(addr) => output::print_lookup(cli.format, ip, store.lookup(addr)),
+            Err(_) => {
+                eprintln!("Invalid IP address: {}", ip);
+                invalid = true;
+            }
+        }
+    };
+
+    if ips.is_empty() {
+        for line in std::io::stdin().lock().lines() {
+            lookup_one(&line?);
+        }
+    } else {
+        ips.iter().for_each(|ip| lookup_one(ip));
+    }
+
+    Ok(if invalid {
+        ExitCode::from(1)
+    } else {
+        ExitCode::SUCCESS
+    })
+}
+
+fn fetch(cli: &Cli) -> Result<ExitCode> {
+    let mut fetcher = DatabaseFetcher::new(cli.url.clone(), &cli.cache_dir)?;
+
+    let runtime = tokio::runtime::Runtime::new()?;
+    let downloaded = runtime.block_on(fetcher.fetch())?;
+
+    let version = fetcher.version();
+    output::print_fields(
+        cli.format,
+        &[
+            ("updated", json!(downloaded.is_some())),
+            ("bytes", json!(downloaded.map(|data| data.len()))),
+            ("cache", json!(fetcher.cache_path().display().to_string())),
+            ("data_modified", json!(unix_secs(version.upstream_modified))),
+        ],
+    );
+    Ok(ExitCode::SUCCESS)
+}
+
+fn validate(format: Format, file: &Path) -> Result<ExitCode> {
+    let text = Database::decode(std::fs::read(file)?)?;
+    let (_, report) = Database::parse_tsv_with_report(&text)?;
+
+    for issue in &report.issues {
+        eprintln!("line {}: {}", issue.line, issue.message);
+    }
+
+    output::print_fields(
+        format,
+        &[
+            ("lines", json!(report.lines)),
+            ("records", json!(report.records)),
+            ("ipv4_records", json!(report.ipv4_records)),
+            ("ipv6_records", json!(report.ipv6_records)),
+            ("unique_countries", json!(report.unique_countries)),
+            ("unique_descriptions", json!(report.unique_descriptions)),
+            ("overlapping_ranges", json!(report.overlapping_ranges)),
+            ("skipped_lines", json!(report.issues.len())),
+        ],
+    );
+
+    Ok(if report.issues.is_empty() {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::from(1)
+    })
+}
+
+fn stats(cli: &Cli) -> Result<ExitCode> {
+    let (database, version) = open_database(cli)?;
+    let records = &database.records;
+
+    let asns: BTreeSet<u32> = records.iter().map(|r| r.number).collect();
+    let countries: BTreeSet<&str> = records.iter().map(|r| &*r.country).collect();
+    let version = version.unwrap_or_default();
+
+    output::print_fields(
+        cli.format,
+        &[
+            ("records", json!(records.len())),
+            ("ipv4_records", json!(records.iter().filter(|r| r.first_ip.is_ipv4()).count())),
+            ("ipv6_records", json!(records.iter().filter(|r| r.first_ip.is_ipv6()).count())),
+            ("asns", json!(asns.len())),
+            ("countries", json!(countries.len())),
+            ("data_modified", json!(unix_secs(version.upstream_modified))),
+            ("downloaded_at", json!(unix_secs(version.downloaded_at))),
+            ("data_age_seconds", json!(version.age().map(|d| d.as_secs()))),
+        ],
+    );
+    Ok(ExitCode::SUCCESS)
+}
+
+// Accept "15169", "AS15169" or "as15169"
+fn asn_arg(value: &str) -> std::result::Result<u32, String> {
+    iptoasn_core::parse_asn(value).ok_or_else(|| format!("invalid AS number: {}", value))
+}
+
+fn asn(cli: &Cli, number: u32) -> Result<ExitCode> {
+    let (database, _) = open_database(cli)?;
+    let ranges: Vec<_> = database.records.iter().filter(|r| r.number == number).collect();
+
+    output::print_records(cli.format, ranges.iter().copied());
+
+    Ok(if ranges.is_empty() {
+        ExitCode::from(1)
+    } else {
+        ExitCode::SUCCESS
+    })
+}
+
+fn diff(format: Format, old: &Path, new: &Path, summary: bool) -> Result<ExitCode> {
+    let old = Database::load_file(old)?;
+    let new = Database::load_file(new)?;
+    let diff = DatabaseDiff::compute(&old.records, &new.records);
+
+    output::print_diff(format, &diff, summary);
+
+    Ok(if diff.is_empty() {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::from(1)
+    })
+}
+
+fn enrich(
+    cli: &Cli,
+    field: Option<usize>,
+    regex: Option<&str>,
+    delimiter: char,
+    header: bool,
+    input: &Option<PathBuf>,
+    output: &Option<PathBuf>,
+) -> Result<ExitCode> {
+    let field = match regex {
+        Some(pattern) => IpField::pattern(pattern)?,
+        None => IpField::Column(field.unwrap_or_default()),
+    };
+
+    let enricher = Enricher::new(
+        open_store(cli)?,
+        EnrichOptions {
+            field,
+            delimiter,
+            header,
+        },
+    );
+
+    let reader: Box<dyn BufRead> = match input {
+        Some(path) => Box::new(BufReader::new(File::open(path)?)),
+        None => Box::new(std::io::stdin().lock()),
+    };
+    let summary = match output {
+        Some(path) => enricher.enrich_stream(reader, BufWriter::new(File::create(path)?))?,
+        None => enricher.enrich_stream(reader, BufWriter::new(std::io::stdout().lock()))?,
+    };
+
+    eprintln!(
+        "{} lines, {} matched, {} without an IP address",
+        summary.lines, summary.matched, summary.invalid
+    );
+    Ok(ExitCode::SUCCESS)
+}
@@ -0,0 +1,187 @@
+use clap::ValueEnum;
+use iptoasn_core::{AsnRecord, DatabaseDiff};
+use serde_json::{json, Map, Value};
+
+/**
+ * Output format shared by all subcommands
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    /// Aligned text for people
+    Human,
+    /// One JSON document per result
+    Json,
+    /// Tab-separated values in iptoasn column order
+    Tsv,
+}
+
+// JSON representation of a record, using the iptoasn.com field names
+pub fn record_json(record: &AsnRecord) -> Value {
+    json!({
+        "first_ip": record.first_ip.to_string(),
+        "last_ip": record.last_ip.to_string(),
+        "as_number": record.number,
+        "as_country_code": &*record.country,
+        "as_description": &*record.description,
+    })
+}
+
+// TSV columns of a record: first_ip, last_ip, asn, country, description
+fn record_tsv(record: &AsnRecord) -> String {
+    format!(
+        "{}\t{}\t{}\t{}\t{}",
+        record.first_ip, record.last_ip, record.number, record.country, record.description
+    )
+}
+
+// Single human-readable line for a record
+fn record_human(record: &AsnRecord) -> String {
+    format!(
+        "{}-{} AS{} {} {}",
+        record.first_ip, record.last_ip, record.number, record.country, record.description
+    )
+}
+
+// Print the result of looking up one address
+pub fn print_lookup(format: Format, ip: &str, record: Option<&AsnRecord>) {
+    match (format, record) {
+        (Format::Human, Some(r)) => println!(
+            "{}\tAS{}\t{}\t{}\t({}-{})",
+            ip, r.number, r.country, r.description, r.first_ip, r.last_ip
+        ),
+        (Format::Human, None) => println!("{}\tnot announced", ip),
+        (Format::Json, Some(r)) => {
+            let mut value = record_json(r);
+            value["ip"] = json!(ip);
+            value["announced"] = json!(true);
+            println!("{}", value);
+        }
+        (Format::Json, None) => println!("{}", json!({ "ip": ip, "announced": false })),
+        (Format::Tsv, Some(r)) => println!("{}\t{}", ip, record_tsv(r)),
+        (Format::Tsv, None) => println!("{}\t\t\t0\tNone\tNot routed", ip),
+    }
+}
+
+// Print a list of records
+pub fn print_records<'a>(format: Format, records: impl IntoIterator<Item = &'a AsnRecord>) {
+    match format {
+        Format::Human => records.into_iter().for_each(|r| println!("{}", record_human(r))),
+        Format::Tsv => records.into_iter().for_each(|r| println!("{}", record_tsv(r))),
+        Format::Json => {
+            let list: Vec<Value> = records.into_iter().map(record_json).collect();
+            println!("{}", Value::Array(list));
+        }
+    }
+}
+
+// Print named values, e.g. statistics or a validation report
+pub fn print_fields(format: Format, fields: &[(&str, Value)]) {
+    match format {
+        Format::Human => {
+            let width = fields.iter().map(|(name, _)| name.len()).max().unwrap_or(0);
+            for (name, value) in fields {
+                println!("{:width$}  {}", name, display(value), width = width);
+            }
+        }
+        Format::Tsv => {
+            for (name, value) in fields {
+                println!("{}\t{}", name, display(value));
+            }
+        }
+        Format::Json => {
+            let object: Map<String, Value> = fields
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.clone()))
+                .collect();
+            println!("{}", Value::Object(object));
+        }
+    }
+}
+
+// Display a JSON value without quoting strings
+fn display(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        Value::Null => "-".to_string(),
+        other => other.to_string(),
+    }
+}
+
+// Print a database diff
+pub fn print_diff(format: Format, diff: &DatabaseDiff, summary: bool) {
+    match format {
+        Format::Human => print_diff_human(diff, summary),
+        Format::Tsv => {
+            for record in &diff.removed {
+                println!("-\t{}", record_tsv(record));
+            }
+            for record in &diff.added {
+                println!("+\t{}", record_tsv(record));
+            }
+            for change in &diff.changed {
+                println!("~\t{}", record_tsv(&change.new));
+            }
+        }
+        Format::Json => {
+            let value = if summary {
+                json!({
+                    "removed": diff.removed.len(),
+                    "added": diff.added.len(),
+                    "changed": diff.changed.len(),
+                    "asns_added": diff.asns_added,
+                    "asns_removed": diff.asns_removed,
+                })
+            } else {
+                json!({
+                    "removed": diff.removed.iter().map(record_json).collect::<Vec<_>>(),
+                    "added": diff.added.iter().map(record_json).collect::<Vec<_>>(),
+                    "changed": diff.changed.iter().map(|c| json!({
+                        "old": record_json(&c.old),
+                        "new": record_json(&c.new),
+                    })).collect::<Vec<_>>(),
+                    "asns_added": diff.asns_added,
+                    "asns_removed": diff.asns_removed,
+                })
+            };
+            println!("{}", value);
+        }
+    }
+}
+
+fn print_diff_human(diff: &DatabaseDiff, summary: bool) {
+    println!("Removed ranges: {}", diff.removed.len());
+    if !summary {
+        for record in &diff.removed {
+            println!("  - {}", record_human(record));
+        }
+    }
+
+    println!("Added ranges: {}", diff.added.len());
+    if !summary {
+        for record in &diff.added {
+            println!("  + {}", record_human(record));
+        }
+    }
+
+    println!("Changed ranges: {}", diff.changed.len());
+    if !summary {
+        for change in &diff.changed {
+            println!(
+                "  ~ {} -> AS{} {} {}",
+                record_human(&change.old),
+                change.new.number,
+                change.new.country,
+                change.new.description
+            );
+        }
+    }
+
+    let join = |asns: &[u32]| {
+        asns.iter()
+            .map(|n| format!("AS{}", n))
+            .collect::<Vec<_>>()
+            .join(" ")
+    };
+    println!("ASNs added: {} {}", diff.asns_added.len(), join(&diff.asns_added));
+    println!("ASNs removed: {} {}", diff.asns_removed.len(), join(&diff.asns_removed));
+}
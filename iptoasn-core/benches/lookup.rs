@@ -0,0 +1,71 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use iptoasn_core::{AsnStore, IpToAsnDb, SyntheticDataset};
+use std::net::IpAddr;
+
+// Addresses inside every `step`th record of a family
+fn hits(store: &AsnStore, ipv6: bool, step: usize) -> Vec<IpAddr> {
+    store
+        .records()
+        .iter()
+        .filter(|r| r.first_ip.is_ipv6() == ipv6)
+        .step_by(step)
+        .map(|r| r.first_ip)
+        .collect()
+}
+
+fn bench_store(c: &mut Criterion) {
+    let store = AsnStore::new(SyntheticDataset::default().database().unwrap());
+    let v4 = hits(&store, false, 997)[100];
+    let v6 = hits(&store, true, 997)[100];
+    // Outside the generated space: 240.0.0.0/4 and fc00::/7
+    let v4_miss: IpAddr = "240.0.0.1".parse().unwrap();
+    let v6_miss: IpAddr = "fc00::1".parse().unwrap();
+
+    let mut group = c.benchmark_group("store");
+    group.bench_function("v4_hit", |b| b.iter(|| store.lookup(black_box(v4))));
+    group.bench_function("v4_miss", |b| b.iter(|| store.lookup(black_box(v4_miss))));
+    group.bench_function("v6_hit", |b| b.iter(|| store.lookup(black_box(v6))));
+    group.bench_function("v6_miss", |b| b.iter(|| store.lookup(black_box(v6_miss))));
+    group.finish();
+}
+
+fn bench_db(c: &mut Criterion) {
+    // A cache directory of its own, so bench runs don't write into the temp root
+    let cache_dir = std::env::temp_dir().join(format!("iptoasn_bench_{}", std::process::id()));
+    let db = IpToAsnDb::new("file:///dev/null".to_string(), cache_dir.display().to_string()).unwrap();
+    let store = AsnStore::new(SyntheticDataset::default().database().unwrap());
+    let batch = hits(&store, false, 400);
+    *db.get_store().blocking_write() = store;
+
+    let addr = batch[100];
+    let ip = addr.to_string();
+
+    let mut group = c.benchmark_group("lookup");
+    group.bench_function("string", |b| b.iter(|| db.lookup(black_box(&ip)).unwrap()));
+    group.bench_function("addr", |b| b.iter(|| db.lookup_addr(black_box(addr)).unwrap()));
+    group.bench_function("addr_cached", |b| {
+        db.set_lookup_cache(1024);
+        b.iter(|| db.lookup_addr(black_box(addr)).unwrap());
+        db.set_lookup_cache(0);
+    });
+    group.finish();
+
+    let mut group = c.benchmark_group("batch");
+    group.throughput(Throughput::Elements(batch.len() as u64));
+    group.bench_function("lookup_numbers", |b| {
+        b.iter(|| db.lookup_numbers(black_box(batch.iter().copied())).unwrap())
+    });
+    group.bench_function("lookup_addr", |b| {
+        b.iter(|| {
+            batch
+                .iter()
+                .map(|&ip| db.lookup_addr(ip).unwrap())
+                .collect::<Vec<_>>()
+        })
+    });
+    group.finish();
+    let _ = std::fs::remove_dir_all(&cache_dir);
+}
+
+criterion_group!(benches, bench_store, bench_db);
+criterion_main!(benches);
@@ -0,0 +1,21 @@
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+use iptoasn_core::{Database, SyntheticDataset};
+
+fn bench_parse(c: &mut Criterion) {
+    let dataset = SyntheticDataset::default();
+    let gzipped = dataset.gzip();
+    let tsv = dataset.tsv();
+
+    let mut group = c.benchmark_group("parse");
+    // A full-size parse takes a good fraction of a second
+    group.sample_size(10);
+    group.throughput(Throughput::Bytes(tsv.len() as u64));
+    group.bench_function("gzip", |b| {
+        b.iter_batched(|| gzipped.clone(), Database::parse, BatchSize::LargeInput)
+    });
+    group.bench_function("tsv", |b| b.iter(|| Database::parse_tsv(&tsv).unwrap()));
+    group.finish();
+}
+
+criterion_group!(benches, bench_parse);
+criterion_main!(benches);
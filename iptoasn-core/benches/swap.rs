@@ -0,0 +1,48 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use iptoasn_core::{AsnStore, IpToAsnDb, SyntheticDataset};
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+// Lookup latency while another thread keeps swapping in a new store
+fn bench_swap(c: &mut Criterion) {
+    // A cache directory of its own, so bench runs don't write into the temp root
+    let cache_dir = std::env::temp_dir().join(format!("iptoasn_bench_{}", std::process::id()));
+    let db = IpToAsnDb::new("file:///dev/null".to_string(), cache_dir.display().to_string()).unwrap();
+    let stores = [1, 2].map(|seed| {
+        let dataset = SyntheticDataset {
+            seed,
+            ..SyntheticDataset::default()
+        };
+        AsnStore::new(dataset.database().unwrap())
+    });
+    let ip: IpAddr = stores[0].records()[stores[0].len() / 3].first_ip;
+    let shared = db.get_store();
+    *shared.blocking_write() = stores[0].clone();
+
+    let mut group = c.benchmark_group("swap");
+    group.bench_function("lookup_idle", |b| b.iter(|| db.lookup_addr(black_box(ip)).unwrap()));
+
+    let done = AtomicBool::new(false);
+    std::thread::scope(|scope| {
+        scope.spawn(|| {
+            for store in stores.iter().cycle() {
+                if done.load(Ordering::Relaxed) {
+                    break;
+                }
+                *shared.blocking_write() = store.clone();
+                std::thread::sleep(Duration::from_millis(1));
+            }
+        });
+
+        group.bench_function("lookup_while_swapping", |b| {
+            b.iter(|| db.lookup_addr(black_box(ip)).unwrap())
+        });
+        done.store(true, Ordering::Relaxed);
+    });
+    group.finish();
+    let _ = std::fs::remove_dir_all(&cache_dir);
+}
+
+criterion_group!(benches, bench_swap);
+criterion_main!(benches);
@@ -0,0 +1,211 @@
+use crate::parser::AsnRecord;
+use crate::range::to_u128;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/**
+ * Address space announced by a set of ranges
+ */
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SpaceTotals {
+    pub ipv4_addresses: u64,
+    // Distinct IPv6 /48 networks touched by any range
+    pub ipv6_48s: u64,
+    pub ipv4_ranges: usize,
+    pub ipv6_ranges: usize,
+}
+
+// Totals being built, with the last /48 counted so a /48 shared by several
+// ranges is only counted once
+#[derive(Debug, Default)]
+struct Tally {
+    totals: SpaceTotals,
+    last_48: Option<u128>,
+}
+
+impl Tally {
+    // Add one range to the totals; ranges must come sorted by first address
+    fn add(&mut self, record: &AsnRecord) {
+        let first = to_u128(record.first_ip);
+        let last = to_u128(record.last_ip);
+        if record.first_ip.is_ipv4() {
+            self.totals.ipv4_addresses += (last - first + 1) as u64;
+            self.totals.ipv4_ranges += 1;
+        } else {
+            let (first, last) = (first >> 80, last >> 80);
+            let first = self.last_48.map_or(first, |counted| first.max(counted + 1));
+            if first <= last {
+                self.totals.ipv6_48s += (last - first + 1) as u64;
+                self.last_48 = Some(last);
+            }
+            self.totals.ipv6_ranges += 1;
+        }
+    }
+}
+
+/**
+ * Address space announced by one ASN
+ */
+#[derive(Debug, Clone)]
+pub struct AsnAggregate {
+    pub number: u32,
+    pub totals: SpaceTotals,
+}
+
+/**
+ * Address space registered in one country
+ */
+#[derive(Debug, Clone)]
+pub struct CountryAggregate {
+    pub country: Arc<str>,
+    // Distinct ASNs with ranges in the country
+    pub asns: usize,
+    pub totals: SpaceTotals,
+}
+
+/**
+ * Address space totals per ASN, per country and overall
+ *
+ * Ranges with ASN 0 (not routed) are left out.
+ */
+#[derive(Debug, Clone, Default)]
+pub struct Aggregates {
+    pub total: SpaceTotals,
+    // Sorted by number
+    pub asns: Vec<AsnAggregate>,
+    // Sorted by IPv4 addresses, largest first
+    pub countries: Vec<CountryAggregate>,
+}
+
+impl Aggregates {
+    // Compute the aggregates from records sorted by range
+    pub fn new(records: &[AsnRecord]) -> Self {
+        let mut total = Tally::default();
+        let mut asns: HashMap<u32, Tally> = HashMap::new();
+        let mut countries: HashMap<Arc<str>, (Tally, Vec<u32>)> = HashMap::new();
+
+        for record in records.iter().filter(|r| r.number != 0) {
+            total.add(record);
+            asns.entry(record.number).or_default().add(record);
+
+            let (totals, numbers) = countries.entry(record.country.clone()).or_default();
+            totals.add(record);
+            numbers.push(record.number);
+        }
+
+        let mut asns: Vec<AsnAggregate> = asns
+            .into_iter()
+            .map(|(number, tally)| AsnAggregate {
+                number,
+                totals: tally.totals,
+            })
+            .collect();
+        asns.sort_unstable_by_key(|a| a.number);
+
+        let mut countries: Vec<CountryAggregate> = countries
+            .into_iter()
+            .map(|(country, (tally, mut numbers))| {
+                numbers.sort_unstable();
+                numbers.dedup();
+                CountryAggregate {
+                    country,
+                    asns: numbers.len(),
+                    totals: tally.totals,
+                }
+            })
+            .collect();
+        countries.sort_unstable_by(|a, b| {
+            b.totals
+                .ipv4_addresses
+                .cmp(&a.totals.ipv4_addresses)
+                .then_with(|| a.country.cmp(&b.country))
+        });
+
+        Self {
+            total: total.totals,
+            asns,
+            countries,
+        }
+    }
+
+    // Totals for one ASN
+    pub fn asn(&self, number: u32) -> Option<&AsnAggregate> {
+        self.asns
+            .binary_search_by_key(&number, |a| a.number)
+            .ok()
+            .map(|index| &self.asns[index])
+    }
+
+    // Totals for one country (case-insensitive)
+    pub fn country(&self, country: &str) -> Option<&CountryAggregate> {
+        self.countries
+            .iter()
+            .find(|c| c.country.eq_ignore_ascii_case(country))
+    }
+
+    // The `n` ASNs announcing the most IPv4 addresses
+    pub fn top_asns_by_ipv4(&self, n: usize) -> Vec<&AsnAggregate> {
+        self.top_asns(n, |totals| totals.ipv4_addresses)
+    }
+
+    // The `n` ASNs announcing the most IPv6 /48s
+    pub fn top_asns_by_ipv6(&self, n: usize) -> Vec<&AsnAggregate> {
+        self.top_asns(n, |totals| totals.ipv6_48s)
+    }
+
+    fn top_asns(&self, n: usize, key: fn(&SpaceTotals) -> u64) -> Vec<&AsnAggregate> {
+        let mut top: Vec<&AsnAggregate> = self.asns.iter().filter(|a| key(&a.totals) > 0).collect();
+        top.sort_unstable_by(|a, b| key(&b.totals).cmp(&key(&a.totals)).then(a.number.cmp(&b.number)));
+        top.truncate(n);
+        top
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Database;
+
+    #[test]
+    fn test_aggregates() {
+        let data = "1.0.0.0\t1.0.0.255\t13335\tUS\tCLOUDFLARENET\n\
+                    3.0.0.0\t3.255.255.255\t16509\tUS\tAMAZON-02\n\
+                    5.0.0.0\t5.0.1.255\t3320\tDE\tDTAG\n\
+                    10.0.0.0\t10.255.255.255\t0\tNone\tNot routed\n\
+                    2001:db8::\t2001:db8:1:ffff::\t16509\tUS\tAMAZON-02\n\
+                    2a00::\t2a00::ff\t3320\tDE\tDTAG\n";
+        let aggregates = Aggregates::new(&Database::parse_tsv(data).unwrap().records);
+
+        assert_eq!(aggregates.total.ipv4_addresses, 256 + (1 << 24) + 512);
+        assert_eq!(aggregates.total.ipv6_48s, 3);
+
+        let amazon = aggregates.asn(16509).unwrap().totals;
+        assert_eq!(amazon.ipv6_48s, 2);
+        assert_eq!(amazon.ipv4_ranges, 1);
+
+        let us = aggregates.country("us").unwrap();
+        assert_eq!(us.asns, 2);
+        assert_eq!(aggregates.countries[0].country.as_ref(), "US");
+        assert!(aggregates.country("None").is_none());
+
+        let top: Vec<u32> = aggregates.top_asns_by_ipv4(2).iter().map(|a| a.number).collect();
+        assert_eq!(top, vec![16509, 3320]);
+        let top: Vec<u32> = aggregates.top_asns_by_ipv6(5).iter().map(|a| a.number).collect();
+        assert_eq!(top, vec![16509, 3320]);
+    }
+
+    #[test]
+    fn test_ipv6_48s_counted_once() {
+        // Two ranges inside 2001:db8::/48, then one spanning it and the next /48
+        let data = "2001:db8::\t2001:db8::ff\t64496\tUS\tTEST\n\
+                    2001:db8:0:1::\t2001:db8:0:1::ff\t64496\tUS\tTEST\n\
+                    2001:db8:0:2::\t2001:db8:1:ffff::\t64497\tUS\tOTHER\n";
+        let aggregates = Aggregates::new(&Database::parse_tsv(data).unwrap().records);
+
+        assert_eq!(aggregates.asn(64496).unwrap().totals.ipv6_48s, 1);
+        assert_eq!(aggregates.asn(64497).unwrap().totals.ipv6_48s, 2);
+        assert_eq!(aggregates.country("US").unwrap().totals.ipv6_48s, 2);
+        assert_eq!(aggregates.total.ipv6_48s, 2);
+        assert_eq!(aggregates.total.ipv6_ranges, 3);
+    }
+}
@@ -0,0 +1,141 @@
+use crate::LookupResult;
+use lru::LruCache;
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+use std::net::IpAddr;
+use std::num::NonZeroUsize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+// Independent LRU shards, so concurrent lookups rarely contend on a lock
+const SHARDS: usize = 16;
+
+/**
+ * Cache counters
+ */
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub capacity: usize,
+    pub entries: usize,
+    pub hits: u64,
+    pub misses: u64,
+}
+
+/**
+ * Bounded LRU cache of lookup results, keyed by parsed address
+ *
+ * Each shard evicts on its own, so the capacity is split evenly between
+ * them. Clearing bumps a generation counter; results computed against the
+ * previous data carry the old generation and are dropped on insert.
+ */
+pub struct LookupCache {
+    shards: Vec<Mutex<LruCache<IpAddr, LookupResult>>>,
+    hasher: RandomState,
+    capacity: usize,
+    generation: AtomicU64,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl LookupCache {
+    // Create a cache holding at least `capacity` results
+    pub fn new(capacity: NonZeroUsize) -> Self {
+        let per_shard = NonZeroUsize::new(capacity.get().div_ceil(SHARDS)).unwrap_or(NonZeroUsize::MIN);
+        Self {
+            shards: (0..SHARDS).map(|_| Mutex::new(LruCache::new(per_shard))).collect(),
+            hasher: RandomState::new(),
+            capacity: per_shard.get() * SHARDS,
+            generation: AtomicU64::new(0),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    fn shard(&self, ip: &IpAddr) -> &Mutex<LruCache<IpAddr, LookupResult>> {
+        &self.shards[self.hasher.hash_one(ip) as usize % SHARDS]
+    }
+
+    // The cached result for an address, counting the hit or miss
+    pub fn get(&self, ip: &IpAddr) -> Option<LookupResult> {
+        let result = self.shard(ip).lock().unwrap().get(ip).cloned();
+        let counter = if result.is_some() { &self.hits } else { &self.misses };
+        counter.fetch_add(1, Ordering::Relaxed);
+        result
+    }
+
+    // Generation to pass to `insert` for a result about to be computed
+    pub fn generation(&self) -> u64 {
+        self.generation.load(Ordering::SeqCst)
+    }
+
+    // Cache a result unless the cache was cleared since `generation` was read
+    pub fn insert(&self, ip: IpAddr, result: LookupResult, generation: u64) {
+        let mut shard = self.shard(&ip).lock().unwrap();
+        if self.generation() == generation {
+            shard.put(ip, result);
+        }
+    }
+
+    // Drop every cached result; counters are kept
+    pub fn clear(&self) {
+        self.generation.fetch_add(1, Ordering::SeqCst);
+        for shard in &self.shards {
+            shard.lock().unwrap().clear();
+        }
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            capacity: self.capacity,
+            entries: self.shards.iter().map(|s| s.lock().unwrap().len()).sum(),
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{override_record, IpRange, RecordSource};
+
+    fn result(ip: IpAddr) -> LookupResult {
+        LookupResult {
+            ip,
+            record: Some(override_record(IpRange { first: ip, last: ip }, 13335, "US", "CLOUDFLARENET")),
+            source: RecordSource::Upstream,
+            tags: Vec::new(),
+            cloud: None,
+        }
+    }
+
+    #[test]
+    fn test_hits_and_invalidation() {
+        let cache = LookupCache::new(NonZeroUsize::new(100).unwrap());
+        let ip: IpAddr = "1.1.1.1".parse().unwrap();
+
+        assert!(cache.get(&ip).is_none());
+        cache.insert(ip, result(ip), cache.generation());
+        assert_eq!(cache.get(&ip).unwrap().record.unwrap().number, 13335);
+
+        // A result computed before a clear must not be cached after it
+        let generation = cache.generation();
+        cache.clear();
+        cache.insert(ip, result(ip), generation);
+        assert!(cache.get(&ip).is_none());
+
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses, stats.entries), (1, 2, 0));
+        assert_eq!(stats.capacity, 112);
+    }
+
+    #[test]
+    fn test_bounded() {
+        let cache = LookupCache::new(NonZeroUsize::new(16).unwrap());
+        for n in 0..1000u32 {
+            let ip = IpAddr::from(n.to_be_bytes());
+            cache.insert(ip, result(ip), cache.generation());
+        }
+        assert!(cache.stats().entries <= 16);
+    }
+}
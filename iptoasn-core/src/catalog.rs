@@ -0,0 +1,220 @@
+use crate::parser::{AsnRecord, Database};
+use std::collections::{BTreeMap, HashSet};
+use std::sync::Arc;
+
+/**
+ * An autonomous system and what the database announces for it
+ */
+#[derive(Debug, Clone)]
+pub struct AsnEntry {
+    pub number: u32,
+    // Country code of the ASN's first range
+    pub country: Arc<str>,
+    // Description of the ASN's first range
+    pub description: Arc<str>,
+    // Every distinct country code of the ASN's ranges, in address order
+    pub countries: Vec<Arc<str>>,
+    // Every distinct description of the ASN's ranges, in address order
+    pub descriptions: Vec<Arc<str>>,
+    pub ipv4_ranges: usize,
+    pub ipv6_ranges: usize,
+}
+
+/**
+ * Index of the ASNs in a database, built from its string pools
+ *
+ * Searches scan the pool of unique descriptions rather than every record,
+ * so they stay fast on the full iptoasn dataset.
+ */
+#[derive(Debug, Default)]
+pub struct AsnCatalog {
+    // Sorted by number
+    entries: Vec<AsnEntry>,
+    countries: Vec<Arc<str>>,
+    descriptions: Vec<Arc<str>>,
+    // Lowercased descriptions, parallel to `descriptions`
+    folded: Vec<String>,
+    // ASNs using each description, parallel to `descriptions`
+    description_asns: Vec<Vec<u32>>,
+}
+
+impl AsnCatalog {
+    // Build the catalogue for a parsed database
+    pub fn new(database: &Database) -> Self {
+        let mut entries: BTreeMap<u32, AsnEntry> = BTreeMap::new();
+        for record in &database.records {
+            // ASN 0 marks ranges that aren't routed
+            if record.number == 0 {
+                continue;
+            }
+            let entry = entries.entry(record.number).or_insert_with(|| AsnEntry {
+                number: record.number,
+                country: record.country.clone(),
+                description: record.description.clone(),
+                countries: Vec::new(),
+                descriptions: Vec::new(),
+                ipv4_ranges: 0,
+                ipv6_ranges: 0,
+            });
+            if !entry.countries.contains(&record.country) {
+                entry.countries.push(record.country.clone());
+            }
+            if !entry.descriptions.contains(&record.description) {
+                entry.descriptions.push(record.description.clone());
+            }
+            if record.first_ip.is_ipv4() {
+                entry.ipv4_ranges += 1;
+            } else {
+                entry.ipv6_ranges += 1;
+            }
+        }
+
+        let descriptions = database.descriptions.clone();
+        let mut description_asns = vec![Vec::new(); descriptions.len()];
+        for entry in entries.values() {
+            for description in &entry.descriptions {
+                if let Ok(index) = descriptions.binary_search(description) {
+                    description_asns[index].push(entry.number);
+                }
+            }
+        }
+
+        Self {
+            entries: entries.into_values().collect(),
+            countries: database.countries.clone(),
+            folded: descriptions.iter().map(|d| d.to_lowercase()).collect(),
+            descriptions,
+            description_asns,
+        }
+    }
+
+    // All ASNs, sorted by number
+    pub fn entries(&self) -> &[AsnEntry] {
+        &self.entries
+    }
+
+    // Look up a single ASN
+    pub fn get(&self, number: u32) -> Option<&AsnEntry> {
+        self.entries
+            .binary_search_by_key(&number, |entry| entry.number)
+            .ok()
+            .map(|index| &self.entries[index])
+    }
+
+    // Country codes present in the database, sorted
+    pub fn countries(&self) -> &[Arc<str>] {
+        &self.countries
+    }
+
+    // Unique descriptions present in the database, sorted
+    pub fn descriptions(&self) -> &[Arc<str>] {
+        &self.descriptions
+    }
+
+    // ASNs with a range in a country (case-insensitive), sorted by number
+    pub fn by_country<'a>(&'a self, country: &'a str) -> impl Iterator<Item = &'a AsnEntry> + 'a {
+        self.entries
+            .iter()
+            .filter(move |entry| entry.countries.iter().any(|c| c.eq_ignore_ascii_case(country)))
+    }
+
+    /**
+     * Search descriptions, case-insensitively
+     *
+     * Every whitespace-separated word of the query must appear in the
+     * description. Exact matches come first, then descriptions starting
+     * with the query, then those containing it, then the rest.
+     */
+    pub fn search(&self, query: &str) -> Vec<&AsnEntry> {
+        let query = query.trim().to_lowercase();
+        let terms: Vec<&str> = query.split_whitespace().collect();
+        if terms.is_empty() {
+            return Vec::new();
+        }
+
+        let mut hits: Vec<(u8, u32)> = Vec::new();
+        for (index, folded) in self.folded.iter().enumerate() {
+            if !terms.iter().all(|term| folded.contains(term)) {
+                continue;
+            }
+            let rank = if *folded == query {
+                0
+            } else if folded.starts_with(&query) {
+                1
+            } else if folded.contains(&query) {
+                2
+            } else {
+                3
+            };
+            hits.extend(self.description_asns[index].iter().map(|&number| (rank, number)));
+        }
+
+        // An ASN matching through several descriptions is listed at its best rank
+        hits.sort_unstable();
+        let mut seen = HashSet::new();
+        hits.into_iter()
+            .filter(|&(_, number)| seen.insert(number))
+            .filter_map(|(_, number)| self.get(number))
+            .collect()
+    }
+}
+
+// Whether a record belongs to a country (case-insensitive)
+pub(crate) fn in_country(record: &AsnRecord, country: &str) -> bool {
+    record.country.eq_ignore_ascii_case(country)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn catalog() -> AsnCatalog {
+        let data = "3.0.0.0\t3.255.255.255\t16509\tUS\tAMAZON-02\n\
+                    8.8.8.0\t8.8.8.255\t15169\tUS\tGOOGLE\n\
+                    13.32.0.0\t13.32.255.255\t16509\tUS\tAMAZON-02\n\
+                    52.94.0.0\t52.94.255.255\t14618\tUS\tAMAZON-AES\n\
+                    54.0.0.0\t54.0.0.255\t38895\tJP\tAMAZON Japan K.K.\n\
+                    54.1.0.0\t54.1.0.255\t38895\tSG\tAMAZON Singapore\n\
+                    54.2.0.0\t54.2.0.255\t38895\tJP\tAMAZON Japan K.K.\n\
+                    2a05:d000::\t2a05:d03f:ffff::\t16509\tUS\tAMAZON-02\n\
+                    192.0.2.0\t192.0.2.255\t0\tNone\tNot routed\n";
+        AsnCatalog::new(&Database::parse_tsv(data).unwrap())
+    }
+
+    #[test]
+    fn test_entries() {
+        let catalog = catalog();
+        let numbers: Vec<u32> = catalog.entries().iter().map(|e| e.number).collect();
+        assert_eq!(numbers, vec![14618, 15169, 16509, 38895]);
+
+        let amazon = catalog.get(16509).unwrap();
+        assert_eq!(amazon.ipv4_ranges, 2);
+        assert_eq!(amazon.ipv6_ranges, 1);
+        assert_eq!(catalog.countries().len(), 4);
+
+        let jp: Vec<u32> = catalog.by_country("jp").map(|e| e.number).collect();
+        assert_eq!(jp, vec![38895]);
+
+        // Later ranges' countries and descriptions are kept too
+        let japan = catalog.get(38895).unwrap();
+        assert_eq!((&*japan.country, &*japan.description), ("JP", "AMAZON Japan K.K."));
+        assert_eq!(japan.countries.len(), 2);
+        assert_eq!(japan.descriptions.len(), 2);
+        let sg: Vec<u32> = catalog.by_country("SG").map(|e| e.number).collect();
+        assert_eq!(sg, vec![38895]);
+    }
+
+    #[test]
+    fn test_search() {
+        let catalog = catalog();
+        let numbers = |query| -> Vec<u32> {
+            catalog.search(query).iter().map(|e| e.number).collect()
+        };
+
+        assert_eq!(numbers("amazon"), vec![14618, 16509, 38895]);
+        assert_eq!(numbers("AMAZON-02"), vec![16509]);
+        assert_eq!(numbers("japan amazon"), vec![38895]);
+        assert_eq!(numbers("singapore"), vec![38895]);
+        assert!(numbers("  ").is_empty());
+    }
+}
@@ -0,0 +1,117 @@
+/**
+ * Country details from the embedded ISO 3166-1 table
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CountryInfo {
+    pub code: &'static str,
+    pub name: &'static str,
+    pub continent: &'static str,
+    // UN M49 sub-region, e.g. "Western Europe"
+    pub region: &'static str,
+}
+
+// Placeholders iptoasn uses for ranges without a country
+const PLACEHOLDERS: [&str; 3] = ["", "None", "Unknown"];
+
+/**
+ * Normalize a raw country code, mapping placeholders to None
+ */
+pub fn normalize_country_code(code: &str) -> Option<&str> {
+    let code = code.trim();
+    (!PLACEHOLDERS.iter().any(|p| p.eq_ignore_ascii_case(code))).then_some(code)
+}
+
+/**
+ * Look up a two-letter country code (case-insensitive)
+ *
+ * Always None when the crate is built without the `country-names` feature.
+ */
+pub fn country_info(code: &str) -> Option<&'static CountryInfo> {
+    #[cfg(feature = "country-names")]
+    {
+        let code = normalize_country_code(code)?.to_ascii_uppercase();
+        let table = table::countries();
+        table
+            .binary_search_by_key(&code.as_str(), |c| c.code)
+            .ok()
+            .map(|index| &table[index])
+    }
+    #[cfg(not(feature = "country-names"))]
+    {
+        let _ = code;
+        None
+    }
+}
+
+#[cfg(feature = "country-names")]
+mod table {
+    use super::CountryInfo;
+    use std::sync::OnceLock;
+
+    const DATA: &str = include_str!("../data/countries.tsv");
+
+    // Parse the embedded table on first use, sorted by code
+    pub(super) fn countries() -> &'static [CountryInfo] {
+        static TABLE: OnceLock<Vec<CountryInfo>> = OnceLock::new();
+        TABLE.get_or_init(|| {
+            let mut table: Vec<CountryInfo> = DATA
+                .lines()
+                .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                .filter_map(|line| {
+                    let mut fields = line.split('\t');
+                    let code = fields.next()?;
+                    let name = fields.next()?;
+                    let continent = continent_name(fields.next()?);
+                    let region = fields.next()?;
+                    Some(CountryInfo {
+                        code,
+                        name,
+                        continent,
+                        region,
+                    })
+                })
+                .collect();
+            table.sort_unstable_by_key(|c| c.code);
+            table
+        })
+    }
+
+    fn continent_name(code: &str) -> &'static str {
+        match code {
+            "AF" => "Africa",
+            "AN" => "Antarctica",
+            "AS" => "Asia",
+            "EU" => "Europe",
+            "NA" => "North America",
+            "OC" => "Oceania",
+            "SA" => "South America",
+            _ => "",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_placeholders() {
+        assert_eq!(normalize_country_code("None"), None);
+        assert_eq!(normalize_country_code("unknown"), None);
+        assert_eq!(normalize_country_code(" "), None);
+        assert_eq!(normalize_country_code("DE"), Some("DE"));
+    }
+
+    #[cfg(feature = "country-names")]
+    #[test]
+    fn test_country_info() {
+        let us = country_info("us").unwrap();
+        assert_eq!(us.name, "United States");
+        assert_eq!(us.continent, "North America");
+        assert_eq!(us.region, "Northern America");
+
+        assert_eq!(country_info("ZW").unwrap().continent, "Africa");
+        assert!(country_info("None").is_none());
+        assert!(country_info("XX").is_none());
+    }
+}
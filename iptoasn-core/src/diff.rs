@@ -0,0 +1,141 @@
+use crate::parser::AsnRecord;
+use std::cmp::Ordering;
+use std::collections::BTreeSet;
+
+/**
+ * A range present in both databases whose attributes changed
+ */
+#[derive(Debug, Clone)]
+pub struct RecordChange {
+    pub old: AsnRecord,
+    pub new: AsnRecord,
+}
+
+impl RecordChange {
+    pub fn asn_changed(&self) -> bool {
+        self.old.number != self.new.number
+    }
+
+    pub fn country_changed(&self) -> bool {
+        self.old.country != self.new.country
+    }
+
+    pub fn description_changed(&self) -> bool {
+        self.old.description != self.new.description
+    }
+}
+
+/**
+ * Record-level differences between two database versions
+ *
+ * Ranges are matched on their exact first and last address. A range that was
+ * split or merged shows up as removed in its old shape and added in its new one.
+ */
+#[derive(Debug, Clone, Default)]
+pub struct DatabaseDiff {
+    // Ranges only in the new database
+    pub added: Vec<AsnRecord>,
+    // Ranges only in the old database
+    pub removed: Vec<AsnRecord>,
+    // Ranges in both with a different ASN, country or description
+    pub changed: Vec<RecordChange>,
+    // ASNs announcing ranges only in the new database
+    pub asns_added: Vec<u32>,
+    // ASNs announcing ranges only in the old database
+    pub asns_removed: Vec<u32>,
+}
+
+impl DatabaseDiff {
+    // Compare two record sets sorted by (first_ip, last_ip), as produced by `Database::parse`
+    pub fn compute(old: &[AsnRecord], new: &[AsnRecord]) -> Self {
+        let mut diff = Self::default();
+        let (mut i, mut j) = (0, 0);
+
+        while i < old.len() && j < new.len() {
+            let (a, b) = (&old[i], &new[j]);
+
+            match (a.first_ip, a.last_ip).cmp(&(b.first_ip, b.last_ip)) {
+                Ordering::Less => {
+                    diff.removed.push(a.clone());
+                    i += 1;
+                }
+                Ordering::Greater => {
+                    diff.added.push(b.clone());
+                    j += 1;
+                }
+                Ordering::Equal => {
+                    if a.number != b.number
+                        || a.country != b.country
+                        || a.description != b.description
+                    {
+                        diff.changed.push(RecordChange {
+                            old: a.clone(),
+                            new: b.clone(),
+                        });
+                    }
+                    i += 1;
+                    j += 1;
+                }
+            }
+        }
+
+        diff.removed.extend_from_slice(&old[i..]);
+        diff.added.extend_from_slice(&new[j..]);
+
+        let old_asns: BTreeSet<u32> = old.iter().map(|r| r.number).collect();
+        let new_asns: BTreeSet<u32> = new.iter().map(|r| r.number).collect();
+        diff.asns_added = new_asns.difference(&old_asns).copied().collect();
+        diff.asns_removed = old_asns.difference(&new_asns).copied().collect();
+
+        diff
+    }
+
+    // Check if the two databases are identical
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    fn record(first: &str, last: &str, number: u32, description: &str) -> AsnRecord {
+        AsnRecord {
+            first_ip: first.parse().unwrap(),
+            last_ip: last.parse().unwrap(),
+            number,
+            country: Arc::from("US"),
+            description: Arc::from(description),
+        }
+    }
+
+    #[test]
+    fn test_diff_added_removed_changed() {
+        let old = vec![
+            record("1.0.0.0", "1.0.0.255", 13335, "CLOUDFLARENET"),
+            record("8.8.8.0", "8.8.8.255", 15169, "GOOGLE"),
+            record("9.9.9.0", "9.9.9.255", 19281, "QUAD9"),
+        ];
+        let new = vec![
+            record("1.0.0.0", "1.0.0.255", 13335, "CLOUDFLARENET"),
+            record("8.8.8.0", "8.8.8.255", 396982, "GOOGLE-CLOUD"),
+            record("9.9.9.0", "9.9.9.127", 19281, "QUAD9"),
+            record("9.9.9.128", "9.9.9.255", 19281, "QUAD9"),
+        ];
+
+        let diff = DatabaseDiff::compute(&old, &new);
+
+        assert_eq!(diff.removed.len(), 1);
+        assert_eq!(diff.added.len(), 2);
+        assert_eq!(diff.changed.len(), 1);
+        assert!(diff.changed[0].asn_changed());
+        assert!(diff.changed[0].description_changed());
+        assert!(!diff.changed[0].country_changed());
+        assert_eq!(diff.asns_added, vec![396982]);
+        assert_eq!(diff.asns_removed, vec![15169]);
+
+        assert!(DatabaseDiff::compute(&old, &old).is_empty());
+    }
+}
@@ -0,0 +1,309 @@
+use crate::error::{AppError, Result};
+use crate::store::AsnStore;
+use regex::Regex;
+use std::io::{BufRead, Write};
+use std::net::IpAddr;
+
+// Lines read per batch; bounds memory regardless of input size
+const BATCH_SIZE: usize = 16 * 1024;
+
+// Below this many lines a batch is enriched on the calling thread
+const PARALLEL_THRESHOLD: usize = 1024;
+
+/**
+ * Where to find the IP address in each line
+ */
+#[derive(Debug, Clone)]
+pub enum IpField {
+    // Zero-based column, split on the delimiter (double quotes are honoured)
+    Column(usize),
+    // First capture group of a pattern, or the whole match without groups
+    Pattern(Regex),
+}
+
+impl IpField {
+    // Compile a pattern field
+    pub fn pattern(pattern: &str) -> Result<Self> {
+        Regex::new(pattern)
+            .map(IpField::Pattern)
+            .map_err(|e| AppError::InvalidPattern(e.to_string()))
+    }
+}
+
+/**
+ * Options for enriching delimited records
+ */
+#[derive(Debug, Clone)]
+pub struct EnrichOptions {
+    pub field: IpField,
+    // Column delimiter of the input; appended columns use it too
+    pub delimiter: char,
+    // Whether the first line is a header
+    pub header: bool,
+}
+
+/**
+ * Counters for an enrichment run
+ */
+#[derive(Debug, Clone, Copy, Default)]
+pub struct EnrichSummary {
+    pub lines: u64,
+    // Lines whose IP was found in an announced range
+    pub matched: u64,
+    // Lines without a parseable IP
+    pub invalid: u64,
+}
+
+/**
+ * Appends ASN number, country and description columns to delimited records
+ *
+ * Works on a snapshot of the store, so a long-running stream sees one
+ * consistent database even if the updater swaps in a new one meanwhile.
+ */
+pub struct Enricher {
+    store: AsnStore,
+    options: EnrichOptions,
+}
+
+// Outcome of enriching a single line
+enum LineResult {
+    Matched,
+    Unannounced,
+    Invalid,
+}
+
+impl Enricher {
+    pub fn new(store: AsnStore, options: EnrichOptions) -> Self {
+        Self { store, options }
+    }
+
+    // Append the column names to a header line
+    pub fn enrich_header(&self, line: &str) -> String {
+        let d = self.options.delimiter;
+        format!("{line}{d}as_number{d}as_country_code{d}as_description")
+    }
+
+    // Enrich a single line
+    pub fn enrich_line(&self, line: &str) -> String {
+        let mut out = String::with_capacity(line.len() + 48);
+        self.enrich_into(line, &mut out);
+        out
+    }
+
+    fn enrich_into(&self, line: &str, out: &mut String) -> LineResult {
+        let d = self.options.delimiter;
+        out.push_str(line);
+
+        let ip = self.extract(line).and_then(|s| s.trim().parse::<IpAddr>().ok());
+        let Some(ip) = ip else {
+            out.extend([d, d, d]);
+            return LineResult::Invalid;
+        };
+
+        match self.store.lookup(ip) {
+            Some(record) => {
+                out.push(d);
+                out.push_str(&record.number.to_string());
+                out.push(d);
+                self.push_field(out, &record.country);
+                out.push(d);
+                self.push_field(out, &record.description);
+                LineResult::Matched
+            }
+            None => {
+                out.extend([d, d, d]);
+                LineResult::Unannounced
+            }
+        }
+    }
+
+    // Append a field, quoting it if it contains the delimiter or quotes
+    fn push_field(&self, out: &mut String, value: &str) {
+        if value.contains(self.options.delimiter) || value.contains('"') {
+            out.push('"');
+            out.push_str(&value.replace('"', "\"\""));
+            out.push('"');
+        } else {
+            out.push_str(value);
+        }
+    }
+
+    // Find the IP address text in a line
+    fn extract<'a>(&self, line: &'a str) -> Option<&'a str> {
+        match &self.options.field {
+            IpField::Column(index) => split_column(line, self.options.delimiter, *index),
+            IpField::Pattern(regex) => {
+                let captures = regex.captures(line)?;
+                captures.get(1).or_else(|| captures.get(0)).map(|m| m.as_str())
+            }
+        }
+    }
+
+    // Enrich a batch of lines, in parallel for large batches
+    pub fn enrich_lines(&self, lines: &[String]) -> Vec<String> {
+        self.enrich_batch(lines).0
+    }
+
+    fn enrich_batch(&self, lines: &[String]) -> (Vec<String>, EnrichSummary) {
+        let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+
+        if lines.len() < PARALLEL_THRESHOLD || threads == 1 {
+            return self.enrich_sequential(lines);
+        }
+
+        let chunk_size = lines.len().div_ceil(threads);
+        let results: Vec<_> = std::thread::scope(|scope| {
+            let handles: Vec<_> = lines
+                .chunks(chunk_size)
+                .map(|chunk| scope.spawn(|| self.enrich_sequential(chunk)))
+                .collect();
+            handles
+                .into_iter()
+                .map(|handle| handle.join().expect("enrichment thread panicked"))
+                .collect()
+        });
+
+        let mut out = Vec::with_capacity(lines.len());
+        let mut summary = EnrichSummary::default();
+        for (chunk, chunk_summary) in results {
+            out.extend(chunk);
+            summary.lines += chunk_summary.lines;
+            summary.matched += chunk_summary.matched;
+            summary.invalid += chunk_summary.invalid;
+        }
+        (out, summary)
+    }
+
+    fn enrich_sequential(&self, lines: &[String]) -> (Vec<String>, EnrichSummary) {
+        let mut summary = EnrichSummary::default();
+        let out = lines
+            .iter()
+            .map(|line| {
+                let mut enriched = String::with_capacity(line.len() + 48);
+                match self.enrich_into(line, &mut enriched) {
+                    LineResult::Matched => summary.matched += 1,
+                    LineResult::Unannounced => {}
+                    LineResult::Invalid => summary.invalid += 1,
+                }
+                summary.lines += 1;
+                enriched
+            })
+            .collect();
+        (out, summary)
+    }
+
+    // Enrich every line of a reader into a writer, in bounded batches
+    pub fn enrich_stream<R: BufRead, W: Write>(&self, reader: R, mut writer: W) -> Result<EnrichSummary> {
+        let mut lines = reader.lines();
+        let mut summary = EnrichSummary::default();
+
+        if self.options.header {
+            if let Some(header) = lines.next() {
+                writeln!(writer, "{}", self.enrich_header(&header?))?;
+            }
+        }
+
+        let mut batch = Vec::with_capacity(BATCH_SIZE);
+        loop {
+            batch.clear();
+            for line in lines.by_ref().take(BATCH_SIZE) {
+                batch.push(line?);
+            }
+            if batch.is_empty() {
+                break;
+            }
+
+            let (enriched, batch_summary) = self.enrich_batch(&batch);
+            for line in &enriched {
+                writeln!(writer, "{}", line)?;
+            }
+            summary.lines += batch_summary.lines;
+            summary.matched += batch_summary.matched;
+            summary.invalid += batch_summary.invalid;
+        }
+
+        writer.flush()?;
+        Ok(summary)
+    }
+}
+
+// Return the column at `index`, treating double-quoted sections as one column
+fn split_column(line: &str, delimiter: char, index: usize) -> Option<&str> {
+    let mut column = 0;
+    let mut start = 0;
+    let mut in_quotes = false;
+
+    for (i, c) in line.char_indices() {
+        if c == '"' {
+            in_quotes = !in_quotes;
+        } else if c == delimiter && !in_quotes {
+            if column == index {
+                return Some(line[start..i].trim_matches('"'));
+            }
+            column += 1;
+            start = i + c.len_utf8();
+        }
+    }
+
+    (column == index).then(|| line[start..].trim_matches('"'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Database;
+
+    fn store() -> AsnStore {
+        AsnStore::new(
+            Database::parse_tsv("3.0.0.0\t3.255.255.255\t16509\tUS\tAMAZON-02, Amazon.com\n")
+                .unwrap(),
+        )
+    }
+
+    #[test]
+    fn test_enrich_csv_column() {
+        let enricher = Enricher::new(
+            store(),
+            EnrichOptions {
+                field: IpField::Column(1),
+                delimiter: ',',
+                header: true,
+            },
+        );
+
+        let input = "time,\"client\",path\n1,\"3.3.3.3\",/\n2,10.0.0.1,/\n3,junk,/\n";
+        let mut output = Vec::new();
+        let summary = enricher.enrich_stream(input.as_bytes(), &mut output).unwrap();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "time,\"client\",path,as_number,as_country_code,as_description\n\
+             1,\"3.3.3.3\",/,16509,US,\"AMAZON-02, Amazon.com\"\n\
+             2,10.0.0.1,/,,,\n\
+             3,junk,/,,,\n"
+        );
+        assert_eq!(summary.lines, 3);
+        assert_eq!(summary.matched, 1);
+        assert_eq!(summary.invalid, 1);
+    }
+
+    #[test]
+    fn test_enrich_pattern_in_parallel() {
+        let enricher = Enricher::new(
+            store(),
+            EnrichOptions {
+                field: IpField::pattern(r"client=(\S+)").unwrap(),
+                delimiter: '\t',
+                header: false,
+            },
+        );
+
+        let lines: Vec<String> = (0..PARALLEL_THRESHOLD * 3)
+            .map(|i| format!("req={} client=3.0.{}.1", i, i % 256))
+            .collect();
+        let enriched = enricher.enrich_lines(&lines);
+
+        assert_eq!(enriched.len(), lines.len());
+        assert_eq!(enriched[42], format!("{}\t16509\tUS\tAMAZON-02, Amazon.com", lines[42]));
+    }
+}
@@ -8,20 +8,72 @@ pub enum AppError {
     #[error("HTTP request failed: {0}")]
     HttpRequest(String),
 
+    #[error("HTTP request timed out: {0}")]
+    HttpTimeout(String),
+
+    #[error("HTTP error: {status} - {reason}")]
+    HttpStatus { status: u16, reason: String },
+
     #[error("Failed to parse response: {0}")]
     HttpParse(String),
 
     #[error("Database parse error: {0}")]
     DatabaseParse(String),
 
+    #[error("Failed to decompress: {0}")]
+    Decompress(String),
+
+    #[error("Cached database is corrupt: {path}: {reason}")]
+    CacheCorrupt { path: String, reason: String },
+
     #[error("IO error: {0}")]
     Io(#[from] std::io::Error),
 
     #[error("Invalid IP address: {0}")]
     InvalidIp(String),
 
+    #[error("Invalid IP range: {0}")]
+    InvalidRange(String),
+
     #[error("Database not loaded")]
     DatabaseNotLoaded,
+
+    #[error("Timed out waiting for cache lock: {0}")]
+    CacheLockTimeout(String),
+
+    #[error("Database version not found: {0}")]
+    VersionNotFound(String),
+
+    #[error("Invalid pattern: {0}")]
+    InvalidPattern(String),
+
+    #[error("Database is stale: data is {age_secs}s old (max {max_age_secs}s)")]
+    StaleDatabase { age_secs: u64, max_age_secs: u64 },
+}
+
+impl AppError {
+    /**
+     * Stable identifier of the error kind, for callers that can't match on the enum
+     */
+    pub fn code(&self) -> &'static str {
+        match self {
+            AppError::HttpRequest(_) => "ERR_HTTP",
+            AppError::HttpTimeout(_) => "ERR_HTTP_TIMEOUT",
+            AppError::HttpStatus { .. } => "ERR_HTTP_STATUS",
+            AppError::HttpParse(_) => "ERR_HTTP_RESPONSE",
+            AppError::DatabaseParse(_) => "ERR_PARSE",
+            AppError::Decompress(_) => "ERR_DECOMPRESS",
+            AppError::CacheCorrupt { .. } => "ERR_CACHE_CORRUPT",
+            AppError::Io(_) => "ERR_IO",
+            AppError::InvalidIp(_) => "ERR_INVALID_IP",
+            AppError::InvalidRange(_) => "ERR_INVALID_RANGE",
+            AppError::DatabaseNotLoaded => "ERR_NOT_LOADED",
+            AppError::CacheLockTimeout(_) => "ERR_CACHE_LOCKED",
+            AppError::VersionNotFound(_) => "ERR_VERSION_NOT_FOUND",
+            AppError::InvalidPattern(_) => "ERR_INVALID_PATTERN",
+            AppError::StaleDatabase { .. } => "ERR_STALE",
+        }
+    }
 }
 
 /**
@@ -1,15 +1,21 @@
 use crate::error::{AppError, Result};
+use crate::freshness::{self, DataVersion};
+use crate::lock::{self, CacheLock};
+use crate::versions::{VersionArchive, VersionInfo};
 use serde::{Deserialize, Serialize};
 use std::path::{Path, PathBuf};
-use tracing::{debug, info, warn};
+use std::time::{Duration, Instant, SystemTime};
+use tracing::{debug, field, info, info_span, warn, Instrument, Span};
 
 /**
  * Metadata for caching the database file, including ETag and Last-Modified headers.
  */
 #[derive(Debug, Serialize, Deserialize, Default)]
-struct CacheMetadata {
-    etag: Option<String>,
-    last_modified: Option<String>,
+pub(crate) struct CacheMetadata {
+    pub(crate) etag: Option<String>,
+    pub(crate) last_modified: Option<String>,
+    // Unix timestamp of the download that produced the cached file
+    pub(crate) downloaded_at: Option<u64>,
 }
 /**
  * Handles fetching and caching the database file
@@ -18,11 +24,19 @@ pub struct DatabaseFetcher {
     url: String,
     cache_path: PathBuf,
     metadata_path: PathBuf, // NEW: path to metadata file
+    lock_path: PathBuf,
+    lock_timeout: Duration,
+    request_timeout: Duration,
+    archive: VersionArchive,
     client: reqwest::Client,
 
     // Conditional request state
     etag: Option<String>,
     last_modified: Option<String>,
+    downloaded_at: Option<SystemTime>,
+
+    // Version of the data most recently returned by fetch or load_from_cache
+    version: DataVersion,
 }
 
 impl DatabaseFetcher {
@@ -33,58 +47,89 @@ impl DatabaseFetcher {
 
         let cache_path = PathBuf::from(cache_dir).join("ip2asn-combined.tsv.gz");
         let metadata_path = PathBuf::from(cache_dir).join("metadata.json");
+        let lock_path = PathBuf::from(cache_dir).join("cache.lock");
 
         let client = reqwest::Client::builder()
             .gzip(true)
-            .timeout(std::time::Duration::from_secs(60))
             .build()
             .map_err(|e| AppError::HttpRequest(format!("Failed to create HTTP client: {}", e)))?;
 
         // Load existing metadata if available
-        let (etag, last_modified) = Self::load_metadata(&metadata_path);
+        let metadata = Self::load_metadata(&metadata_path);
 
         Ok(Self {
             url,
             cache_path,
             metadata_path,
+            lock_path,
+            // Longer than the HTTP timeout so a healthy download always finishes first
+            lock_timeout: Duration::from_secs(120),
+            request_timeout: Duration::from_secs(60),
+            archive: VersionArchive::new(Path::new(cache_dir)),
             client,
-            etag,
-            last_modified,
+            etag: metadata.etag,
+            last_modified: metadata.last_modified,
+            downloaded_at: metadata.downloaded_at.map(freshness::from_unix_secs),
+            version: DataVersion::default(),
         })
     }
 
     // Load metadata from disk
-    fn load_metadata(path: &Path) -> (Option<String>, Option<String>) {
+    fn load_metadata(path: &Path) -> CacheMetadata {
         if let Ok(content) = std::fs::read_to_string(path) {
             if let Ok(metadata) = serde_json::from_str::<CacheMetadata>(&content) {
                 debug!(
                     "Loaded cached metadata: etag={:?}, last_modified={:?}",
                     metadata.etag, metadata.last_modified
                 );
-                return (metadata.etag, metadata.last_modified);
+                return metadata;
             }
         }
         debug!("No cached metadata found");
-        (None, None)
+        CacheMetadata::default()
     }
 
-    // Save metadata to disk
-    fn save_metadata(&self) -> Result<()> {
-        let metadata = CacheMetadata {
+    // Metadata describing the current cache file
+    fn metadata(&self) -> CacheMetadata {
+        CacheMetadata {
             etag: self.etag.clone(),
             last_modified: self.last_modified.clone(),
-        };
+            downloaded_at: self.downloaded_at.and_then(freshness::to_unix_secs),
+        }
+    }
+
+    // Save metadata to disk
+    fn save_metadata(&self) -> Result<()> {
+        let metadata = self.metadata();
 
         let json = serde_json::to_string_pretty(&metadata)
             .map_err(|e| AppError::HttpParse(format!("Failed to serialize metadata: {}", e)))?;
 
-        std::fs::write(&self.metadata_path, json)?;
+        lock::write_atomic(&self.metadata_path, json.as_bytes())?;
         debug!("Saved metadata to: {}", self.metadata_path.display());
         Ok(())
     }
 
     // Fetch the database, returns None if unchanged (304 Not Modified)
     pub async fn fetch(&mut self) -> Result<Option<Vec<u8>>> {
+        let span = info_span!(
+            "fetch",
+            url = %self.url,
+            status = field::Empty,
+            bytes = field::Empty,
+            duration_ms = field::Empty,
+        );
+        let started = Instant::now();
+        let result = self.fetch_source().instrument(span.clone()).await;
+
+        span.record("duration_ms", started.elapsed().as_millis() as u64);
+        if let Ok(Some(data)) = &result {
+            span.record("bytes", data.len());
+        }
+        result
+    }
+
+    async fn fetch_source(&mut self) -> Result<Option<Vec<u8>>> {
         if self.url.starts_with("file://") {
             // Local file - just read it
             return self.fetch_local_file();
@@ -101,23 +146,46 @@ impl DatabaseFetcher {
     }
 
     // Fetch from local file
-    fn fetch_local_file(&self) -> Result<Option<Vec<u8>>> {
+    fn fetch_local_file(&mut self) -> Result<Option<Vec<u8>>> {
         let path = self.url.strip_prefix("file://").unwrap();
         info!("Reading database from local file: {}", path);
 
         let data = std::fs::read(path)?;
+        self.version = DataVersion {
+            upstream_modified: std::fs::metadata(path).and_then(|m| m.modified()).ok(),
+            downloaded_at: Some(SystemTime::now()),
+        };
         Ok(Some(data))
     }
 
+    // Set how long to wait for another process sharing the cache directory
+    pub fn set_lock_timeout(&mut self, timeout: Duration) {
+        self.lock_timeout = timeout;
+    }
+
+    // Set how long a download may take, from connecting to the last byte
+    pub fn set_request_timeout(&mut self, timeout: Duration) {
+        self.request_timeout = timeout;
+    }
+
     // Fetch from remote URL with conditional request support
     async fn fetch_remote(&mut self) -> Result<Option<Vec<u8>>> {
+        // Only one process sharing the cache directory downloads at a time
+        let _lock = CacheLock::acquire(&self.lock_path, self.lock_timeout).await?;
+
+        // Another process may have refreshed the cache while we waited
+        if let Some(data) = self.adopt_newer_cache()? {
+            return Ok(Some(data));
+        }
+
         info!("Fetching database from: {}", self.url);
 
         // Build request with conditional headers
-        let mut request = self.client.get(&self.url).header(
-            "User-Agent",
-            concat!("iptoasn-server/", env!("CARGO_PKG_VERSION")),
-        );
+        let mut request = self
+            .client
+            .get(&self.url)
+            .timeout(self.request_timeout)
+            .header("User-Agent", concat!("iptoasn-core/", env!("CARGO_PKG_VERSION")));
 
         // Add If-None-Match (ETag) if we have it
         if let Some(etag) = &self.etag {
@@ -133,10 +201,11 @@ impl DatabaseFetcher {
 
         let response = request.send().await.map_err(|e| {
             warn!("Network request failed: {}", e);
-            AppError::HttpRequest(format!("Request failed: {}", e))
+            request_error(&self.url, e)
         })?;
 
         let status = response.status();
+        Span::current().record("status", status.as_u16());
 
         // 304 Not Modified
         if status == reqwest::StatusCode::NOT_MODIFIED {
@@ -145,11 +214,10 @@ impl DatabaseFetcher {
         }
 
         if !status.is_success() {
-            return Err(AppError::HttpRequest(format!(
-                "HTTP error: {} - {}",
-                status.as_u16(),
-                status.canonical_reason().unwrap_or("Unknown")
-            )));
+            return Err(AppError::HttpStatus {
+                status: status.as_u16(),
+                reason: status.canonical_reason().unwrap_or("Unknown").to_string(),
+            });
         }
 
         // Extract and store ETag for next request
@@ -171,37 +239,171 @@ impl DatabaseFetcher {
         let bytes = response
             .bytes()
             .await
-            .map_err(|e| AppError::HttpParse(format!("Failed to read response body: {}", e)))?;
+            .map_err(|e| match request_error(&self.url, e) {
+                AppError::HttpRequest(message) => {
+                    AppError::HttpParse(format!("Failed to read response body: {}", message))
+                }
+                timeout => timeout,
+            })?;
 
         let data = bytes.to_vec();
         info!("Database downloaded ({} bytes)", data.len());
 
+        // Keep the version being replaced, e.g. one cached before archiving was enabled
+        self.archive_cache(&Self::load_metadata(&self.metadata_path));
+
+        self.downloaded_at = Some(next_download_time(self.downloaded_at, SystemTime::now()));
+        self.version = self.cached_version();
+
         self.save_to_cache(&data)?;
 
         // Save metadata (ETag/Last-Modified) for next run
         self.save_metadata()?;
 
+        self.archive_cache(&self.metadata());
+
+        Ok(Some(data))
+    }
+
+    // Copy the cache file into the version archive; failures only cost history
+    fn archive_cache(&self, metadata: &CacheMetadata) {
+        if !self.archive.enabled() {
+            return;
+        }
+
+        let result = self
+            .archive
+            .archive(&self.cache_path, metadata)
+            .and_then(|_| self.archive.prune());
+        if let Err(e) = result {
+            warn!("Failed to archive database version: {}", e);
+        }
+    }
+
+    // Reuse a cache written by another process since our last fetch
+    fn adopt_newer_cache(&mut self) -> Result<Option<Vec<u8>>> {
+        let on_disk = Self::load_metadata(&self.metadata_path);
+        let ours = self.downloaded_at.and_then(freshness::to_unix_secs);
+
+        if on_disk.downloaded_at <= ours || !self.cache_path.exists() {
+            return Ok(None);
+        }
+
+        info!("Cache was refreshed by another process, reusing it");
+        self.etag = on_disk.etag;
+        self.last_modified = on_disk.last_modified;
+        self.downloaded_at = on_disk.downloaded_at.map(freshness::from_unix_secs);
+
+        let data = std::fs::read(&self.cache_path)?;
+        self.version = self.cached_version();
         Ok(Some(data))
     }
 
     fn save_to_cache(&self, data: &[u8]) -> Result<()> {
-        std::fs::write(&self.cache_path, data)?;
+        lock::write_atomic(&self.cache_path, data)?;
         info!("Database cached to: {}", self.cache_path.display());
         Ok(())
     }
 
     // Try to load from cache (fallback for network failures)
-    pub fn load_from_cache(&self) -> Result<Vec<u8>> {
+    pub fn load_from_cache(&mut self) -> Result<Vec<u8>> {
         if self.cache_path.exists() {
             info!("Loading database from cache: {}", self.cache_path.display());
             let data = std::fs::read(&self.cache_path)?;
+            self.version = self.cached_version();
             Ok(data)
         } else {
             Err(AppError::DatabaseNotLoaded)
         }
     }
 
+    // Version of the cached file, from saved metadata or the file's mtime
+    fn cached_version(&self) -> DataVersion {
+        let downloaded_at = self.downloaded_at.or_else(|| {
+            std::fs::metadata(&self.cache_path)
+                .and_then(|m| m.modified())
+                .ok()
+        });
+
+        DataVersion {
+            upstream_modified: self
+                .last_modified
+                .as_deref()
+                .and_then(freshness::parse_http_date),
+            downloaded_at,
+        }
+    }
+
+    // Set how many previous versions to keep in the cache directory (0 disables the archive)
+    pub fn set_keep_versions(&mut self, keep: usize) {
+        self.archive.set_keep(keep);
+    }
+
+    // List the archived versions, newest first
+    pub fn list_versions(&self) -> Result<Vec<VersionInfo>> {
+        let current = VersionArchive::id_for(&self.metadata());
+        self.archive.list(current.as_deref())
+    }
+
+    // Read an archived version without making it current
+    pub fn read_version(&self, id: &str) -> Result<Vec<u8>> {
+        self.archive.read(id).map(|(data, _)| data)
+    }
+
+    // Make an archived version the current cache file
+    pub async fn restore_version(&mut self, id: &str) -> Result<()> {
+        let _lock = CacheLock::acquire(&self.lock_path, self.lock_timeout).await?;
+
+        let (data, metadata) = self.archive.read(id)?;
+        if VersionArchive::id_for(&self.metadata()).as_deref() != Some(id) {
+            self.archive_cache(&self.metadata());
+            self.save_to_cache(&data)?;
+
+            self.etag = metadata.etag;
+            self.last_modified = metadata.last_modified;
+            self.downloaded_at = metadata.downloaded_at.map(freshness::from_unix_secs);
+            self.save_metadata()?;
+            info!("Restored database version {}", id);
+        }
+
+        self.version = self.cached_version();
+        Ok(())
+    }
+
+    // Pinned version, if any; a pinned cache directory is never updated
+    pub fn pinned_version(&self) -> Option<String> {
+        self.archive.pinned()
+    }
+
+    // Pin or unpin a version for every process sharing the cache directory
+    pub fn pin_version(&self, id: Option<&str>) -> Result<()> {
+        self.archive.set_pinned(id)
+    }
+
+    // Version of the data most recently returned by `fetch` or `load_from_cache`
+    pub fn version(&self) -> DataVersion {
+        self.version
+    }
+
     pub fn cache_path(&self) -> &Path {
         &self.cache_path
     }
 }
+
+// Time to record for a new download; version ids are its whole seconds, so
+// a second download within the same second moves on to the next one
+fn next_download_time(previous: Option<SystemTime>, now: SystemTime) -> SystemTime {
+    match previous.and_then(freshness::to_unix_secs) {
+        Some(secs) if freshness::to_unix_secs(now) <= Some(secs) => freshness::from_unix_secs(secs + 1),
+        _ => now,
+    }
+}
+
+// Classify a failed request, keeping timeouts apart from other failures
+fn request_error(url: &str, e: reqwest::Error) -> AppError {
+    if e.is_timeout() {
+        AppError::HttpTimeout(url.to_string())
+    } else {
+        AppError::HttpRequest(format!("Request failed: {}", e))
+    }
+}
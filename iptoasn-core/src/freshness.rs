@@ -0,0 +1,106 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use time::format_description::well_known::Rfc2822;
+use time::OffsetDateTime;
+
+/**
+ * Describes when the currently loaded data was produced and fetched
+ */
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DataVersion {
+    // Upstream Last-Modified (or file modification time for local sources)
+    pub upstream_modified: Option<SystemTime>,
+    // When this copy was downloaded from the source
+    pub downloaded_at: Option<SystemTime>,
+}
+
+impl DataVersion {
+    // Age of the data, measured from the upstream timestamp when known
+    pub fn age(&self) -> Option<Duration> {
+        let produced = self.upstream_modified.or(self.downloaded_at)?;
+        Some(
+            SystemTime::now()
+                .duration_since(produced)
+                .unwrap_or(Duration::ZERO),
+        )
+    }
+}
+
+/**
+ * What to do when the loaded data exceeds the configured maximum age
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StaleAction {
+    // Log a warning after each load or update check
+    Warn,
+    // Emit a `DbEvent::Stale` after each load or update check
+    Event,
+    // Fail lookups with `AppError::StaleDatabase`
+    Fail,
+}
+
+/**
+ * Maximum data age policy
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MaxAgePolicy {
+    pub max_age: Duration,
+    pub action: StaleAction,
+}
+
+impl MaxAgePolicy {
+    pub fn new(max_age: Duration, action: StaleAction) -> Self {
+        Self { max_age, action }
+    }
+
+    // Returns the data age if it exceeds the policy limit
+    pub fn exceeded(&self, version: &DataVersion) -> Option<Duration> {
+        version.age().filter(|age| *age > self.max_age)
+    }
+}
+
+// Parse an HTTP-date (RFC 7231 IMF-fixdate) such as a Last-Modified header
+pub(crate) fn parse_http_date(value: &str) -> Option<SystemTime> {
+    let parsed = OffsetDateTime::parse(value.trim(), &Rfc2822).ok()?;
+    Some(SystemTime::from(parsed))
+}
+
+// Convert a timestamp to whole seconds since the Unix epoch
+pub(crate) fn to_unix_secs(time: SystemTime) -> Option<u64> {
+    time.duration_since(UNIX_EPOCH).ok().map(|d| d.as_secs())
+}
+
+// Convert whole seconds since the Unix epoch to a timestamp
+pub(crate) fn from_unix_secs(secs: u64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_secs(secs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_http_date() {
+        let parsed = parse_http_date("Wed, 21 Oct 2015 07:28:00 GMT").unwrap();
+        assert_eq!(to_unix_secs(parsed), Some(1_445_412_480));
+        assert!(parse_http_date("not a date").is_none());
+    }
+
+    #[test]
+    fn test_policy_exceeded() {
+        let policy = MaxAgePolicy::new(Duration::from_secs(3600), StaleAction::Fail);
+
+        let fresh = DataVersion {
+            upstream_modified: Some(SystemTime::now()),
+            downloaded_at: None,
+        };
+        assert!(policy.exceeded(&fresh).is_none());
+
+        let old = DataVersion {
+            upstream_modified: Some(SystemTime::now() - Duration::from_secs(7200)),
+            downloaded_at: Some(SystemTime::now()),
+        };
+        assert!(policy.exceeded(&old).is_some());
+
+        assert!(policy.exceeded(&DataVersion::default()).is_none());
+    }
+}
@@ -1,20 +1,54 @@
+pub mod aggregate;
+pub mod cache;
+pub mod catalog;
+pub mod country;
+pub mod diff;
+pub mod enrich;
 pub mod error;
 pub mod fetcher;
+pub mod freshness;
+pub mod lock;
+pub mod metrics;
+pub mod overrides;
 pub mod parser;
+pub mod providers;
+pub mod range;
 pub mod store;
+pub mod synthetic;
+pub mod tags;
+#[cfg(feature = "otlp")]
+pub mod telemetry;
 pub mod updater;
+pub mod versions;
 
 use std::net::IpAddr;
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
-use std::time::SystemTime;
-use tokio::sync::RwLock;
-use tracing::info;
+use std::time::{Duration, Instant, SystemTime};
+use tokio::sync::{broadcast, RwLock};
+use tracing::{field, info, info_span, warn, Instrument};
 
+pub use aggregate::{Aggregates, AsnAggregate, CountryAggregate, SpaceTotals};
+pub use cache::{CacheStats, LookupCache};
+pub use catalog::{AsnCatalog, AsnEntry};
+pub use country::{country_info, normalize_country_code, CountryInfo};
+pub use diff::{DatabaseDiff, RecordChange};
+pub use enrich::{EnrichOptions, EnrichSummary, Enricher, IpField};
 pub use error::{AppError, Result};
 pub use fetcher::DatabaseFetcher;
-pub use parser::{AsnRecord, Database};
+pub use freshness::{DataVersion, MaxAgePolicy, StaleAction};
+pub use metrics::{LookupOutcome, Metrics, UpdateOutcome};
+pub use overrides::{override_record, OverrideSet, RecordSource};
+pub use parser::{parse_asn, AsnRecord, Database, ParseIssue, ParseReport};
+pub use providers::{CloudInfo, ProviderFormat, ProviderSet};
+pub use range::{IpRange, RangeLookup, RangeMatch};
 pub use store::AsnStore;
+pub use synthetic::SyntheticDataset;
+pub use tags::TagSet;
 pub use updater::DatabaseUpdater;
+pub use versions::VersionInfo;
 
 // Information about an ASN record
 #[derive(Debug, Clone)]
@@ -24,8 +58,61 @@ pub struct AsnInfo {
     pub first_ip: Option<String>,
     pub last_ip: Option<String>,
     pub as_number: Option<u32>,
+    // None when iptoasn has no country ("None", "Unknown" or empty)
     pub as_country_code: Option<String>,
     pub as_description: Option<String>,
+    // From the embedded ISO 3166-1 table, when the code is known
+    pub country_name: Option<String>,
+    pub continent: Option<String>,
+    pub region: Option<String>,
+    // Whether the record came from the override layer
+    pub is_override: bool,
+    // User tags of the ASN and of ranges containing the address
+    pub tags: Vec<String>,
+    // Cloud or hosting provider publishing a range containing the address
+    pub cloud_provider: Option<String>,
+    pub cloud_service: Option<String>,
+    pub cloud_region: Option<String>,
+}
+
+// Lookup result holding the record's shared strings; convert with `to_info`
+#[derive(Debug, Clone)]
+pub struct LookupResult {
+    pub ip: IpAddr,
+    // None when the address is not announced
+    pub record: Option<AsnRecord>,
+    pub source: RecordSource,
+    pub tags: Vec<Arc<str>>,
+    pub cloud: Option<CloudInfo>,
+}
+
+impl LookupResult {
+    // Convert to owned strings, echoing `ip` back as the caller spelled it
+    pub fn to_info(&self, ip: &str) -> AsnInfo {
+        let record = self.record.as_ref();
+        let country = record.and_then(|r| country_info(&r.country));
+        let cloud = self.cloud.as_ref();
+
+        AsnInfo {
+            ip: ip.to_string(),
+            announced: record.is_some(),
+            first_ip: record.map(|r| r.first_ip.to_string()),
+            last_ip: record.map(|r| r.last_ip.to_string()),
+            as_number: record.map(|r| r.number),
+            as_country_code: record
+                .and_then(|r| normalize_country_code(&r.country))
+                .map(str::to_string),
+            as_description: record.map(|r| r.description.to_string()),
+            country_name: country.map(|c| c.name.to_string()),
+            continent: country.map(|c| c.continent.to_string()),
+            region: country.map(|c| c.region.to_string()),
+            is_override: self.source == RecordSource::Override,
+            tags: self.tags.iter().map(|tag| tag.to_string()).collect(),
+            cloud_provider: cloud.map(|c| c.provider.to_string()),
+            cloud_service: cloud.and_then(|c| c.service.as_deref().map(str::to_string)),
+            cloud_region: cloud.and_then(|c| c.region.as_deref().map(str::to_string)),
+        }
+    }
 }
 
 // Statistics about the database
@@ -33,116 +120,672 @@ pub struct AsnInfo {
 pub struct DbStats {
     pub record_count: usize,
     pub last_update: Option<SystemTime>,
+    // Upstream Last-Modified of the loaded data
+    pub data_modified: Option<SystemTime>,
+    // When the loaded data was downloaded
+    pub downloaded_at: Option<SystemTime>,
+    // Age of the loaded data
+    pub data_age: Option<Duration>,
+    // Whether the data exceeds the configured maximum age
+    pub stale: bool,
+    // Lookup cache counters, when the cache is enabled
+    pub cache: Option<CacheStats>,
+}
+
+// Events emitted by the database
+#[derive(Debug, Clone)]
+pub enum DbEvent {
+    // A new database was installed; the diff is present when replacing
+    // a loaded database while a `subscribe_with_diffs` receiver is alive
+    Updated {
+        record_count: usize,
+        diff: Option<Arc<DatabaseDiff>>,
+    },
+    // The loaded data exceeds the configured maximum age
+    Stale { age: Duration, max_age: Duration },
+}
+
+/**
+ * Event receiver that has updates carry a diff for as long as it's alive
+ *
+ * Derefs to the underlying broadcast receiver.
+ */
+pub struct DiffReceiver {
+    events: broadcast::Receiver<DbEvent>,
+    wanted: Arc<AtomicUsize>,
+}
+
+impl std::ops::Deref for DiffReceiver {
+    type Target = broadcast::Receiver<DbEvent>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.events
+    }
+}
+
+impl std::ops::DerefMut for DiffReceiver {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.events
+    }
+}
+
+impl Drop for DiffReceiver {
+    fn drop(&mut self) {
+        self.wanted.fetch_sub(1, Ordering::Relaxed);
+    }
 }
 
+// Tags file and its modification time when last loaded
+type TagsSource = (PathBuf, Option<SystemTime>);
+
 // Main database instance for IP to ASN lookups
+#[derive(Clone)]
 pub struct IpToAsnDb {
     store: Arc<RwLock<AsnStore>>,
     fetcher: Arc<tokio::sync::Mutex<DatabaseFetcher>>,
     last_update: Arc<RwLock<Option<SystemTime>>>,
+    data_version: Arc<std::sync::RwLock<DataVersion>>,
+    max_age_policy: Arc<std::sync::RwLock<Option<MaxAgePolicy>>>,
+    events: broadcast::Sender<DbEvent>,
+    // Live receivers that asked for diffs
+    diffs_wanted: Arc<AtomicUsize>,
+    tags: Arc<std::sync::RwLock<Arc<TagSet>>>,
+    tags_source: Arc<std::sync::Mutex<Option<TagsSource>>>,
+    providers: Arc<std::sync::RwLock<Arc<ProviderSet>>>,
+    cache: Arc<std::sync::RwLock<Option<Arc<LookupCache>>>>,
+    metrics: Arc<Metrics>,
 }
 
 impl IpToAsnDb {
     // Create a new database instance
     pub fn new(url: String, cache_dir: String) -> Result<Self> {
         let fetcher = DatabaseFetcher::new(url, &cache_dir)?;
-        
+        let (events, _) = broadcast::channel(16);
+
         Ok(Self {
-            store: Arc::new(RwLock::new(AsnStore::new(Database { records: vec![] }))),
+            store: Arc::new(RwLock::new(AsnStore::new(Database::default()))),
             fetcher: Arc::new(tokio::sync::Mutex::new(fetcher)),
             last_update: Arc::new(RwLock::new(None)),
+            data_version: Arc::new(std::sync::RwLock::new(DataVersion::default())),
+            max_age_policy: Arc::new(std::sync::RwLock::new(None)),
+            tags: Arc::new(std::sync::RwLock::new(Arc::new(TagSet::default()))),
+            tags_source: Arc::new(std::sync::Mutex::new(None)),
+            providers: Arc::new(std::sync::RwLock::new(Arc::new(ProviderSet::default()))),
+            cache: Arc::new(std::sync::RwLock::new(None)),
+            metrics: Arc::new(Metrics::default()),
+            events,
+            diffs_wanted: Arc::new(AtomicUsize::new(0)),
         })
     }
 
     // Load the database (initial load or manual refresh)
     pub async fn load(&self) -> Result<()> {
+        let span = info_span!("load", outcome = field::Empty, duration_ms = field::Empty);
+        let started = Instant::now();
+        let outcome = self.load_source().instrument(span.clone()).await;
+        self.record_update(&span, started, &outcome);
+        outcome.map(|_| ())
+    }
+
+    // Fetch, falling back to the cache, and install
+    async fn load_source(&self) -> Result<UpdateOutcome> {
         info!("Loading database...");
-        
+
         let mut fetcher = self.fetcher.lock().await;
-        
+
+        // A pinned version is loaded as-is, without checking the source
+        if let Some(id) = fetcher.pinned_version() {
+            info!("Loading pinned database version {}", id);
+            let database = self.parse(fetcher.read_version(&id)?)?;
+            fetcher.restore_version(&id).await?;
+            self.install(database, fetcher.version()).await;
+            drop(fetcher);
+
+            self.check_freshness();
+            return Ok(UpdateOutcome::Unchanged);
+        }
+
         // Try to fetch new data, fall back to cache if needed
-        let data = match fetcher.fetch().await {
+        let (database, outcome) = match self.fetch(&mut fetcher).await {
             Ok(Some(data)) => {
                 info!("Downloaded new database");
-                data
+                (self.parse(data)?, UpdateOutcome::Updated)
             }
             Ok(None) => {
                 info!("Database unchanged, loading from cache");
-                fetcher.load_from_cache()?
+                let data = fetcher.load_from_cache()?;
+                (self.parse_cached(data, &fetcher)?, UpdateOutcome::Unchanged)
             }
             Err(e) => {
                 info!("Fetch failed: {}, trying cache", e);
-                fetcher.load_from_cache()?
+                // Without a cache, the fetch failure is what the caller needs to see
+                let data = match fetcher.load_from_cache() {
+                    Err(AppError::DatabaseNotLoaded) => return Err(e),
+                    data => data?,
+                };
+                (self.parse_cached(data, &fetcher)?, UpdateOutcome::Cached)
+            }
+        };
+        let record_count = database.len();
+
+        self.install(database, fetcher.version()).await;
+        drop(fetcher);
+
+        info!("Database loaded successfully ({} records)", record_count);
+        self.check_freshness();
+        Ok(outcome)
+    }
+
+    /**
+     * Load the cached database immediately, then refresh in the background
+     * Lookups are served from the cache while the conditional fetch runs; the
+     * store is hot-swapped if a newer version is downloaded. Falls back to a
+     * regular blocking `load()` when no usable cache exists.
+     */
+    pub async fn load_cached_first(&self) -> Result<()> {
+        let span = info_span!("load_cache", duration_ms = field::Empty);
+        let started = Instant::now();
+        let cached = async {
+            let mut fetcher = self.fetcher.lock().await;
+            fetcher
+                .load_from_cache()
+                .and_then(|data| self.parse_cached(data, &fetcher))
+                .map(|database| (database, fetcher.version()))
+        }
+        .instrument(span.clone())
+        .await;
+
+        let (database, version) = match cached {
+            Ok(cached) => cached,
+            Err(e) => {
+                span.record("duration_ms", started.elapsed().as_millis() as u64);
+                info!("No usable cache ({}), loading from source", e);
+                return self.load().await;
             }
         };
 
+        let record_count = database.len();
+        self.install(database, version).instrument(span.clone()).await;
+        span.record("duration_ms", started.elapsed().as_millis() as u64);
+        info!("Serving cached database ({} records), refreshing in background", record_count);
+
+        // Its own trace, linked to the caller's rather than nested in it
+        let pass = info_span!(parent: None, "background_refresh");
+        pass.follows_from(tracing::Span::current());
+        let db = self.clone();
+        tokio::spawn(async move {
+            match db.refresh().await {
+                Ok(true) => info!("Background refresh loaded new database"),
+                Ok(false) => info!("Cached database is up to date"),
+                Err(e) => warn!("Background refresh failed, keeping cached database: {}", e),
+            }
+            db.check_freshness();
+        }.instrument(pass));
+
+        Ok(())
+    }
+
+    /**
+     * Check for a new database version and hot-swap it if available
+     * Unlike `load()`, this never falls back to the cache.
+     * @returns true if a new version was loaded
+     */
+    pub async fn refresh(&self) -> Result<bool> {
+        let span = info_span!("refresh", outcome = field::Empty, duration_ms = field::Empty);
+        let started = Instant::now();
+        let outcome = self.check_source().instrument(span.clone()).await;
+        self.record_update(&span, started, &outcome);
+        outcome.map(|outcome| outcome == UpdateOutcome::Updated)
+    }
+
+    // Count a load or update attempt and note its outcome and duration on the span
+    fn record_update(&self, span: &tracing::Span, started: Instant, outcome: &Result<UpdateOutcome>) {
+        let outcome = *outcome.as_ref().unwrap_or(&UpdateOutcome::Failed);
+        self.metrics.record_update(outcome);
+        span.record("outcome", outcome.label());
+        span.record("duration_ms", started.elapsed().as_millis() as u64);
+    }
+
+    // Fetch and install without falling back to the cache
+    async fn check_source(&self) -> Result<UpdateOutcome> {
+        // Lock the fetcher for the update
+        let mut fetcher = self.fetcher.lock().await;
+
+        if let Some(id) = fetcher.pinned_version() {
+            info!("Database pinned to version {}, skipping update", id);
+            return Ok(UpdateOutcome::Unchanged);
+        }
+
+        let data = match self.fetch(&mut fetcher).await? {
+            Some(data) => data,
+            None => return Ok(UpdateOutcome::Unchanged),
+        };
+
+        info!("New database version available");
+        let database = self.parse(data)?;
+        let record_count = database.len();
+
+        self.install(database, fetcher.version()).await;
+        info!("Database updated successfully ({} records)", record_count);
+        Ok(UpdateOutcome::Updated)
+    }
+
+    // Fetch through the fetcher, timing downloads that return data
+    async fn fetch(&self, fetcher: &mut DatabaseFetcher) -> Result<Option<Vec<u8>>> {
+        let started = Instant::now();
+        let data = fetcher.fetch().await?;
+        if let Some(data) = &data {
+            self.metrics.record_download(data.len(), started.elapsed());
+        }
+        Ok(data)
+    }
+
+    // Decompress and parse, timing it
+    fn parse(&self, data: Vec<u8>) -> Result<Database> {
+        let started = Instant::now();
         let database = Database::parse(data)?;
+        self.metrics.record_parse(started.elapsed());
+        Ok(database)
+    }
+
+    // Parse the cached file, blaming the cache when it can't be read back
+    fn parse_cached(&self, data: Vec<u8>, fetcher: &DatabaseFetcher) -> Result<Database> {
+        self.parse(data).map_err(|e| match e {
+            AppError::Decompress(reason) | AppError::DatabaseParse(reason) => AppError::CacheCorrupt {
+                path: fetcher.cache_path().display().to_string(),
+                reason,
+            },
+            e => e,
+        })
+    }
+
+    // Hot-swap the store and record when and what was loaded
+    async fn install(&self, database: Database, version: DataVersion) {
+        let span = info_span!(
+            "swap",
+            records = database.len(),
+            added = field::Empty,
+            removed = field::Empty,
+            changed = field::Empty,
+            duration_ms = field::Empty,
+        );
+        let started = Instant::now();
+        self.swap(database, version).instrument(span.clone()).await;
+        span.record("duration_ms", started.elapsed().as_millis() as u64);
+    }
+
+    async fn swap(&self, database: Database, version: DataVersion) {
         let record_count = database.len();
-        
-        // Create new store
+        let ipv4 = database.records.iter().filter(|r| r.first_ip.is_ipv4()).count();
+        self.metrics.set_records(ipv4, record_count - ipv4);
+
         let new_store = AsnStore::new(database);
-        
-        // Hot-swap the store
+
+        // Diffing is only worth its cost when someone asked for it
+        let previous = if self.diffs_wanted.load(Ordering::Relaxed) > 0 {
+            Some(self.store.read().await.clone()).filter(|store| !store.is_empty())
+        } else {
+            None
+        };
+        let diff = match previous {
+            Some(previous) => {
+                // Comparing two full record sets takes a while; keep it off the runtime
+                let current = new_store.clone();
+                let diff = tokio::task::spawn_blocking(move || {
+                    DatabaseDiff::compute(previous.records(), current.records())
+                })
+                .await;
+                match diff {
+                    Ok(diff) => Some(Arc::new(diff)),
+                    Err(e) => {
+                        warn!("Failed to compute the update diff: {}", e);
+                        None
+                    }
+                }
+            }
+            None => None,
+        };
+        if let Some(diff) = &diff {
+            let span = tracing::Span::current();
+            span.record("added", diff.added.len());
+            span.record("removed", diff.removed.len());
+            span.record("changed", diff.changed.len());
+        }
+
         {
+            // Overrides survive the swap
             let mut store_guard = self.store.write().await;
-            *store_guard = new_store;
+            let overrides = store_guard.overrides().clone();
+            *store_guard = new_store.with_overrides(overrides);
         }
-        
-        // Update timestamp
+        self.invalidate_cache();
+
         {
             let mut last_update = self.last_update.write().await;
             *last_update = Some(SystemTime::now());
         }
 
-        info!("Database loaded successfully ({} records)", record_count);
+        *self.data_version.write().unwrap() = version;
+
+        // No subscribers is not an error
+        let _ = self.events.send(DbEvent::Updated { record_count, diff });
+    }
+
+    // Apply the max-age policy's warn/event actions to the loaded data
+    fn check_freshness(&self) {
+        let Some(policy) = *self.max_age_policy.read().unwrap() else {
+            return;
+        };
+        let Some(age) = policy.exceeded(&self.data_version.read().unwrap()) else {
+            return;
+        };
+
+        match policy.action {
+            StaleAction::Warn => warn!(
+                "Database is stale: data is {}s old (max {}s)",
+                age.as_secs(),
+                policy.max_age.as_secs()
+            ),
+            StaleAction::Event => {
+                // No subscribers is not an error
+                let _ = self.events.send(DbEvent::Stale {
+                    age,
+                    max_age: policy.max_age,
+                });
+            }
+            StaleAction::Fail => {}
+        }
+    }
+
+    /**
+     * Set how many previous database versions to keep in the cache directory
+     * @param keep - Number of versions besides the current one (0 disables the archive)
+     */
+    pub async fn set_keep_versions(&self, keep: usize) {
+        self.fetcher.lock().await.set_keep_versions(keep);
+    }
+
+    /**
+     * List the database versions kept in the cache directory
+     * @returns Versions, newest first
+     */
+    pub async fn list_versions(&self) -> Result<Vec<VersionInfo>> {
+        self.fetcher.lock().await.list_versions()
+    }
+
+    /**
+     * Roll back to a previously downloaded version
+     * The version becomes the current cache file; pin it to stop the updater
+     * from replacing it with the next upstream release.
+     * @param id - Version id from `list_versions`
+     */
+    pub async fn rollback(&self, id: &str) -> Result<()> {
+        let mut fetcher = self.fetcher.lock().await;
+
+        // Parse first so a broken version never replaces the current cache
+        let database = self.parse(fetcher.read_version(id)?)?;
+        fetcher.restore_version(id).await?;
+
+        let record_count = database.len();
+        self.install(database, fetcher.version()).await;
+        drop(fetcher);
+
+        info!("Rolled back to database version {} ({} records)", id, record_count);
+        self.check_freshness();
         Ok(())
     }
 
+    /**
+     * Pin a version so the updater won't replace it, or unpin with `None`
+     * Pinning a version that isn't loaded rolls back to it first.
+     * @param id - Version id from `list_versions`
+     */
+    pub async fn pin_version(&self, id: Option<&str>) -> Result<()> {
+        if let Some(id) = id {
+            let current = self.list_versions().await?.into_iter().find(|v| v.current);
+            if current.is_none_or(|v| v.id != id) {
+                self.rollback(id).await?;
+            }
+        }
+
+        self.fetcher.lock().await.pin_version(id)
+    }
+
+    /**
+     * Set or clear the maximum data age policy
+     * @param policy - Maximum age and the action to take when exceeded
+     */
+    pub fn set_max_age_policy(&self, policy: Option<MaxAgePolicy>) {
+        *self.max_age_policy.write().unwrap() = policy;
+        self.check_freshness();
+    }
+
+    /**
+     * Subscribe to database events
+     * @returns Receiver for events emitted after this call
+     */
+    pub fn subscribe(&self) -> broadcast::Receiver<DbEvent> {
+        self.events.subscribe()
+    }
+
+    /**
+     * Subscribe to database events, with a diff on each update
+     *
+     * Diffing compares every record of both databases, so it only happens
+     * while at least one of these receivers is alive.
+     * @returns Receiver for events emitted after this call
+     */
+    pub fn subscribe_with_diffs(&self) -> DiffReceiver {
+        self.diffs_wanted.fetch_add(1, Ordering::Relaxed);
+        DiffReceiver {
+            events: self.events.subscribe(),
+            wanted: self.diffs_wanted.clone(),
+        }
+    }
+
     /**
      * Look up an IP address
      * @param ip - IP address to look up (IPv4 or IPv6)
      * @returns ASN information
      */
     pub fn lookup(&self, ip: &str) -> Result<AsnInfo> {
-        let parsed_ip = ip.parse::<IpAddr>()
-            .map_err(|_| AppError::InvalidIp(ip.to_string()))?;
-        
+        let Ok(parsed_ip) = ip.parse::<IpAddr>() else {
+            self.metrics.record_lookup(LookupOutcome::Invalid, None);
+            return Err(AppError::InvalidIp(ip.to_string()));
+        };
+
+        Ok(self.lookup_addr(parsed_ip)?.to_info(ip))
+    }
+
+    /**
+     * Look up a parsed address without converting the result to strings
+     * @param ip - IP address to look up
+     * @returns The matched record and annotations, sharing the store's strings
+     */
+    pub fn lookup_addr(&self, ip: IpAddr) -> Result<LookupResult> {
+        self.ensure_fresh()?;
+
+        let started = Instant::now();
+        let result = self.lookup_cached(ip);
+        let outcome = if result.record.is_some() {
+            LookupOutcome::Hit
+        } else {
+            LookupOutcome::Miss
+        };
+        self.metrics.record_lookup(outcome, Some(started.elapsed()));
+        Ok(result)
+    }
+
+    // Serve from the lookup cache when enabled, filling it on a miss
+    fn lookup_cached(&self, ip: IpAddr) -> LookupResult {
+        let Some(cache) = self.cache.read().unwrap().clone() else {
+            return self.resolve(ip);
+        };
+        if let Some(result) = cache.get(&ip) {
+            return result;
+        }
+
+        // Read before the store, so a swap in between discards this result
+        let generation = cache.generation();
+        let result = self.resolve(ip);
+        cache.insert(ip, result.clone(), generation);
+        result
+    }
+
+    /**
+     * Look up many addresses under a single read lock, bypassing the cache
+     * @param ips - Addresses to look up
+     * @returns The ASN of each address in order, 0 when not announced; records
+     * and overrides for AS0 ("Not routed") also read as 0
+     */
+    pub fn lookup_numbers(&self, ips: impl IntoIterator<Item = IpAddr>) -> Result<Vec<u32>> {
+        self.ensure_fresh()?;
+
+        // Count matched records rather than non-zero ASNs, like lookup_addr,
+        // so "Not routed" ranges are hits on both paths
+        let mut hits = 0;
+        let store = read_blocking(&self.store);
+        let numbers: Vec<u32> = ips
+            .into_iter()
+            .map(|ip| match store.lookup(ip) {
+                Some(record) => {
+                    hits += 1;
+                    record.number
+                }
+                None => 0,
+            })
+            .collect();
+        drop(store);
+
+        self.metrics
+            .record_batch(hits, numbers.len() as u64 - hits);
+        Ok(numbers)
+    }
+
+    // Look up an address in the store and annotation layers
+    fn resolve(&self, ip: IpAddr) -> LookupResult {
         // Note: This blocks, but only briefly for the read lock
-        let store = futures::executor::block_on(self.store.read());
-        
-        match store.lookup(parsed_ip) {
-            Some(record) => Ok(AsnInfo {
-                ip: ip.to_string(),
-                announced: true,
-                first_ip: Some(record.first_ip.to_string()),
-                last_ip: Some(record.last_ip.to_string()),
-                as_number: Some(record.number),
-                as_country_code: Some(record.country.to_string()),
-                as_description: Some(record.description.to_string()),
-            }),
-            None => Ok(AsnInfo {
-                ip: ip.to_string(),
-                announced: false,
-                first_ip: None,
-                last_ip: None,
-                as_number: None,
-                as_country_code: None,
-                as_description: None,
-            }),
+        let store = read_blocking(&self.store);
+        let (record, source) = match store.lookup_with_source(ip) {
+            Some((record, source)) => (Some(record.clone()), source),
+            None => (None, RecordSource::Upstream),
+        };
+        drop(store);
+
+        let tags = self.tags.read().unwrap().lookup(ip, record.as_ref().map(|r| r.number));
+        let cloud = self.providers.read().unwrap().lookup(ip).cloned();
+        LookupResult {
+            ip,
+            record,
+            source,
+            tags,
+            cloud,
         }
     }
+    /**
+     * Find every record overlapping a CIDR prefix or address range
+     *
+     * Only upstream records are reported; overrides are not merged in, so an
+     * address covered by an override can resolve differently in `lookup`.
+     * @param range - "203.0.113.0/22", "10.0.0.0-10.255.255.255" or a single address
+     */
+    pub fn lookup_range(&self, range: &str) -> Result<RangeLookup> {
+        let range: IpRange = range.parse()?;
+
+        self.ensure_fresh()?;
+
+        let store = read_blocking(&self.store);
+        Ok(RangeLookup::new(range, store.lookup_range(&range)))
+    }
+
+    // Fail lookups when the max-age policy says so
+    fn ensure_fresh(&self) -> Result<()> {
+        if let Some(policy) = *self.max_age_policy.read().unwrap() {
+            if policy.action == StaleAction::Fail {
+                if let Some(age) = policy.exceeded(&self.data_version.read().unwrap()) {
+                    return Err(AppError::StaleDatabase {
+                        age_secs: age.as_secs(),
+                        max_age_secs: policy.max_age.as_secs(),
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
     /**
      * Get database statistics
-     * @returns Statistics including record count and last update time
+     * @returns Statistics including record count, last update time and data age
      */
     pub fn stats(&self) -> DbStats {
-        let store = futures::executor::block_on(self.store.read());
-        let last_update = futures::executor::block_on(self.last_update.read());
-        
+        let store = read_blocking(&self.store);
+        let last_update = read_blocking(&self.last_update);
+        let version = *self.data_version.read().unwrap();
+        let policy = *self.max_age_policy.read().unwrap();
+
         DbStats {
             record_count: store.len(),
             last_update: *last_update,
+            data_modified: version.upstream_modified,
+            downloaded_at: version.downloaded_at,
+            data_age: version.age(),
+            stale: policy.is_some_and(|p| p.exceeded(&version).is_some()),
+            cache: self.cache.read().unwrap().as_ref().map(|cache| cache.stats()),
+        }
+    }
+
+    /**
+     * Render lookup, update and data metrics for Prometheus
+     * @returns The text exposition format, ready to serve from a scrape endpoint
+     */
+    pub fn metrics(&self) -> String {
+        let mut out = String::new();
+        self.metrics.render(&mut out);
+
+        let stats = self.stats();
+        let unix_secs = |time: Option<SystemTime>| {
+            time.and_then(|t| t.duration_since(SystemTime::UNIX_EPOCH).ok())
+                .map_or(0.0, |d| d.as_secs_f64())
+        };
+        let gauges = [
+            (
+                "iptoasn_last_update_timestamp_seconds",
+                "When the database was last installed",
+                unix_secs(stats.last_update),
+            ),
+            (
+                "iptoasn_data_modified_timestamp_seconds",
+                "Upstream modification time of the loaded data",
+                unix_secs(stats.data_modified),
+            ),
+            (
+                "iptoasn_data_age_seconds",
+                "Age of the loaded data",
+                stats.data_age.map_or(0.0, |age| age.as_secs_f64()),
+            ),
+            (
+                "iptoasn_stale",
+                "Whether the data exceeds the max-age policy",
+                if stats.stale { 1.0 } else { 0.0 },
+            ),
+        ];
+        for (name, help, value) in gauges {
+            metrics::header(&mut out, name, help, "gauge");
+            out.push_str(&format!("{} {}\n", name, value));
+        }
+
+        if let Some(cache) = stats.cache {
+            metrics::header(
+                &mut out,
+                "iptoasn_lookup_cache_requests_total",
+                "Lookup cache requests by result",
+                "counter",
+            );
+            out.push_str(&format!("iptoasn_lookup_cache_requests_total{{result=\"hit\"}} {}\n", cache.hits));
+            out.push_str(&format!("iptoasn_lookup_cache_requests_total{{result=\"miss\"}} {}\n", cache.misses));
+            metrics::header(&mut out, "iptoasn_lookup_cache_entries", "Results held in the lookup cache", "gauge");
+            out.push_str(&format!("iptoasn_lookup_cache_entries {}\n", cache.entries));
         }
+        out
     }
     /**
      * Start automatic database updates
@@ -150,83 +793,545 @@ impl IpToAsnDb {
      * @returns Handle to the updater task
      */
     pub async fn start_updater(&self, interval_minutes: u64) -> tokio::task::JoinHandle<()> {
-        info!("Starting database updater (interval: {} minutes)", interval_minutes);
-        
-        let store = self.store.clone();
-        let fetcher = self.fetcher.clone();
-        let last_update = self.last_update.clone();
-        
+        self.start_updater_every(Duration::from_secs(interval_minutes * 60)).await
+    }
+
+    // Start the background updater with an arbitrary check interval
+    pub async fn start_updater_every(&self, interval: Duration) -> tokio::task::JoinHandle<()> {
+        info!("Starting database updater (interval: {:?})", interval);
+
+        let db = self.clone();
+        let origin = tracing::Span::current();
+
         tokio::spawn(async move {
-            let interval = std::time::Duration::from_secs(interval_minutes * 60);
-            
             loop {
                 tokio::time::sleep(interval).await;
-                
-                info!("Checking for database updates...");
-                
-                // Lock the fetcher for the update
-                let mut fetcher_guard = fetcher.lock().await;
-                
-                match fetcher_guard.fetch().await {
-                    Ok(Some(data)) => {
-                        info!("New database version available");
-                        
-                        match Database::parse(data) {
-                            Ok(database) => {
-                                let record_count = database.len();
-                                let new_store = AsnStore::new(database);
-                                
-                                // Hot-swap
-                                {
-                                    let mut store_guard = store.write().await;
-                                    *store_guard = new_store;
-                                }
-                                
-                                // Update timestamp
-                                {
-                                    let mut last_update_guard = last_update.write().await;
-                                    *last_update_guard = Some(SystemTime::now());
-                                }
-                                
-                                info!("Database updated successfully ({} records)", record_count);
-                            }
-                            Err(e) => {
-                                tracing::error!("Failed to parse database: {}", e);
-                            }
+
+                // Each check is its own trace, linked to where the updater was started
+                let pass = info_span!(parent: None, "auto_update");
+                pass.follows_from(&origin);
+                async {
+                    info!("Checking for database updates...");
+
+                    match db.refresh().await {
+                        Ok(true) => {}
+                        Ok(false) => {
+                            info!("Database is up to date (304 Not Modified)");
+                        }
+                        Err(e) => {
+                            warn!("Database update failed: {}", e);
                         }
                     }
-                    Ok(None) => {
-                        info!("Database is up to date (304 Not Modified)");
-                    }
-                    Err(e) => {
-                        tracing::warn!("Database update failed: {}", e);
-                    }
+
+                    // Data keeps ageing even when the fetch fails
+                    db.check_freshness();
+                }
+                .instrument(pass)
+                .await;
+            }
+        })
+    }
+
+    /**
+     * Create an enricher for delimited records over the current database
+     * @param options - Where to find the IP and how columns are delimited
+     */
+    pub fn enricher(&self, options: EnrichOptions) -> Enricher {
+        let store = read_blocking(&self.store).clone();
+        Enricher::new(store, options)
+    }
+
+    /**
+     * List every ASN in the database, sorted by number
+     */
+    pub fn list_asns(&self) -> Vec<AsnEntry> {
+        let store = read_blocking(&self.store);
+        store.catalog().entries().to_vec()
+    }
+
+    /**
+     * Get catalogue details for one ASN
+     * @param number - AS number
+     */
+    pub fn asn(&self, number: u32) -> Option<AsnEntry> {
+        let store = read_blocking(&self.store);
+        store.catalog().get(number).cloned()
+    }
+
+    /**
+     * Search ASN descriptions, best matches first
+     * @param query - Words that must all appear in the description, e.g. "AMAZON"
+     */
+    pub fn search_asns(&self, query: &str) -> Vec<AsnEntry> {
+        let store = read_blocking(&self.store);
+        store.catalog().search(query).into_iter().cloned().collect()
+    }
+
+    /**
+     * List the ASNs registered in a country
+     * @param country - ISO country code, case-insensitive
+     */
+    pub fn asns_by_country(&self, country: &str) -> Vec<AsnEntry> {
+        let store = read_blocking(&self.store);
+        store.catalog().by_country(country).cloned().collect()
+    }
+
+    /**
+     * List the country codes present in the database
+     */
+    pub fn countries(&self) -> Vec<String> {
+        let store = read_blocking(&self.store);
+        store.catalog().countries().iter().map(|c| c.to_string()).collect()
+    }
+
+    /**
+     * List the ranges announced by an ASN
+     * @param number - AS number
+     */
+    pub fn ranges_for_asn(&self, number: u32) -> Vec<AsnRecord> {
+        let store = read_blocking(&self.store);
+        store.records_for_asn(number).cloned().collect()
+    }
+
+    /**
+     * List the ranges registered in a country
+     * @param country - ISO country code, case-insensitive
+     */
+    pub fn ranges_by_country(&self, country: &str) -> Vec<AsnRecord> {
+        let store = read_blocking(&self.store);
+        store.records_in_country(country).cloned().collect()
+    }
+
+    /**
+     * Get address space totals per ASN, per country and overall
+     *
+     * Computed on first use for each loaded database.
+     */
+    pub fn aggregates(&self) -> Arc<Aggregates> {
+        // Build from a clone so a first-time computation doesn't hold up swaps
+        let store = read_blocking(&self.store).clone();
+        store.aggregates()
+    }
+
+    /**
+     * Replace the overrides with the records of a TSV or JSON file
+     * @param path - Overrides file; see `OverrideSet::load_file` for the formats
+     * @returns Number of overrides loaded
+     */
+    pub async fn load_overrides(&self, path: &Path) -> Result<usize> {
+        let path = path.to_path_buf();
+        let overrides = tokio::task::spawn_blocking(move || OverrideSet::load_file(&path))
+            .await
+            .map_err(|e| AppError::DatabaseParse(format!("Override task failed: {}", e)))??;
+        let count = overrides.len();
+
+        self.update_overrides(|current| *current = overrides).await;
+        info!("Loaded {} overrides", count);
+        Ok(count)
+    }
+
+    /**
+     * Add an override, replacing any override for exactly the same range
+     * @param record - Record that takes priority over upstream data
+     */
+    pub async fn add_override(&self, record: AsnRecord) {
+        self.update_overrides(|overrides| overrides.insert(record)).await;
+    }
+
+    /**
+     * Remove the override for exactly this range
+     * @returns Whether an override was removed
+     */
+    pub async fn remove_override(&self, range: &IpRange) -> bool {
+        let mut removed = false;
+        self.update_overrides(|overrides| removed = overrides.remove(range)).await;
+        removed
+    }
+
+    /**
+     * Remove all overrides
+     */
+    pub async fn clear_overrides(&self) {
+        self.update_overrides(|overrides| *overrides = OverrideSet::default()).await;
+    }
+
+    /**
+     * List the overrides, sorted by range
+     */
+    pub fn overrides(&self) -> Vec<AsnRecord> {
+        let store = read_blocking(&self.store);
+        store.overrides().records().to_vec()
+    }
+
+    // Apply a change to the overrides of the current store
+    async fn update_overrides(&self, change: impl FnOnce(&mut OverrideSet)) {
+        let mut store = self.store.write().await;
+        let mut overrides = OverrideSet::clone(store.overrides());
+        change(&mut overrides);
+        *store = store.clone().with_overrides(Arc::new(overrides));
+        self.invalidate_cache();
+    }
+
+    /**
+     * Replace the tags with those of a file and remember it for reloads
+     * @param path - Tags file; see `TagSet::load_file` for the formats
+     * @returns Number of tagged ASNs and ranges
+     */
+    pub fn load_tags(&self, path: &Path) -> Result<usize> {
+        let modified = std::fs::metadata(path).and_then(|m| m.modified()).ok();
+        let tags = TagSet::load_file(path)?;
+        let count = tags.len();
+
+        *self.tags.write().unwrap() = Arc::new(tags);
+        *self.tags_source.lock().unwrap() = Some((path.to_path_buf(), modified));
+        self.invalidate_cache();
+        info!("Loaded tags for {} ASNs and ranges from {}", count, path.display());
+        Ok(count)
+    }
+
+    /**
+     * Reload the tags file if it changed since it was loaded
+     * @returns Whether the tags were reloaded
+     */
+    pub fn reload_tags(&self) -> Result<bool> {
+        match self.changed_tags_path() {
+            Some(path) => self.load_tags(&path).map(|_| true),
+            None => Ok(false),
+        }
+    }
+
+    // The loaded tags file, if it was modified since
+    fn changed_tags_path(&self) -> Option<PathBuf> {
+        let (path, loaded) = self.tags_source.lock().unwrap().clone()?;
+        let modified = std::fs::metadata(&path).and_then(|m| m.modified()).ok();
+        (modified != loaded).then_some(path)
+    }
+
+    /**
+     * Watch the tags file, reloading it when it changes
+     * @param interval - How often to check the file's modification time
+     * @returns Handle to the watcher task
+     */
+    pub fn start_tags_watcher(&self, interval: Duration) -> tokio::task::JoinHandle<()> {
+        let db = self.clone();
+        let origin = tracing::Span::current();
+
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(interval).await;
+                if db.changed_tags_path().is_none() {
+                    continue;
                 }
-                
-                drop(fetcher_guard); // Release the lock
+
+                // Only changes get a trace, linked to where the watcher was started
+                let pass = info_span!(parent: None, "tags_reload");
+                pass.follows_from(&origin);
+                pass.in_scope(|| {
+                    // A half-written file fails to parse; keep the old tags and retry
+                    if let Err(e) = db.reload_tags() {
+                        warn!("Failed to reload tags: {}", e);
+                    }
+                });
             }
         })
     }
 
+    /**
+     * Merge a provider range list into the cloud annotation layer
+     * @param path - AWS, GCP, Azure or generic JSON file
+     * @param format - File format; detected from the content when None
+     * @returns Number of new prefixes
+     */
+    pub fn load_provider_ranges(&self, path: &Path, format: Option<ProviderFormat>) -> Result<usize> {
+        let mut providers = ProviderSet::clone(&self.providers.read().unwrap());
+        let skipped = providers.skipped();
+        let count = providers.load_file(path, format)?;
+        if providers.skipped() > skipped {
+            warn!("Skipped {} prefixes with invalid lengths in {}", providers.skipped() - skipped, path.display());
+        }
+
+        *self.providers.write().unwrap() = Arc::new(providers);
+        self.invalidate_cache();
+        info!("Loaded {} provider prefixes from {}", count, path.display());
+        Ok(count)
+    }
+
+    // Remove all provider ranges
+    pub fn clear_provider_ranges(&self) {
+        *self.providers.write().unwrap() = Arc::new(ProviderSet::default());
+        self.invalidate_cache();
+    }
+
+    // The loaded provider ranges
+    pub fn provider_ranges(&self) -> Arc<ProviderSet> {
+        self.providers.read().unwrap().clone()
+    }
+
+    /**
+     * Cache lookup results for the most recently looked up addresses
+     * @param capacity - Maximum number of cached results; 0 disables the cache
+     *
+     * Replacing the cache resets its counters. Cached results are dropped
+     * whenever the data, overrides, tags or provider ranges change.
+     */
+    pub fn set_lookup_cache(&self, capacity: usize) {
+        *self.cache.write().unwrap() = NonZeroUsize::new(capacity).map(|c| Arc::new(LookupCache::new(c)));
+    }
+
+    // Drop cached lookup results after a change to what lookups return
+    fn invalidate_cache(&self) {
+        if let Some(cache) = self.cache.read().unwrap().as_ref() {
+            cache.clear();
+        }
+    }
+
     // Get the internal store (for server usage)
     pub fn get_store(&self) -> Arc<RwLock<AsnStore>> {
         self.store.clone()
     }
 }
 
+/**
+ * Take a tokio read lock from sync code
+ *
+ * The acquire runs unconstrained: inside a tokio task, each acquire spends
+ * the task's cooperative budget, and once it runs out the read keeps
+ * returning Pending until the task yields, which `block_on` never does.
+ */
+fn read_blocking<T>(lock: &RwLock<T>) -> tokio::sync::RwLockReadGuard<'_, T> {
+    futures::executor::block_on(tokio::task::unconstrained(lock.read()))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    // A fresh directory for one test, unique to this process
+    fn test_dir(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("iptoasn_test_{}_{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
     #[tokio::test]
     async fn test_lookup_flow() {
-
+        let cache_dir = test_dir("lookup_flow");
         let db = IpToAsnDb::new(
             "file:///dev/null".to_string(),
-            "/tmp/test_cache".to_string(),
-        ).unwrap();
+            cache_dir.to_string_lossy().to_string(),
+        )
+        .unwrap();
         
         let stats = db.stats();
         assert_eq!(stats.record_count, 0);
+        let _ = std::fs::remove_dir_all(&cache_dir);
+    }
+
+    #[tokio::test]
+    async fn test_load_cached_first_serves_cache() {
+        use flate2::write::GzEncoder;
+        use std::io::Write;
+
+        let cache_dir = test_dir("cached_first");
+
+        let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder
+            .write_all(b"8.8.8.0\t8.8.8.255\t15169\tUS\tGOOGLE\n")
+            .unwrap();
+        std::fs::write(
+            cache_dir.join("ip2asn-combined.tsv.gz"),
+            encoder.finish().unwrap(),
+        )
+        .unwrap();
+
+        // The source is unreachable, so only the cache can satisfy lookups
+        let db = IpToAsnDb::new(
+            "file:///nonexistent/ip2asn.tsv.gz".to_string(),
+            cache_dir.to_string_lossy().to_string(),
+        )
+        .unwrap();
+        db.load_cached_first().await.unwrap();
+
+        let info = db.lookup("8.8.8.8").unwrap();
+        assert!(info.announced);
+        assert_eq!(info.as_number, Some(15169));
+        let _ = std::fs::remove_dir_all(&cache_dir);
+    }
+
+    #[tokio::test]
+    async fn test_load_cached_first_refreshes_in_background() {
+        use flate2::write::GzEncoder;
+        use std::io::Write;
+
+        let gzip = |tsv: &[u8]| {
+            let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+            encoder.write_all(tsv).unwrap();
+            encoder.finish().unwrap()
+        };
+        let dir = test_dir("cached_first_refresh");
+        let cache_dir = dir.join("cache");
+        std::fs::create_dir_all(&cache_dir).unwrap();
+        std::fs::write(
+            cache_dir.join("ip2asn-combined.tsv.gz"),
+            gzip(b"8.8.8.0\t8.8.8.255\t15169\tUS\tGOOGLE\n"),
+        )
+        .unwrap();
+        let source = dir.join("source.tsv.gz");
+        std::fs::write(&source, gzip(b"8.8.8.0\t8.8.8.255\t64500\tUS\tNEWER\n")).unwrap();
+
+        let db = IpToAsnDb::new(
+            format!("file://{}", source.display()),
+            cache_dir.to_string_lossy().to_string(),
+        )
+        .unwrap();
+        let mut events = db.subscribe();
+        db.load_cached_first().await.unwrap();
+
+        // The cache is installed first, then the refresh swaps in the source
+        for _ in 0..2 {
+            let event = tokio::time::timeout(Duration::from_secs(5), events.recv()).await;
+            assert!(matches!(event.unwrap().unwrap(), DbEvent::Updated { .. }));
+        }
+        assert_eq!(db.lookup("8.8.8.8").unwrap().as_number, Some(64500));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn test_max_age_policy_fails_lookups() {
+        let cache_dir = test_dir("max_age");
+        let db = IpToAsnDb::new(
+            "file:///dev/null".to_string(),
+            cache_dir.to_string_lossy().to_string(),
+        )
+        .unwrap();
+
+        *db.data_version.write().unwrap() = DataVersion {
+            upstream_modified: Some(SystemTime::now() - Duration::from_secs(7200)),
+            downloaded_at: Some(SystemTime::now()),
+        };
+        assert!(db.lookup("8.8.8.8").is_ok());
+
+        db.set_max_age_policy(Some(MaxAgePolicy::new(
+            Duration::from_secs(3600),
+            StaleAction::Fail,
+        )));
+        assert!(db.stats().stale);
+        assert!(matches!(
+            db.lookup("8.8.8.8"),
+            Err(AppError::StaleDatabase { .. })
+        ));
+        let _ = std::fs::remove_dir_all(&cache_dir);
+    }
+
+    #[tokio::test]
+    async fn test_overrides_survive_swaps() {
+        let cache_dir = test_dir("overrides_swaps");
+        let db = IpToAsnDb::new(
+            "file:///dev/null".to_string(),
+            cache_dir.to_string_lossy().to_string(),
+        )
+        .unwrap();
+
+        db.add_override(override_record("10.0.0.0/8".parse().unwrap(), 64512, "US", "CORP")).await;
+        db.install(
+            Database::parse_tsv("10.1.0.0\t10.1.255.255\t15169\tUS\tGOOGLE\n").unwrap(),
+            DataVersion::default(),
+        )
+        .await;
+
+        let info = db.lookup("10.1.2.3").unwrap();
+        assert_eq!(info.as_number, Some(64512));
+        assert!(info.is_override);
+        let ips = ["10.1.2.3", "11.0.0.1"].map(|ip| ip.parse().unwrap());
+        assert_eq!(db.lookup_numbers(ips).unwrap(), vec![64512, 0]);
+
+        assert!(db.remove_override(&"10.0.0.0/8".parse().unwrap()).await);
+        let info = db.lookup("10.1.2.3").unwrap();
+        assert_eq!(info.as_number, Some(15169));
+        assert!(!info.is_override);
+        let _ = std::fs::remove_dir_all(&cache_dir);
+    }
+
+    #[tokio::test]
+    async fn test_lookup_cache_invalidated_on_change() {
+        let cache_dir = test_dir("lookup_cache");
+        let db = IpToAsnDb::new(
+            "file:///dev/null".to_string(),
+            cache_dir.to_string_lossy().to_string(),
+        )
+        .unwrap();
+        db.set_lookup_cache(100);
+        db.install(
+            Database::parse_tsv("10.1.0.0\t10.1.255.255\t15169\tUS\tGOOGLE\n").unwrap(),
+            DataVersion::default(),
+        )
+        .await;
+
+        assert_eq!(db.lookup("10.1.2.3").unwrap().as_number, Some(15169));
+        assert_eq!(db.lookup("10.1.2.3").unwrap().as_number, Some(15169));
+
+        db.add_override(override_record("10.0.0.0/8".parse().unwrap(), 64512, "US", "CORP")).await;
+        assert_eq!(db.lookup("10.1.2.3").unwrap().as_number, Some(64512));
+
+        let cache = db.stats().cache.unwrap();
+        assert_eq!((cache.hits, cache.misses, cache.entries), (1, 2, 1));
+        let _ = std::fs::remove_dir_all(&cache_dir);
+    }
+
+    #[tokio::test]
+    async fn test_many_lookups_in_one_task() {
+        let cache_dir = test_dir("many_lookups");
+        let db = IpToAsnDb::new(
+            "file:///dev/null".to_string(),
+            cache_dir.to_string_lossy().to_string(),
+        )
+        .unwrap();
+
+        // Well past tokio's per-task cooperative budget
+        for _ in 0..1_000 {
+            assert!(!db.lookup("8.8.8.8").unwrap().announced);
+        }
+        let _ = std::fs::remove_dir_all(&cache_dir);
+    }
+
+    #[tokio::test]
+    async fn test_metrics_count_lookups_and_updates() {
+        let dir = test_dir("metrics");
+        let source = dir.join("source.tsv.gz");
+        let db = IpToAsnDb::new(
+            format!("file://{}", source.display()),
+            dir.join("cache").display().to_string(),
+        ).unwrap();
+
+        // Missing source and no cache
+        assert!(db.load().await.is_err());
+
+        SyntheticDataset::new(100, 20).write_gzip(&source).unwrap();
+        db.load().await.unwrap();
+
+        let record = db.lookup_addr("1.0.0.1".parse().unwrap()).unwrap().record.unwrap();
+        assert!(!db.lookup("240.0.0.1").unwrap().announced);
+        assert!(db.lookup("not-an-ip").is_err());
+        db.lookup_numbers([record.first_ip, "fc00::1".parse().unwrap()]).unwrap();
+
+        // A "Not routed" record is a hit on both paths
+        let synthetic = SyntheticDataset::new(100, 20).database().unwrap();
+        let not_routed = synthetic.records.iter().find(|r| r.number == 0).unwrap().first_ip;
+        assert!(db.lookup_addr(not_routed).unwrap().record.is_some());
+        assert_eq!(db.lookup_numbers([not_routed]).unwrap(), vec![0]);
+
+        let out = db.metrics();
+        assert!(out.contains("iptoasn_lookups_total{result=\"hit\"} 4\n"), "{}", out);
+        assert!(out.contains("iptoasn_lookups_total{result=\"miss\"} 2\n"));
+        assert!(out.contains("iptoasn_lookups_total{result=\"invalid\"} 1\n"));
+        assert!(out.contains("iptoasn_lookup_duration_seconds_count 3\n"));
+        assert!(out.contains("iptoasn_update_attempts_total{outcome=\"updated\"} 1\n"));
+        assert!(out.contains("iptoasn_update_attempts_total{outcome=\"failed\"} 1\n"));
+        assert!(out.contains("iptoasn_parse_duration_seconds_count 1\n"));
+        assert!(out.contains("iptoasn_records{family=\"ipv4\"} 100\n"));
+        assert!(out.contains("iptoasn_records{family=\"ipv6\"} 20\n"));
+        assert!(!out.contains("iptoasn_lookup_cache_entries"));
+        let _ = std::fs::remove_dir_all(&dir);
     }
 }
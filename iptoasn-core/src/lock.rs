@@ -0,0 +1,99 @@
+use crate::error::{AppError, Result};
+use std::fs::{File, OpenOptions, TryLockError};
+use std::io::Write;
+use std::path::Path;
+use std::time::{Duration, Instant, SystemTime};
+use tracing::{debug, info};
+
+// How often to retry while another process holds the lock
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/**
+ * Exclusive advisory lock on a cache directory, shared between processes
+ *
+ * The lock is held through an OS file lock, so it is released automatically
+ * when the owning process exits or crashes and never needs manual cleanup.
+ */
+pub struct CacheLock {
+    file: File,
+}
+
+impl CacheLock {
+    // Acquire the lock, waiting up to `timeout` for other holders to finish
+    pub async fn acquire(path: &Path, timeout: Duration) -> Result<Self> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(path)?;
+
+        let started = Instant::now();
+        let mut waiting = false;
+
+        loop {
+            match file.try_lock() {
+                Ok(()) => break,
+                Err(TryLockError::WouldBlock) => {
+                    if started.elapsed() >= timeout {
+                        return Err(AppError::CacheLockTimeout(path.display().to_string()));
+                    }
+                    if !waiting {
+                        info!("Waiting for cache lock held by another process: {}", path.display());
+                        waiting = true;
+                    }
+                    tokio::time::sleep(POLL_INTERVAL).await;
+                }
+                Err(TryLockError::Error(e)) => return Err(e.into()),
+            }
+        }
+
+        // Record the owner for diagnostics; the OS lock is what matters
+        file.set_len(0)?;
+        let since = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        writeln!(file, "pid={} since={}", std::process::id(), since)?;
+
+        debug!("Acquired cache lock: {}", path.display());
+        Ok(Self { file })
+    }
+}
+
+impl Drop for CacheLock {
+    fn drop(&mut self) {
+        let _ = self.file.unlock();
+    }
+}
+
+// Write a file via a temporary sibling and rename, so readers never see partial data
+pub(crate) fn write_atomic(path: &Path, data: &[u8]) -> Result<()> {
+    let mut tmp_name = path.as_os_str().to_owned();
+    tmp_name.push(format!(".{}.tmp", std::process::id()));
+    let tmp_path = Path::new(&tmp_name);
+
+    std::fs::write(tmp_path, data)?;
+    std::fs::rename(tmp_path, path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_lock_is_exclusive() {
+        let dir = std::env::temp_dir().join(format!("iptoasn_test_lock_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("cache.lock");
+
+        let held = CacheLock::acquire(&path, Duration::from_secs(1)).await.unwrap();
+        let contended = CacheLock::acquire(&path, Duration::from_millis(300)).await;
+        assert!(matches!(contended, Err(AppError::CacheLockTimeout(_))));
+
+        drop(held);
+        assert!(CacheLock::acquire(&path, Duration::from_millis(300)).await.is_ok());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
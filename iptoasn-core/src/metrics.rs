@@ -0,0 +1,262 @@
+use std::fmt::Write as _;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+// Bucket bounds in seconds; a lookup is normally well under a microsecond
+const LOOKUP_BUCKETS: &[f64] = &[
+    0.000_000_25, 0.000_000_5, 0.000_001, 0.000_002_5, 0.000_005, 0.000_01, 0.000_05, 0.000_1, 0.001,
+];
+const DOWNLOAD_BUCKETS: &[f64] = &[0.1, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 120.0];
+const PARSE_BUCKETS: &[f64] = &[0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+/**
+ * How a lookup ended
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LookupOutcome {
+    // The address is in a listed range, including "Not routed" ones
+    Hit,
+    // The address is valid but no range lists it
+    Miss,
+    // The input isn't an IP address
+    Invalid,
+}
+
+/**
+ * How a load or update attempt ended
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UpdateOutcome {
+    // New data was downloaded and installed
+    Updated,
+    // The source reported no change, or the database is pinned
+    Unchanged,
+    // The source failed and the cached copy was loaded instead
+    Cached,
+    // Nothing was installed
+    Failed,
+}
+
+impl UpdateOutcome {
+    // Label used in metrics and traces
+    pub fn label(self) -> &'static str {
+        match self {
+            UpdateOutcome::Updated => "updated",
+            UpdateOutcome::Unchanged => "unchanged",
+            UpdateOutcome::Cached => "cached",
+            UpdateOutcome::Failed => "failed",
+        }
+    }
+}
+
+// Cumulative histogram over fixed bucket bounds
+struct Histogram {
+    bounds: &'static [f64],
+    // One per bound plus +Inf, not cumulative until rendered
+    buckets: Vec<AtomicU64>,
+    sum_nanos: AtomicU64,
+}
+
+impl Histogram {
+    fn new(bounds: &'static [f64]) -> Self {
+        Self {
+            bounds,
+            buckets: (0..=bounds.len()).map(|_| AtomicU64::new(0)).collect(),
+            sum_nanos: AtomicU64::new(0),
+        }
+    }
+
+    fn observe(&self, elapsed: Duration) {
+        let seconds = elapsed.as_secs_f64();
+        let bucket = self.bounds.iter().position(|&bound| seconds <= bound).unwrap_or(self.bounds.len());
+        self.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        self.sum_nanos
+            .fetch_add(elapsed.as_nanos().try_into().unwrap_or(u64::MAX), Ordering::Relaxed);
+    }
+
+    fn render(&self, out: &mut String, name: &str, help: &str) {
+        header(out, name, help, "histogram");
+        let mut count = 0;
+        for (bucket, bound) in self.buckets.iter().zip(self.bounds) {
+            count += bucket.load(Ordering::Relaxed);
+            let _ = writeln!(out, "{}_bucket{{le=\"{}\"}} {}", name, bound, count);
+        }
+        count += self.buckets[self.bounds.len()].load(Ordering::Relaxed);
+        let _ = writeln!(out, "{}_bucket{{le=\"+Inf\"}} {}", name, count);
+        let sum = self.sum_nanos.load(Ordering::Relaxed) as f64 / 1e9;
+        let _ = writeln!(out, "{}_sum {}", name, sum);
+        let _ = writeln!(out, "{}_count {}", name, count);
+    }
+}
+
+/**
+ * Counters and histograms for lookups, updates and parsing
+ *
+ * Everything is a relaxed atomic, so recording from the lookup path never
+ * takes a lock. `render` writes the Prometheus text exposition format.
+ */
+pub struct Metrics {
+    lookups: [AtomicU64; 3],
+    lookup_duration: Histogram,
+    updates: [AtomicU64; 4],
+    download_bytes: AtomicU64,
+    download_duration: Histogram,
+    parse_duration: Histogram,
+    records: [AtomicU64; 2],
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self {
+            lookups: Default::default(),
+            lookup_duration: Histogram::new(LOOKUP_BUCKETS),
+            updates: Default::default(),
+            download_bytes: AtomicU64::new(0),
+            download_duration: Histogram::new(DOWNLOAD_BUCKETS),
+            parse_duration: Histogram::new(PARSE_BUCKETS),
+            records: Default::default(),
+        }
+    }
+}
+
+const UPDATE_OUTCOMES: [UpdateOutcome; 4] = [
+    UpdateOutcome::Updated,
+    UpdateOutcome::Unchanged,
+    UpdateOutcome::Cached,
+    UpdateOutcome::Failed,
+];
+
+impl Metrics {
+    // Count a lookup; invalid input has no meaningful duration
+    pub fn record_lookup(&self, outcome: LookupOutcome, elapsed: Option<Duration>) {
+        self.lookups[outcome as usize].fetch_add(1, Ordering::Relaxed);
+        if let Some(elapsed) = elapsed {
+            self.lookup_duration.observe(elapsed);
+        }
+    }
+
+    // Count hits and misses from a batch lookup, which isn't timed per address
+    pub fn record_batch(&self, hits: u64, misses: u64) {
+        self.lookups[LookupOutcome::Hit as usize].fetch_add(hits, Ordering::Relaxed);
+        self.lookups[LookupOutcome::Miss as usize].fetch_add(misses, Ordering::Relaxed);
+    }
+
+    pub fn record_update(&self, outcome: UpdateOutcome) {
+        self.updates[outcome as usize].fetch_add(1, Ordering::Relaxed);
+    }
+
+    // A fetch that returned data; unchanged and failed fetches aren't downloads
+    pub fn record_download(&self, bytes: usize, elapsed: Duration) {
+        self.download_bytes.fetch_add(bytes as u64, Ordering::Relaxed);
+        self.download_duration.observe(elapsed);
+    }
+
+    pub fn record_parse(&self, elapsed: Duration) {
+        self.parse_duration.observe(elapsed);
+    }
+
+    // Record counts of the installed database
+    pub fn set_records(&self, ipv4: usize, ipv6: usize) {
+        self.records[0].store(ipv4 as u64, Ordering::Relaxed);
+        self.records[1].store(ipv6 as u64, Ordering::Relaxed);
+    }
+
+    // Lookups so far with the given outcome
+    pub fn lookups(&self, outcome: LookupOutcome) -> u64 {
+        self.lookups[outcome as usize].load(Ordering::Relaxed)
+    }
+
+    // Load and update attempts so far with the given outcome
+    pub fn updates(&self, outcome: UpdateOutcome) -> u64 {
+        self.updates[outcome as usize].load(Ordering::Relaxed)
+    }
+
+    // Append every metric in the Prometheus text format
+    pub fn render(&self, out: &mut String) {
+        header(out, "iptoasn_lookups_total", "Lookups by result", "counter");
+        for (result, counter) in ["hit", "miss", "invalid"].iter().zip(&self.lookups) {
+            let _ = writeln!(
+                out,
+                "iptoasn_lookups_total{{result=\"{}\"}} {}",
+                result,
+                counter.load(Ordering::Relaxed)
+            );
+        }
+        self.lookup_duration
+            .render(out, "iptoasn_lookup_duration_seconds", "Time to resolve a lookup");
+
+        header(out, "iptoasn_update_attempts_total", "Database loads and update checks by outcome", "counter");
+        for outcome in UPDATE_OUTCOMES {
+            let _ = writeln!(
+                out,
+                "iptoasn_update_attempts_total{{outcome=\"{}\"}} {}",
+                outcome.label(),
+                self.updates(outcome)
+            );
+        }
+
+        header(out, "iptoasn_download_bytes_total", "Bytes of database downloaded", "counter");
+        let _ = writeln!(out, "iptoasn_download_bytes_total {}", self.download_bytes.load(Ordering::Relaxed));
+        self.download_duration
+            .render(out, "iptoasn_download_duration_seconds", "Time to download the database");
+        self.parse_duration
+            .render(out, "iptoasn_parse_duration_seconds", "Time to decompress and parse the database");
+
+        header(out, "iptoasn_records", "Records in the installed database", "gauge");
+        for (family, gauge) in ["ipv4", "ipv6"].iter().zip(&self.records) {
+            let _ = writeln!(
+                out,
+                "iptoasn_records{{family=\"{}\"}} {}",
+                family,
+                gauge.load(Ordering::Relaxed)
+            );
+        }
+    }
+}
+
+// Write the HELP and TYPE lines of a metric family
+pub(crate) fn header(out: &mut String, name: &str, help: &str, kind: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_histogram_is_cumulative() {
+        let histogram = Histogram::new(&[0.1, 1.0]);
+        histogram.observe(Duration::from_millis(50));
+        histogram.observe(Duration::from_millis(500));
+        histogram.observe(Duration::from_secs(5));
+
+        let mut out = String::new();
+        histogram.render(&mut out, "test_seconds", "Test");
+        assert!(out.contains("test_seconds_bucket{le=\"0.1\"} 1\n"));
+        assert!(out.contains("test_seconds_bucket{le=\"1\"} 2\n"));
+        assert!(out.contains("test_seconds_bucket{le=\"+Inf\"} 3\n"));
+        assert!(out.contains("test_seconds_sum 5.55\n"));
+        assert!(out.contains("test_seconds_count 3\n"));
+    }
+
+    #[test]
+    fn test_render_counters() {
+        let metrics = Metrics::default();
+        metrics.record_lookup(LookupOutcome::Hit, Some(Duration::from_nanos(300)));
+        metrics.record_batch(2, 1);
+        metrics.record_lookup(LookupOutcome::Invalid, None);
+        metrics.record_update(UpdateOutcome::Cached);
+        metrics.record_download(1234, Duration::from_secs(2));
+
+        let mut out = String::new();
+        metrics.render(&mut out);
+        assert!(out.contains("iptoasn_lookups_total{result=\"hit\"} 3\n"));
+        assert!(out.contains("iptoasn_lookups_total{result=\"miss\"} 1\n"));
+        assert!(out.contains("iptoasn_lookups_total{result=\"invalid\"} 1\n"));
+        assert!(out.contains("iptoasn_lookup_duration_seconds_count 1\n"));
+        assert!(out.contains("iptoasn_update_attempts_total{outcome=\"cached\"} 1\n"));
+        assert!(out.contains("iptoasn_download_bytes_total 1234\n"));
+        assert!(out.contains("# TYPE iptoasn_records gauge\n"));
+    }
+}
@@ -0,0 +1,254 @@
+use crate::error::{AppError, Result};
+use crate::parser::{AsnRecord, Database};
+use crate::range::{next_addr, prev_addr, to_u128, IpRange};
+use serde::Deserialize;
+use std::net::IpAddr;
+use std::path::Path;
+use std::sync::Arc;
+
+/**
+ * Where a lookup result came from
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecordSource {
+    Upstream,
+    Override,
+}
+
+/**
+ * User-supplied records that take priority over upstream data
+ *
+ * Meant for internal ranges and corrections. When override ranges overlap,
+ * the narrowest one containing the address wins.
+ */
+#[derive(Debug, Clone, Default)]
+pub struct OverrideSet {
+    // Sorted by range
+    records: Vec<AsnRecord>,
+    // The records split where they overlap, so each address is in at most one
+    // segment pointing at the record that wins there; sorted
+    segments: Vec<(IpRange, usize)>,
+}
+
+// An entry of a JSON overrides file
+#[derive(Deserialize)]
+struct OverrideEntry {
+    // CIDR prefix, "first-last" range or single address
+    range: String,
+    as_number: u32,
+    #[serde(default)]
+    as_country_code: String,
+    #[serde(default)]
+    as_description: String,
+}
+
+impl OverrideSet {
+    // Create a set from records
+    pub fn new(mut records: Vec<AsnRecord>) -> Self {
+        records.sort_by_key(|r| (r.first_ip, r.last_ip));
+        let mut overrides = Self {
+            records,
+            segments: Vec::new(),
+        };
+        overrides.build_segments();
+        overrides
+    }
+
+    // Split the records into disjoint segments, painting wider ranges first so
+    // narrower ones end up on top; among equal widths the first range wins
+    fn build_segments(&mut self) {
+        let width = |r: &AsnRecord| to_u128(r.last_ip) - to_u128(r.first_ip);
+        let mut order: Vec<usize> = (0..self.records.len()).collect();
+        order.sort_by_key(|&index| std::cmp::Reverse((width(&self.records[index]), index)));
+
+        self.segments.clear();
+        for index in order {
+            let record = &self.records[index];
+            let range = IpRange {
+                first: record.first_ip,
+                last: record.last_ip,
+            };
+            let start = self.segments.partition_point(|(segment, _)| segment.last < range.first);
+            let end = self.segments.partition_point(|(segment, _)| segment.first <= range.last);
+
+            // Keep the parts of the covered segments that stick out of the range
+            let mut replacement = Vec::with_capacity(3);
+            if let Some(&(segment, winner)) = self.segments[start..end].first() {
+                if segment.first < range.first {
+                    replacement.push((IpRange { first: segment.first, last: prev_addr(range.first) }, winner));
+                }
+            }
+            replacement.push((range, index));
+            if let Some(&(segment, winner)) = self.segments[start..end].last() {
+                if range.last < segment.last {
+                    replacement.push((IpRange { first: next_addr(range.last), last: segment.last }, winner));
+                }
+            }
+            self.segments.splice(start..end, replacement);
+        }
+    }
+
+    /**
+     * Load overrides from a file
+     *
+     * Files ending in `.json` hold an array of
+     * `{ "range", "as_number", "as_country_code", "as_description" }` objects;
+     * anything else is read as iptoasn TSV (gzipped or plain).
+     */
+    pub fn load_file(path: &Path) -> Result<Self> {
+        let is_json = path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("json"));
+        if !is_json {
+            return Ok(Self::new(Database::load_file(path)?.records));
+        }
+
+        let entries: Vec<OverrideEntry> = serde_json::from_slice(&std::fs::read(path)?)
+            .map_err(|e| AppError::DatabaseParse(format!("{}: {}", path.display(), e)))?;
+        let records = entries
+            .into_iter()
+            .map(|entry| {
+                let range: IpRange = entry.range.parse()?;
+                Ok(override_record(
+                    range,
+                    entry.as_number,
+                    &entry.as_country_code,
+                    &entry.as_description,
+                ))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self::new(records))
+    }
+
+    // The override for an address, if any
+    pub fn lookup(&self, ip: IpAddr) -> Option<&AsnRecord> {
+        let index = self.segments.partition_point(|(segment, _)| segment.last < ip);
+        self.segments
+            .get(index)
+            .filter(|(segment, _)| segment.first <= ip)
+            .map(|&(_, winner)| &self.records[winner])
+    }
+
+    // Add a record, replacing any override for exactly the same range
+    pub fn insert(&mut self, record: AsnRecord) {
+        self.remove(&IpRange {
+            first: record.first_ip,
+            last: record.last_ip,
+        });
+        let index = self
+            .records
+            .partition_point(|r| (r.first_ip, r.last_ip) < (record.first_ip, record.last_ip));
+        self.records.insert(index, record);
+        self.build_segments();
+    }
+
+    // Remove the override for exactly this range; returns whether one existed
+    pub fn remove(&mut self, range: &IpRange) -> bool {
+        let before = self.records.len();
+        self.records
+            .retain(|r| r.first_ip != range.first || r.last_ip != range.last);
+        if self.records.len() == before {
+            return false;
+        }
+        self.build_segments();
+        true
+    }
+
+    // All overrides, sorted by range
+    pub fn records(&self) -> &[AsnRecord] {
+        &self.records
+    }
+
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+}
+
+// Build a record for an override range
+pub fn override_record(range: IpRange, number: u32, country: &str, description: &str) -> AsnRecord {
+    AsnRecord {
+        first_ip: range.first,
+        last_ip: range.last,
+        number,
+        country: Arc::from(country),
+        description: Arc::from(description),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(range: &str, number: u32) -> AsnRecord {
+        override_record(range.parse().unwrap(), number, "US", "CORP")
+    }
+
+    #[test]
+    fn test_narrowest_override_wins() {
+        let mut overrides = OverrideSet::new(vec![record("10.0.0.0/8", 64512), record("10.1.0.0/16", 64513)]);
+
+        let number = |overrides: &OverrideSet, ip: &str| overrides.lookup(ip.parse().unwrap()).map(|r| r.number);
+        assert_eq!(number(&overrides, "10.1.2.3"), Some(64513));
+        assert_eq!(number(&overrides, "10.2.0.1"), Some(64512));
+        assert_eq!(number(&overrides, "11.0.0.1"), None);
+
+        overrides.insert(record("10.1.0.0/16", 64514));
+        assert_eq!(overrides.len(), 2);
+        assert_eq!(number(&overrides, "10.1.2.3"), Some(64514));
+
+        assert!(overrides.remove(&"10.1.0.0/16".parse().unwrap()));
+        assert_eq!(number(&overrides, "10.1.2.3"), Some(64512));
+    }
+
+    #[test]
+    fn test_overlapping_overrides() {
+        let overrides = OverrideSet::new(vec![
+            record("10.0.0.0/8", 1),
+            record("10.0.1.0/24", 2),
+            record("10.0.1.128-10.0.2.127", 3),
+            record("10.0.1.64/26", 4),
+            record("10.0.2.0-10.0.2.255", 5),
+            record("2001:db8::/32", 6),
+        ]);
+
+        let number = |ip: &str| overrides.lookup(ip.parse().unwrap()).map(|r| r.number);
+        assert_eq!(number("10.0.0.255"), Some(1));
+        assert_eq!(number("10.0.1.0"), Some(2));
+        assert_eq!(number("10.0.1.64"), Some(4));
+        assert_eq!(number("10.0.1.127"), Some(4));
+        // Two /24-sized ranges overlap here; the first one wins
+        assert_eq!(number("10.0.1.200"), Some(2));
+        assert_eq!(number("10.0.2.0"), Some(3));
+        assert_eq!(number("10.0.2.128"), Some(5));
+        assert_eq!(number("10.0.3.0"), Some(1));
+        assert_eq!(number("11.0.0.0"), None);
+        assert_eq!(number("2001:db8::1"), Some(6));
+
+        // Segments stay sorted and disjoint
+        assert!(overrides.segments.windows(2).all(|pair| pair[0].0.last < pair[1].0.first));
+    }
+
+    #[test]
+    fn test_load_json() {
+        let dir = std::env::temp_dir().join(format!("iptoasn_test_overrides_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("overrides.json");
+        std::fs::write(
+            &path,
+            r#"[{ "range": "192.168.0.0/16", "as_number": 64512, "as_description": "Office" },
+                { "range": "203.0.113.0-203.0.113.127", "as_number": 64513 }]"#,
+        )
+        .unwrap();
+
+        let overrides = OverrideSet::load_file(&path).unwrap();
+        assert_eq!(overrides.len(), 2);
+        let office = overrides.lookup("192.168.1.1".parse().unwrap()).unwrap();
+        assert_eq!(&*office.description, "Office");
+        assert_eq!(&*office.country, "");
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
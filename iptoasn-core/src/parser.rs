@@ -3,8 +3,10 @@ use flate2::read::GzDecoder;
 use std::collections::HashMap;
 use std::io::Read;
 use std::net::IpAddr;
+use std::path::Path;
 use std::sync::Arc;
-use tracing::{info, warn};
+use std::time::Instant;
+use tracing::{field, info, info_span, warn};
 
 /**
  * Represents a single ASN record in the database
@@ -17,33 +19,163 @@ pub struct AsnRecord {
     pub country: Arc<str>,
     pub description: Arc<str>,
 }
+/**
+ * A line that could not be parsed
+ */
+#[derive(Debug, Clone)]
+pub struct ParseIssue {
+    pub line: usize,
+    pub message: String,
+}
+
+/**
+ * Summary of a parse run, for validating database files
+ */
+#[derive(Debug, Clone, Default)]
+pub struct ParseReport {
+    // Lines read, including empty ones
+    pub lines: usize,
+    pub records: usize,
+    pub ipv4_records: usize,
+    pub ipv6_records: usize,
+    pub unique_countries: usize,
+    pub unique_descriptions: usize,
+    // Records starting before the previous record ended
+    pub overlapping_ranges: usize,
+    // Lines that were skipped
+    pub issues: Vec<ParseIssue>,
+}
 /**
  * Database containing ASN records
  */
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub struct Database {
     pub records: Vec<AsnRecord>,
+    // Interned country codes, sorted and shared with the records
+    pub countries: Vec<Arc<str>>,
+    // Interned descriptions, sorted and shared with the records
+    pub descriptions: Vec<Arc<str>>,
+}
+
+impl ParseReport {
+    // Check the parsed data for signs of a broken upstream file
+    pub fn validate(&self) {
+        let span = info_span!(
+            "validate",
+            records = self.records,
+            ipv4_records = self.ipv4_records,
+            ipv6_records = self.ipv6_records,
+            skipped = self.issues.len(),
+            overlapping_ranges = self.overlapping_ranges,
+            duration_ms = field::Empty,
+        )
+        .entered();
+        let started = Instant::now();
+
+        if self.records == 0 {
+            warn!("Database contains no records");
+        }
+        if self.overlapping_ranges > 0 {
+            warn!("Database has {} overlapping ranges", self.overlapping_ranges);
+        }
+        span.record("duration_ms", started.elapsed().as_millis() as u64);
+    }
 }
 
 impl Database {
-    // Parse gzipped TSV data into a Database
+    // Create a database from records, collecting their string pools
+    pub fn new(records: Vec<AsnRecord>) -> Self {
+        let pool = |field: fn(&AsnRecord) -> &Arc<str>| {
+            let mut pool: Vec<Arc<str>> = records.iter().map(|r| field(r).clone()).collect();
+            pool.sort_unstable();
+            pool.dedup();
+            pool
+        };
+        let countries = pool(|r| &r.country);
+        let descriptions = pool(|r| &r.description);
+
+        Self {
+            records,
+            countries,
+            descriptions,
+        }
+    }
+
+    // Parse gzipped (or plain) TSV data into a Database
     pub fn parse(gzipped_data: Vec<u8>) -> Result<Self> {
         info!("Parsing database...");
 
-        // Decompress gzip
-        let mut decoder = GzDecoder::new(gzipped_data.as_slice());
-        let mut data = String::new();
-        decoder
-            .read_to_string(&mut data)
-            .map_err(|e| AppError::DatabaseParse(format!("Failed to decompress: {}", e)))?;
+        let span = info_span!(
+            "decompress",
+            compressed_bytes = gzipped_data.len(),
+            bytes = field::Empty,
+            duration_ms = field::Empty,
+        )
+        .entered();
+        let started = Instant::now();
+        let data = Self::decode(gzipped_data)?;
+        span.record("bytes", data.len());
+        span.record("duration_ms", started.elapsed().as_millis() as u64);
+        drop(span);
+
+        let span = info_span!(
+            "parse",
+            bytes = data.len(),
+            records = field::Empty,
+            skipped = field::Empty,
+            duration_ms = field::Empty,
+        )
+        .entered();
+        let started = Instant::now();
+        let (database, report) = Self::parse_tsv_with_report(&data)?;
+        span.record("records", report.records);
+        span.record("skipped", report.issues.len());
+        span.record("duration_ms", started.elapsed().as_millis() as u64);
+        drop(span);
+
+        report.validate();
+        Ok(database)
+    }
 
+    // Load a database file, either gzipped or plain TSV
+    pub fn load_file(path: &Path) -> Result<Self> {
+        Self::parse_tsv(&Self::decode(std::fs::read(path)?)?)
+    }
+
+    // Decode database file contents, decompressing if gzipped
+    pub fn decode(data: Vec<u8>) -> Result<String> {
+        // Gzip magic bytes
+        if data.starts_with(&[0x1f, 0x8b]) {
+            let mut decoder = GzDecoder::new(data.as_slice());
+            let mut text = String::new();
+            decoder
+                .read_to_string(&mut text)
+                .map_err(|e| AppError::Decompress(e.to_string()))?;
+            return Ok(text);
+        }
+
+        String::from_utf8(data)
+            .map_err(|e| AppError::DatabaseParse(format!("Invalid UTF-8: {}", e)))
+    }
+
+    // Parse uncompressed TSV data into a Database
+    pub fn parse_tsv(data: &str) -> Result<Self> {
+        Self::parse_tsv_with_report(data).map(|(database, _)| database)
+    }
+
+    // Parse uncompressed TSV data, also returning a report of what was read
+    pub fn parse_tsv_with_report(data: &str) -> Result<(Self, ParseReport)> {
         // String interning pools to deduplicate repeated strings
         let mut country_pool: HashMap<String, Arc<str>> = HashMap::new();
         let mut description_pool: HashMap<String, Arc<str>> = HashMap::new();
 
         let mut records = Vec::new();
         let mut line_count = 0;
-        let mut error_count = 0;
+        let mut issues = Vec::new();
+        let mut skip = |line: usize, message: String| {
+            warn!("Line {}: {}", line, message);
+            issues.push(ParseIssue { line, message });
+        };
 
         for line in data.lines() {
             line_count += 1;
@@ -57,8 +189,7 @@ impl Database {
             let parts: Vec<&str> = line.split('\t').collect();
 
             if parts.len() < 3 {
-                warn!("Line {}: not enough fields", line_count);
-                error_count += 1;
+                skip(line_count, "not enough fields".to_string());
                 continue;
             }
 
@@ -66,8 +197,7 @@ impl Database {
             let first_ip = match parts[0].parse::<IpAddr>() {
                 Ok(ip) => ip,
                 Err(_) => {
-                    warn!("Line {}: invalid first IP: {}", line_count, parts[0]);
-                    error_count += 1;
+                    skip(line_count, format!("invalid first IP: {}", parts[0]));
                     continue;
                 }
             };
@@ -76,8 +206,7 @@ impl Database {
             let last_ip = match parts[1].parse::<IpAddr>() {
                 Ok(ip) => ip,
                 Err(_) => {
-                    warn!("Line {}: invalid last IP: {}", line_count, parts[1]);
-                    error_count += 1;
+                    skip(line_count, format!("invalid last IP: {}", parts[1]));
                     continue;
                 }
             };
@@ -86,8 +215,7 @@ impl Database {
             let number = match parts[2].parse::<u32>() {
                 Ok(num) => num,
                 Err(_) => {
-                    warn!("Line {}: invalid ASN number: {}", line_count, parts[2]);
-                    error_count += 1;
+                    skip(line_count, format!("invalid ASN number: {}", parts[2]));
                     continue;
                 }
             };
@@ -115,8 +243,8 @@ impl Database {
             });
         }
 
-        // Sort records by first_ip for efficient binary search later
-        records.sort_by(|a, b| a.first_ip.cmp(&b.first_ip));
+        // Sort records by range for efficient binary search and diffing later
+        records.sort_by_key(|r| (r.first_ip, r.last_ip));
 
         info!(
             "Database parsed: {} records ({} unique countries, {} unique descriptions)",
@@ -125,11 +253,37 @@ impl Database {
             description_pool.len()
         );
 
-        if error_count > 0 {
-            warn!("Encountered {} parse errors", error_count);
+        if !issues.is_empty() {
+            warn!("Encountered {} parse errors", issues.len());
         }
 
-        Ok(Self { records })
+        let report = ParseReport {
+            lines: line_count,
+            records: records.len(),
+            ipv4_records: records.iter().filter(|r| r.first_ip.is_ipv4()).count(),
+            ipv6_records: records.iter().filter(|r| r.first_ip.is_ipv6()).count(),
+            unique_countries: country_pool.len(),
+            unique_descriptions: description_pool.len(),
+            overlapping_ranges: records
+                .windows(2)
+                .filter(|pair| pair[1].first_ip <= pair[0].last_ip)
+                .count(),
+            issues,
+        };
+
+        let mut countries: Vec<Arc<str>> = country_pool.into_values().collect();
+        countries.sort_unstable();
+        let mut descriptions: Vec<Arc<str>> = description_pool.into_values().collect();
+        descriptions.sort_unstable();
+
+        Ok((
+            Self {
+                records,
+                countries,
+                descriptions,
+            },
+            report,
+        ))
     }
 
     // Get total number of records
@@ -142,3 +296,39 @@ impl Database {
         self.records.is_empty()
     }
 }
+
+/**
+ * Parse an AS number written as "15169", "AS15169" or "as15169"
+ */
+pub fn parse_asn(value: &str) -> Option<u32> {
+    let digits = value
+        .strip_prefix("AS")
+        .or_else(|| value.strip_prefix("as"))
+        .unwrap_or(value);
+    digits.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_report() {
+        let data = "8.8.8.0\t8.8.8.255\t15169\tUS\tGOOGLE\n\
+                    \n\
+                    not-an-ip\t8.8.4.255\t15169\tUS\tGOOGLE\n\
+                    8.8.8.128\t8.8.9.255\t15169\tUS\tGOOGLE\n\
+                    2001:4860::\t2001:4860:ffff::\t15169\tUS\tGOOGLE\n";
+
+        let (database, report) = Database::parse_tsv_with_report(data).unwrap();
+
+        assert_eq!(database.len(), 3);
+        assert_eq!(report.lines, 5);
+        assert_eq!(report.ipv4_records, 2);
+        assert_eq!(report.ipv6_records, 1);
+        assert_eq!(report.unique_descriptions, 1);
+        assert_eq!(report.overlapping_ranges, 1);
+        assert_eq!(report.issues.len(), 1);
+        assert_eq!(report.issues[0].line, 3);
+    }
+}
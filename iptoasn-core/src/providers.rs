@@ -0,0 +1,420 @@
+use crate::error::{AppError, Result};
+use crate::range::{to_u128, IpRange};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Arc;
+
+/**
+ * Cloud or hosting provider a range is published by
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CloudInfo {
+    pub provider: Arc<str>,
+    // e.g. "EC2", "Google Cloud" or "AzureStorage"
+    pub service: Option<Arc<str>>,
+    // e.g. "us-east-1", "europe-west1" or "eastus"
+    pub region: Option<Arc<str>>,
+}
+
+/**
+ * Published range list formats
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProviderFormat {
+    // https://ip-ranges.amazonaws.com/ip-ranges.json
+    Aws,
+    // https://www.gstatic.com/ipranges/cloud.json (or goog.json)
+    Gcp,
+    // Azure Service Tags (ServiceTags_Public_*.json)
+    Azure,
+    // [{ "range", "provider", "service", "region" }]
+    Generic,
+}
+
+impl FromStr for ProviderFormat {
+    type Err = AppError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "aws" => Ok(Self::Aws),
+            "gcp" | "google" => Ok(Self::Gcp),
+            "azure" => Ok(Self::Azure),
+            "generic" => Ok(Self::Generic),
+            _ => Err(AppError::DatabaseParse(format!("Unknown provider format: {}", s))),
+        }
+    }
+}
+
+impl ProviderFormat {
+    // Recognize a format from the document's shape
+    pub fn detect(document: &Value) -> Option<Self> {
+        if document.is_array() {
+            return Some(Self::Generic);
+        }
+        if document.get("values").is_some() {
+            return Some(Self::Azure);
+        }
+        let first = document.get("prefixes")?.get(0)?;
+        if first.get("ip_prefix").is_some() {
+            Some(Self::Aws)
+        } else if first.get("ipv4Prefix").is_some() || first.get("ipv6Prefix").is_some() {
+            Some(Self::Gcp)
+        } else {
+            None
+        }
+    }
+}
+
+/**
+ * Provider ranges, indexed by prefix length for longest-prefix matching
+ *
+ * When several lists publish the same prefix, the entry naming a specific
+ * service wins over catch-all entries such as AWS's "AMAZON" or Azure's
+ * regional "AzureCloud" tags.
+ */
+#[derive(Debug, Clone, Default)]
+pub struct ProviderSet {
+    entries: Vec<(CloudInfo, Specificity)>,
+    // Per family (IPv4, IPv6): prefix length and network -> entry, longest first
+    prefixes: [Vec<(u32, HashMap<u128, usize>)>; 2],
+    ranges: usize,
+    // Prefixes left out for being longer than their address family allows
+    skipped: usize,
+}
+
+// How precisely an entry describes its range; higher wins on the same prefix
+type Specificity = u8;
+const CATCH_ALL: Specificity = 0;
+const REGIONAL: Specificity = 1;
+const SERVICE: Specificity = 2;
+
+impl ProviderSet {
+    /**
+     * Load a provider file, detecting its format unless one is given
+     * @returns Number of prefixes read
+     */
+    pub fn load_file(&mut self, path: &Path, format: Option<ProviderFormat>) -> Result<usize> {
+        let invalid = |message: String| AppError::DatabaseParse(format!("{}: {}", path.display(), message));
+
+        let document: Value =
+            serde_json::from_slice(&std::fs::read(path)?).map_err(|e| invalid(e.to_string()))?;
+        let format = format
+            .or_else(|| ProviderFormat::detect(&document))
+            .ok_or_else(|| invalid("unrecognized provider range format".to_string()))?;
+
+        let before = self.ranges;
+        match format {
+            ProviderFormat::Aws => self.add_aws(&document)?,
+            ProviderFormat::Gcp => self.add_gcp(&document)?,
+            ProviderFormat::Azure => self.add_azure(&document)?,
+            ProviderFormat::Generic => self.add_generic(&document)?,
+        }
+        Ok(self.ranges - before)
+    }
+
+    fn add_aws(&mut self, document: &Value) -> Result<()> {
+        for (list, key) in [("prefixes", "ip_prefix"), ("ipv6_prefixes", "ipv6_prefix")] {
+            for entry in items(document, list) {
+                let Some(prefix) = text(entry, key) else { continue };
+                let service = text(entry, "service");
+                let specificity = if service == Some("AMAZON") { CATCH_ALL } else { SERVICE };
+                self.insert(prefix, "AWS", service, text(entry, "region"), specificity)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn add_gcp(&mut self, document: &Value) -> Result<()> {
+        for entry in items(document, "prefixes") {
+            let Some(prefix) = text(entry, "ipv4Prefix").or_else(|| text(entry, "ipv6Prefix")) else {
+                continue;
+            };
+            // goog.json lists Google-wide space without service or scope
+            let service = text(entry, "service");
+            let (provider, specificity) = match service {
+                Some(_) => ("GCP", SERVICE),
+                None => ("Google", CATCH_ALL),
+            };
+            self.insert(prefix, provider, service, text(entry, "scope"), specificity)?;
+        }
+        Ok(())
+    }
+
+    fn add_azure(&mut self, document: &Value) -> Result<()> {
+        for tag in items(document, "values") {
+            let properties = &tag["properties"];
+            let service = text(properties, "systemService");
+            let region = text(properties, "region");
+            let specificity = match (service, region) {
+                (Some(_), _) => SERVICE,
+                (None, Some(_)) => REGIONAL,
+                (None, None) => CATCH_ALL,
+            };
+            for prefix in items(properties, "addressPrefixes").filter_map(Value::as_str) {
+                self.insert(prefix, "Azure", service, region, specificity)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn add_generic(&mut self, document: &Value) -> Result<()> {
+        for entry in document.as_array().into_iter().flatten() {
+            let (Some(range), Some(provider)) = (text(entry, "range"), text(entry, "provider")) else {
+                return Err(AppError::DatabaseParse(format!(
+                    "provider entry needs \"range\" and \"provider\": {}",
+                    entry
+                )));
+            };
+            let range: IpRange = range.parse()?;
+            for prefix in cidrs(&range) {
+                self.insert_prefix(
+                    prefix,
+                    provider,
+                    text(entry, "service"),
+                    text(entry, "region"),
+                    SERVICE,
+                );
+            }
+        }
+        Ok(())
+    }
+
+    fn insert(
+        &mut self,
+        prefix: &str,
+        provider: &str,
+        service: Option<&str>,
+        region: Option<&str>,
+        specificity: Specificity,
+    ) -> Result<()> {
+        let (addr, len) = prefix
+            .split_once('/')
+            .and_then(|(addr, len)| Some((addr.parse::<IpAddr>().ok()?, len.parse::<u32>().ok()?)))
+            .ok_or_else(|| AppError::InvalidRange(prefix.to_string()))?;
+        if len > if addr.is_ipv4() { 32 } else { 128 } {
+            self.skipped += 1;
+            return Ok(());
+        }
+        self.insert_prefix((addr, len), provider, service, region, specificity);
+        Ok(())
+    }
+
+    fn insert_prefix(
+        &mut self,
+        (addr, len): (IpAddr, u32),
+        provider: &str,
+        service: Option<&str>,
+        region: Option<&str>,
+        specificity: Specificity,
+    ) {
+        let info = CloudInfo {
+            provider: Arc::from(provider),
+            service: service.map(Arc::from),
+            region: region.map(Arc::from),
+        };
+        let (family, bits) = if addr.is_ipv4() { (0, 32) } else { (1, 128) };
+
+        let lengths = &mut self.prefixes[family];
+        let index = match lengths.binary_search_by(|(l, _)| len.cmp(l)) {
+            Ok(index) => index,
+            Err(index) => {
+                lengths.insert(index, (len, HashMap::new()));
+                index
+            }
+        };
+
+        let network = network(to_u128(addr), len, bits);
+        match lengths[index].1.get(&network) {
+            Some(&existing) if self.entries[existing].1 >= specificity => {}
+            Some(&existing) => self.entries[existing] = (info, specificity),
+            None => {
+                lengths[index].1.insert(network, self.entries.len());
+                self.entries.push((info, specificity));
+                self.ranges += 1;
+            }
+        }
+    }
+
+    // The provider of the longest published prefix containing an address
+    pub fn lookup(&self, ip: IpAddr) -> Option<&CloudInfo> {
+        let (family, bits) = if ip.is_ipv4() { (0, 32) } else { (1, 128) };
+        let value = to_u128(ip);
+
+        self.prefixes[family]
+            .iter()
+            .find_map(|(len, networks)| networks.get(&network(value, *len, bits)))
+            .map(|&index| &self.entries[index].0)
+    }
+
+    // Number of distinct prefixes
+    pub fn len(&self) -> usize {
+        self.ranges
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ranges == 0
+    }
+
+    // Number of prefixes skipped for an impossible length, e.g. a /33
+    pub fn skipped(&self) -> usize {
+        self.skipped
+    }
+}
+
+// Clear the host bits of an address
+fn network(value: u128, len: u32, bits: u32) -> u128 {
+    let host_mask = u128::MAX.checked_shr(len + 128 - bits).unwrap_or(0);
+    value & !host_mask
+}
+
+// Split a range into the CIDR prefixes covering it exactly
+fn cidrs(range: &IpRange) -> Vec<(IpAddr, u32)> {
+    let ipv4 = range.first.is_ipv4();
+    let bits = if ipv4 { 32 } else { 128 };
+    let (mut first, last) = (to_u128(range.first), to_u128(range.last));
+
+    let mut prefixes = Vec::new();
+    loop {
+        // Largest block aligned at `first` that doesn't pass `last`
+        let mut size = first.trailing_zeros().min(bits);
+        while size > 0 && first + block_mask(size) > last {
+            size -= 1;
+        }
+        let addr: IpAddr = if ipv4 {
+            std::net::Ipv4Addr::from(first as u32).into()
+        } else {
+            std::net::Ipv6Addr::from(first).into()
+        };
+        prefixes.push((addr, bits - size));
+
+        let end = first + block_mask(size);
+        if end >= last {
+            return prefixes;
+        }
+        first = end + 1;
+    }
+}
+
+// Offset of the last address in a block of 2^size addresses
+fn block_mask(size: u32) -> u128 {
+    u128::MAX.checked_shr(128 - size).unwrap_or(0)
+}
+
+fn items<'a>(value: &'a Value, key: &str) -> impl Iterator<Item = &'a Value> {
+    value.get(key).and_then(Value::as_array).into_iter().flatten()
+}
+
+// A non-empty string field
+fn text<'a>(value: &'a Value, key: &str) -> Option<&'a str> {
+    value.get(key).and_then(Value::as_str).filter(|s| !s.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A fresh directory for one test, unique to this process
+    fn test_dir(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("iptoasn_test_providers_{}_{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn load(dir: &Path, name: &str, contents: &str) -> ProviderSet {
+        let path = dir.join(name);
+        std::fs::write(&path, contents).unwrap();
+        let mut set = ProviderSet::default();
+        set.load_file(&path, None).unwrap();
+        set
+    }
+
+    fn describe(set: &ProviderSet, ip: &str) -> Option<(String, Option<String>, Option<String>)> {
+        set.lookup(ip.parse().unwrap()).map(|info| {
+            (
+                info.provider.to_string(),
+                info.service.as_deref().map(str::to_string),
+                info.region.as_deref().map(str::to_string),
+            )
+        })
+    }
+
+    #[test]
+    fn test_aws_prefers_specific_service() {
+        let dir = test_dir("aws");
+        let set = load(
+            &dir,
+            "aws.json",
+            r#"{ "prefixes": [
+                  { "ip_prefix": "3.5.0.0/16", "region": "us-east-1", "service": "AMAZON" },
+                  { "ip_prefix": "3.5.140.0/22", "region": "ap-northeast-2", "service": "AMAZON" },
+                  { "ip_prefix": "3.5.140.0/22", "region": "ap-northeast-2", "service": "S3" },
+                  { "ip_prefix": "3.6.0.0/33", "region": "us-east-1", "service": "EC2" }
+                ],
+                "ipv6_prefixes": [
+                  { "ipv6_prefix": "2600:1f00::/24", "region": "GLOBAL", "service": "EC2" },
+                  { "ipv6_prefix": "2600:1f01::/129", "region": "GLOBAL", "service": "EC2" }
+                ] }"#,
+        );
+
+        assert_eq!((set.len(), set.skipped()), (3, 2));
+        let s3 = describe(&set, "3.5.141.1").unwrap();
+        assert_eq!(s3.1.as_deref(), Some("S3"));
+        assert_eq!(s3.2.as_deref(), Some("ap-northeast-2"));
+        assert_eq!(describe(&set, "3.5.1.1").unwrap().1.as_deref(), Some("AMAZON"));
+        assert_eq!(describe(&set, "2600:1f00::1").unwrap().0, "AWS");
+        assert!(describe(&set, "3.6.0.1").is_none());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_gcp_azure_and_generic() {
+        let dir = test_dir("gcp_azure_generic");
+        let mut set = load(
+            &dir,
+            "gcp.json",
+            r#"{ "prefixes": [{ "ipv4Prefix": "34.80.0.0/15", "service": "Google Cloud", "scope": "asia-east1" }] }"#,
+        );
+        set.load_file(
+            &{
+                let path = dir.join("azure.json");
+                std::fs::write(
+                    &path,
+                    r#"{ "values": [
+                          { "name": "AzureCloud.eastus", "properties": { "region": "eastus", "systemService": "", "addressPrefixes": ["20.42.0.0/16"] } },
+                          { "name": "Storage.EastUS", "properties": { "region": "eastus", "systemService": "AzureStorage", "addressPrefixes": ["20.42.0.0/16"] } }
+                        ] }"#,
+                )
+                .unwrap();
+                path
+            },
+            None,
+        )
+        .unwrap();
+
+        let gcp = describe(&set, "34.81.0.1").unwrap();
+        assert_eq!((gcp.0.as_str(), gcp.2.as_deref()), ("GCP", Some("asia-east1")));
+        assert_eq!(describe(&set, "20.42.1.1").unwrap().1.as_deref(), Some("AzureStorage"));
+
+        let generic = load(
+            &dir,
+            "generic.json",
+            r#"[{ "range": "192.0.2.10-192.0.2.20", "provider": "Example Hosting" }]"#,
+        );
+        assert_eq!(generic.len(), 4);
+        assert!(describe(&generic, "192.0.2.9").is_none());
+        assert_eq!(describe(&generic, "192.0.2.20").unwrap().0, "Example Hosting");
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_cidrs() {
+        let range: IpRange = "0.0.0.0-255.255.255.255".parse().unwrap();
+        assert_eq!(cidrs(&range), vec![("0.0.0.0".parse().unwrap(), 0)]);
+        let range: IpRange = "::/0".parse().unwrap();
+        assert_eq!(cidrs(&range), vec![("::".parse().unwrap(), 0)]);
+    }
+}
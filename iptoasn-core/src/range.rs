@@ -0,0 +1,237 @@
+use crate::error::{AppError, Result};
+use crate::parser::AsnRecord;
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::str::FromStr;
+
+/**
+ * An inclusive range of addresses of one family
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IpRange {
+    pub first: IpAddr,
+    pub last: IpAddr,
+}
+
+impl IpRange {
+    // Create a range, checking both ends are the same family and in order
+    pub fn new(first: IpAddr, last: IpAddr) -> Result<Self> {
+        if first.is_ipv4() != last.is_ipv4() {
+            return Err(AppError::InvalidRange(format!(
+                "{} and {} are different address families",
+                first, last
+            )));
+        }
+        if first > last {
+            return Err(AppError::InvalidRange(format!("{} is after {}", first, last)));
+        }
+        Ok(Self { first, last })
+    }
+
+    // Whether the range shares at least one address with a record
+    pub fn overlaps(&self, record: &AsnRecord) -> bool {
+        record.first_ip <= self.last && record.last_ip >= self.first
+    }
+
+    // Number of addresses in the range, as a float so a whole IPv6 /0 fits
+    pub fn size(&self) -> f64 {
+        (to_u128(self.last) - to_u128(self.first)) as f64 + 1.0
+    }
+
+    // The addresses shared with another range, if any
+    pub fn intersect(&self, other: &IpRange) -> Option<IpRange> {
+        let first = self.first.max(other.first);
+        let last = self.last.min(other.last);
+        (first <= last && first.is_ipv4() == last.is_ipv4()).then_some(IpRange { first, last })
+    }
+}
+
+/**
+ * Parse "203.0.113.0/22", "10.0.0.0-10.255.255.255" or a single address
+ */
+impl FromStr for IpRange {
+    type Err = AppError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+        let invalid = || AppError::InvalidRange(s.to_string());
+
+        if let Some((addr, len)) = s.split_once('/') {
+            let addr: IpAddr = addr.trim().parse().map_err(|_| invalid())?;
+            let len: u32 = len.trim().parse().map_err(|_| invalid())?;
+            let bits = if addr.is_ipv4() { 32 } else { 128 };
+            if len > bits {
+                return Err(invalid());
+            }
+
+            // Host bits are ignored, so 203.0.113.7/22 means 203.0.112.0/22
+            let host_mask = u128::MAX.checked_shr(len + 128 - bits).unwrap_or(0);
+            let value = to_u128(addr);
+            return Ok(Self {
+                first: from_u128(value & !host_mask, addr.is_ipv4()),
+                last: from_u128(value | host_mask, addr.is_ipv4()),
+            });
+        }
+
+        if let Some((first, last)) = s.split_once('-') {
+            let first = first.trim().parse().map_err(|_| invalid())?;
+            let last = last.trim().parse().map_err(|_| invalid())?;
+            return Self::new(first, last);
+        }
+
+        let addr: IpAddr = s.parse().map_err(|_| invalid())?;
+        Ok(Self { first: addr, last: addr })
+    }
+}
+
+impl fmt::Display for IpRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-{}", self.first, self.last)
+    }
+}
+
+/**
+ * A record overlapping a queried range
+ */
+#[derive(Debug, Clone)]
+pub struct RangeMatch {
+    pub record: AsnRecord,
+    // Addresses shared by the record and the query
+    pub overlap: IpRange,
+    // Percentage of the queried range covered by this record
+    pub coverage: f64,
+    // Percentage of the record that lies inside the queried range
+    pub record_coverage: f64,
+}
+
+/**
+ * Every record overlapping a queried range
+ */
+#[derive(Debug, Clone)]
+pub struct RangeLookup {
+    pub range: IpRange,
+    // Overlapping records, sorted by range
+    pub matches: Vec<RangeMatch>,
+    // Percentage of the queried range covered by any record
+    pub coverage: f64,
+}
+
+impl RangeLookup {
+    // Compute coverage for the records overlapping a range
+    pub fn new<'a>(range: IpRange, records: impl IntoIterator<Item = &'a AsnRecord>) -> Self {
+        let size = range.size();
+        let mut covered = 0.0;
+        // End of the covered prefix, so overlapping records aren't counted twice
+        let mut covered_to: Option<u128> = None;
+
+        let matches = records
+            .into_iter()
+            .filter_map(|record| {
+                let record_range = IpRange {
+                    first: record.first_ip,
+                    last: record.last_ip,
+                };
+                let overlap = range.intersect(&record_range)?;
+
+                let first = to_u128(overlap.first);
+                let last = to_u128(overlap.last);
+                let new_first = covered_to.map_or(first, |end| first.max(end.saturating_add(1)));
+                if covered_to.is_none_or(|end| end < last) {
+                    covered += (last - new_first) as f64 + 1.0;
+                    covered_to = Some(last);
+                }
+
+                Some(RangeMatch {
+                    record: record.clone(),
+                    overlap,
+                    coverage: percent(overlap.size(), size),
+                    record_coverage: percent(overlap.size(), record_range.size()),
+                })
+            })
+            .collect();
+
+        Self {
+            range,
+            matches,
+            coverage: percent(covered, size),
+        }
+    }
+}
+
+fn percent(part: f64, whole: f64) -> f64 {
+    (part / whole * 100.0).min(100.0)
+}
+
+pub(crate) fn to_u128(ip: IpAddr) -> u128 {
+    match ip {
+        IpAddr::V4(v4) => u32::from(v4) as u128,
+        IpAddr::V6(v6) => u128::from(v6),
+    }
+}
+
+pub(crate) fn from_u128(value: u128, ipv4: bool) -> IpAddr {
+    if ipv4 {
+        IpAddr::V4(Ipv4Addr::from(value as u32))
+    } else {
+        IpAddr::V6(Ipv6Addr::from(value))
+    }
+}
+
+// The neighbouring addresses; callers never step past the ends of a family
+pub(crate) fn next_addr(ip: IpAddr) -> IpAddr {
+    from_u128(to_u128(ip) + 1, ip.is_ipv4())
+}
+
+pub(crate) fn prev_addr(ip: IpAddr) -> IpAddr {
+    from_u128(to_u128(ip) - 1, ip.is_ipv4())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    fn record(first: &str, last: &str, number: u32) -> AsnRecord {
+        AsnRecord {
+            first_ip: first.parse().unwrap(),
+            last_ip: last.parse().unwrap(),
+            number,
+            country: Arc::from("US"),
+            description: Arc::from("TEST"),
+        }
+    }
+
+    #[test]
+    fn test_parse_ranges() {
+        let cidr: IpRange = "203.0.113.7/22".parse().unwrap();
+        assert_eq!(cidr.to_string(), "203.0.112.0-203.0.115.255");
+
+        let v6: IpRange = "2001:db8::/32".parse().unwrap();
+        assert_eq!(v6.last, "2001:db8:ffff:ffff:ffff:ffff:ffff:ffff".parse::<IpAddr>().unwrap());
+
+        let all: IpRange = "::/0".parse().unwrap();
+        assert_eq!(all.size(), 2f64.powi(128));
+
+        assert!("10.0.0.0 - 10.255.255.255".parse::<IpRange>().is_ok());
+        assert!("10.0.0.0/33".parse::<IpRange>().is_err());
+        assert!("10.0.0.9-10.0.0.1".parse::<IpRange>().is_err());
+        assert!("10.0.0.0-::1".parse::<IpRange>().is_err());
+    }
+
+    #[test]
+    fn test_coverage() {
+        let records = [
+            record("10.0.0.0", "10.0.0.255", 1),
+            record("10.0.1.0", "10.0.1.127", 2),
+            record("10.0.3.0", "10.0.7.255", 3),
+        ];
+        let range: IpRange = "10.0.0.128/22".parse().unwrap();
+        let lookup = RangeLookup::new(range, records.iter().filter(|r| range.overlaps(r)));
+
+        assert_eq!(lookup.matches.len(), 3);
+        assert_eq!(lookup.matches[0].coverage, 25.0);
+        assert_eq!(lookup.matches[1].coverage, 12.5);
+        assert_eq!(lookup.matches[2].record_coverage, 20.0);
+        assert_eq!(lookup.coverage, 62.5);
+    }
+}
@@ -1,24 +1,63 @@
+use crate::aggregate::Aggregates;
+use crate::catalog::{self, AsnCatalog};
+use crate::overrides::{OverrideSet, RecordSource};
 use crate::parser::{AsnRecord, Database};
+use crate::range::IpRange;
 use std::net::IpAddr;
-use std::sync::Arc;
+use std::sync::{Arc, OnceLock};
 use tracing::debug;
 
 // Thread-safe store for ASN lookups
 #[derive(Debug, Clone)]
 pub struct AsnStore {
     records: Arc<Vec<AsnRecord>>,
+    catalog: Arc<AsnCatalog>,
+    // Computed on first use, shared by clones of the store
+    aggregates: Arc<OnceLock<Arc<Aggregates>>>,
+    // Running maximum of last_ip for range queries, computed on first use
+    reach: Arc<OnceLock<Vec<IpAddr>>>,
+    // User-supplied records checked before the upstream ones
+    overrides: Arc<OverrideSet>,
 }
 
 impl AsnStore {
     // Create a new store from a parsed database
     pub fn new(database: Database) -> Self {
         Self {
+            catalog: Arc::new(AsnCatalog::new(&database)),
             records: Arc::new(database.records),
+            aggregates: Arc::new(OnceLock::new()),
+            reach: Arc::new(OnceLock::new()),
+            overrides: Arc::new(OverrideSet::default()),
         }
     }
 
-    // Look up an IP address and return the associated ASN record
+    // Use a set of overrides for lookups
+    pub fn with_overrides(mut self, overrides: Arc<OverrideSet>) -> Self {
+        self.overrides = overrides;
+        self
+    }
+
+    // Get the overrides used for lookups
+    pub fn overrides(&self) -> &Arc<OverrideSet> {
+        &self.overrides
+    }
+
+    // Look up an IP address, preferring overrides over upstream records
     pub fn lookup(&self, ip: IpAddr) -> Option<&AsnRecord> {
+        self.lookup_with_source(ip).map(|(record, _)| record)
+    }
+
+    // Look up an IP address and report whether the record is an override
+    pub fn lookup_with_source(&self, ip: IpAddr) -> Option<(&AsnRecord, RecordSource)> {
+        if let Some(record) = self.overrides.lookup(ip) {
+            return Some((record, RecordSource::Override));
+        }
+        self.lookup_upstream(ip).map(|record| (record, RecordSource::Upstream))
+    }
+
+    // Look up an IP address in the upstream records only
+    pub fn lookup_upstream(&self, ip: IpAddr) -> Option<&AsnRecord> {
         // Binary search to find the record where first_ip <= target_ip
         // We're looking for the largest first_ip that is <= our target
 
@@ -46,6 +85,61 @@ impl AsnStore {
         }
     }
 
+    // Get every record overlapping a range, partially or fully
+    pub fn lookup_range<'a>(&'a self, range: &'a IpRange) -> impl Iterator<Item = &'a AsnRecord> {
+        // A wide record can cover later, narrower ones, so last_ip isn't sorted;
+        // search the running maximum instead and skip records that end early
+        let reach = self.reach();
+        let start = reach.partition_point(|&last_ip| last_ip < range.first);
+        let end = self.records.partition_point(|record| record.first_ip <= range.last);
+        self.records[start..end.max(start)]
+            .iter()
+            .filter(move |record| range.overlaps(record))
+    }
+
+    // Get the highest last_ip of each record and the ones before it
+    fn reach(&self) -> &[IpAddr] {
+        self.reach.get_or_init(|| {
+            self.records
+                .iter()
+                .scan(None, |max: &mut Option<IpAddr>, record| {
+                    let last_ip = max.map_or(record.last_ip, |max| max.max(record.last_ip));
+                    *max = Some(last_ip);
+                    Some(last_ip)
+                })
+                .collect()
+        })
+    }
+
+    // Get the ranges announced by an ASN
+    pub fn records_for_asn(&self, number: u32) -> impl Iterator<Item = &AsnRecord> {
+        self.records.iter().filter(move |record| record.number == number)
+    }
+
+    // Get the ranges registered in a country (case-insensitive)
+    pub fn records_in_country<'a>(&'a self, country: &'a str) -> impl Iterator<Item = &'a AsnRecord> {
+        self.records
+            .iter()
+            .filter(move |record| catalog::in_country(record, country))
+    }
+
+    // Get the ASN catalogue
+    pub fn catalog(&self) -> &AsnCatalog {
+        &self.catalog
+    }
+
+    // Get address space totals, computing them on first use
+    pub fn aggregates(&self) -> Arc<Aggregates> {
+        self.aggregates
+            .get_or_init(|| Arc::new(Aggregates::new(&self.records)))
+            .clone()
+    }
+
+    // Get all records, sorted by range
+    pub fn records(&self) -> &[AsnRecord] {
+        &self.records
+    }
+
     // Get the number of records in the store
     pub fn len(&self) -> usize {
         self.records.len()
@@ -60,7 +154,7 @@ impl AsnStore {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::database::parser::AsnRecord;
+    use crate::parser::AsnRecord;
     use std::sync::Arc;
 
     #[test]
@@ -73,7 +167,7 @@ mod tests {
             description: Arc::from("GOOGLE"),
         }];
 
-        let db = Database { records };
+        let db = Database::new(records);
         let store = AsnStore::new(db);
 
         let result = store.lookup("8.8.8.8".parse().unwrap());
@@ -91,10 +185,51 @@ mod tests {
             description: Arc::from("GOOGLE"),
         }];
 
-        let db = Database { records };
+        let db = Database::new(records);
         let store = AsnStore::new(db);
 
         let result = store.lookup("9.9.9.9".parse().unwrap());
         assert!(result.is_none());
     }
+
+    #[test]
+    fn test_lookup_range() {
+        let record = |first: &str, last: &str, number| AsnRecord {
+            first_ip: first.parse().unwrap(),
+            last_ip: last.parse().unwrap(),
+            number,
+            country: Arc::from("US"),
+            description: Arc::from("TEST"),
+        };
+        let store = AsnStore::new(Database::new(vec![
+            record("10.0.0.0", "10.0.0.255", 1),
+            record("10.0.2.0", "10.0.2.255", 2),
+            record("10.1.0.0", "10.1.0.255", 3),
+            record("2001:db8::", "2001:db8::ffff", 4),
+        ]));
+
+        let numbers = |range: &str| -> Vec<u32> {
+            let range: IpRange = range.parse().unwrap();
+            store.lookup_range(&range).map(|r| r.number).collect()
+        };
+        assert_eq!(numbers("10.0.0.128/23"), vec![1]);
+        assert_eq!(numbers("10.0.0.0/8"), vec![1, 2, 3]);
+        assert_eq!(numbers("10.0.1.0/24"), Vec::<u32>::new());
+        assert_eq!(numbers("2001:db8::/32"), vec![4]);
+
+        // A wide record followed by a narrower one inside it
+        let store = AsnStore::new(Database::new(vec![
+            record("10.0.0.0", "10.255.255.255", 1),
+            record("10.0.1.0", "10.0.1.255", 2),
+            record("10.6.0.0", "10.6.0.255", 3),
+        ]));
+        let numbers = |range: &str| -> Vec<u32> {
+            let range: IpRange = range.parse().unwrap();
+            store.lookup_range(&range).map(|r| r.number).collect()
+        };
+        assert_eq!(numbers("10.5.0.0/16"), vec![1]);
+        assert_eq!(numbers("10.0.1.128/25"), vec![1, 2]);
+        assert_eq!(numbers("10.6.0.0/16"), vec![1, 3]);
+        assert_eq!(numbers("11.0.0.0/8"), Vec::<u32>::new());
+    }
 }
@@ -0,0 +1,218 @@
+use crate::error::Result;
+use crate::parser::Database;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::fmt::{Display, Write as _};
+use std::io::Write as _;
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+/**
+ * Deterministic synthetic iptoasn dataset, for benchmarks and test fixtures
+ *
+ * Rows are laid out in address order like the upstream file: IPv4 from
+ * 1.0.0.0 and IPv6 from 2001::, with "Not routed" rows and (optionally)
+ * unlisted gaps between announced ranges. The same options always produce
+ * the same data.
+ */
+#[derive(Debug, Clone)]
+pub struct SyntheticDataset {
+    pub ipv4_ranges: usize,
+    pub ipv6_ranges: usize,
+    // Distinct ASNs the ranges are spread over, skewed towards low numbers
+    pub asns: u32,
+    // Leave unlisted address space between some rows
+    pub gaps: bool,
+    // Lines the parser must skip, spread evenly through the file
+    pub malformed_lines: usize,
+    pub seed: u64,
+}
+
+impl Default for SyntheticDataset {
+    // About the size of the upstream file
+    fn default() -> Self {
+        Self {
+            ipv4_ranges: 450_000,
+            ipv6_ranges: 200_000,
+            asns: 75_000,
+            gaps: true,
+            malformed_lines: 0,
+            seed: 1,
+        }
+    }
+}
+
+const COUNTRIES: [&str; 16] = [
+    "US", "CN", "DE", "GB", "JP", "BR", "RU", "FR", "IN", "NL", "CA", "AU", "KR", "SG", "ZA", "AR",
+];
+
+// One of each way a line can fail to parse
+const MALFORMED: [&str; 5] = [
+    "1.2.3.4\t1.2.3.5\n",
+    "not-an-ip\t1.2.3.5\t64512\tUS\tBAD\n",
+    "1.2.3.4\t1.2.3.999\t64512\tUS\tBAD\n",
+    "1.2.3.4\t1.2.3.5\tAS64512\tUS\tBAD\n",
+    "garbage line without tabs\n",
+];
+
+// IPv4 rows stop short of the multicast and reserved space
+const IPV4_END: u64 = 0xE000_0000;
+
+impl SyntheticDataset {
+    // A dataset with the given number of IPv4 and IPv6 ranges
+    pub fn new(ipv4_ranges: usize, ipv6_ranges: usize) -> Self {
+        Self {
+            ipv4_ranges,
+            ipv6_ranges,
+            ..Self::default()
+        }
+    }
+
+    // Uncompressed TSV in the upstream format
+    pub fn tsv(&self) -> String {
+        let mut rng = SplitMix(self.seed);
+        let mut out = String::new();
+        let rows = self.ipv4_ranges + self.ipv6_ranges;
+        // Row index before which the next malformed line goes
+        let interval = rows / (self.malformed_lines + 1);
+        let mut malformed = 0;
+        let mut row = 0;
+        let mut write_row = |out: &mut String, rng: &mut SplitMix, first: &dyn Display, last: &dyn Display| {
+            row += 1;
+            if malformed < self.malformed_lines && row > interval * (malformed + 1) {
+                out.push_str(MALFORMED[malformed % MALFORMED.len()]);
+                malformed += 1;
+            }
+            self.write_row(out, rng, first, last);
+        };
+
+        let mut next = 0x0100_0000u64;
+        for _ in 0..self.ipv4_ranges {
+            next += self.gap(&mut rng);
+            let size = 256u64 << rng.below(6);
+            if next + size > IPV4_END {
+                break;
+            }
+            let (first, last) = (Ipv4Addr::from(next as u32), Ipv4Addr::from((next + size - 1) as u32));
+            write_row(&mut out, &mut rng, &first, &last);
+            next += size;
+        }
+
+        let mut next = 0x2001u128 << 112;
+        for _ in 0..self.ipv6_ranges {
+            next += self.gap(&mut rng) as u128 * (1 << 80);
+            // /32 to /48, aligned to its size
+            let size = 1u128 << (80 + rng.below(17));
+            next = next.div_ceil(size) * size;
+            let (first, last) = (Ipv6Addr::from(next), Ipv6Addr::from(next + size - 1));
+            write_row(&mut out, &mut rng, &first, &last);
+            next += size;
+        }
+
+        // Any left over when the rows stopped early go at the end
+        for line in MALFORMED.iter().cycle().take(self.malformed_lines - malformed) {
+            out.push_str(line);
+        }
+        out
+    }
+
+    // Gzipped TSV, as served upstream
+    pub fn gzip(&self) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::fast());
+        encoder
+            .write_all(self.tsv().as_bytes())
+            .and_then(|_| encoder.finish())
+            .expect("writing to a Vec cannot fail")
+    }
+
+    // The dataset parsed into a database
+    pub fn database(&self) -> Result<Database> {
+        Database::parse_tsv(&self.tsv())
+    }
+
+    // Write the dataset gzipped to a file, e.g. as a file:// source or cache file
+    pub fn write_gzip(&self, path: &std::path::Path) -> Result<()> {
+        std::fs::write(path, self.gzip())?;
+        Ok(())
+    }
+
+    // Unlisted addresses before the next row, in units of 256
+    fn gap(&self, rng: &mut SplitMix) -> u64 {
+        // Drawn either way so toggling gaps keeps the rest of the layout
+        let (roll, units) = (rng.below(10), 1 + rng.below(16));
+        if self.gaps && roll == 0 {
+            256 * units
+        } else {
+            0
+        }
+    }
+
+    fn write_row(&self, out: &mut String, rng: &mut SplitMix, first: &dyn Display, last: &dyn Display) {
+        if rng.below(20) == 0 {
+            let _ = writeln!(out, "{}\t{}\t0\tNone\tNot routed", first, last);
+            return;
+        }
+        // Squaring a uniform draw favours low ASNs, like the few large networks upstream
+        let asns = u64::from(self.asns.max(1));
+        let number = 1 + rng.below(asns) * rng.below(asns) / asns;
+        let country = COUNTRIES[(number % COUNTRIES.len() as u64) as usize];
+        let _ = writeln!(
+            out,
+            "{}\t{}\t{}\t{}\tNET-{} - Synthetic Network {}",
+            first, last, number, country, number, number
+        );
+    }
+}
+
+// SplitMix64, enough randomness for layout without a dependency
+struct SplitMix(u64);
+
+impl SplitMix {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    fn below(&mut self, bound: u64) -> u64 {
+        self.next() % bound
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::range::to_u128;
+
+    #[test]
+    fn test_deterministic_and_sorted() {
+        let dataset = SyntheticDataset::new(2_000, 500);
+        assert_eq!(dataset.tsv(), dataset.tsv());
+
+        let database = Database::parse(dataset.gzip()).unwrap();
+        assert_eq!(database.len(), 2_500);
+        assert!(database
+            .records
+            .windows(2)
+            .all(|pair| pair[0].last_ip < pair[1].first_ip));
+        assert!(database.records.iter().any(|r| r.number == 0));
+        assert!(database.records.iter().any(|r| r.first_ip.is_ipv6()));
+    }
+
+    #[test]
+    fn test_malformed_lines_and_gaps() {
+        let dataset = SyntheticDataset {
+            gaps: false,
+            malformed_lines: 7,
+            ..SyntheticDataset::new(300, 0)
+        };
+        let (database, report) = Database::parse_tsv_with_report(&dataset.tsv()).unwrap();
+        assert_eq!(database.len(), 300);
+        assert_eq!(report.issues.len(), 7);
+        assert!(database
+            .records
+            .windows(2)
+            .all(|pair| to_u128(pair[0].last_ip) + 1 == to_u128(pair[1].first_ip)));
+    }
+}
@@ -0,0 +1,245 @@
+use crate::error::{AppError, Result};
+use crate::parser::parse_asn;
+use crate::range::{next_addr, prev_addr, IpRange};
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use std::net::IpAddr;
+use std::path::Path;
+use std::sync::Arc;
+
+/**
+ * User classification of ASNs and address ranges, e.g. "cloud" or "vpn"
+ */
+#[derive(Debug, Clone, Default)]
+pub struct TagSet {
+    asns: HashMap<u32, Vec<Arc<str>>>,
+    // Tagged ranges split where they overlap, so each address is in at most
+    // one segment holding the tags of every range containing it; sorted
+    segments: Vec<(IpRange, Vec<Arc<str>>)>,
+    // Distinct tagged ranges, for counting
+    ranges: HashSet<(IpAddr, IpAddr)>,
+}
+
+// Layout of a JSON tags file
+#[derive(Deserialize)]
+struct TagsFile {
+    #[serde(default)]
+    asns: HashMap<String, Vec<String>>,
+    #[serde(default)]
+    ranges: Vec<RangeTags>,
+}
+
+#[derive(Deserialize)]
+struct RangeTags {
+    range: String,
+    tags: Vec<String>,
+}
+
+impl TagSet {
+    /**
+     * Load tags from a file
+     *
+     * Files ending in `.json` hold `{ "asns": { "16509": ["cloud"] },
+     * "ranges": [{ "range": "10.0.0.0/8", "tags": ["internal"] }] }`.
+     * Anything else is read as text lines of `<ASN or range> <tag>[,<tag>...]`,
+     * e.g. `AS16509 cloud,hosting`, with `#` starting a comment.
+     */
+    pub fn load_file(path: &Path) -> Result<Self> {
+        let data = std::fs::read_to_string(path)?;
+        let is_json = path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("json"));
+        let invalid = |line: usize, message: String| {
+            AppError::DatabaseParse(format!("{} line {}: {}", path.display(), line, message))
+        };
+
+        let mut tags = Self::default();
+        if is_json {
+            let file: TagsFile = serde_json::from_str(&data)
+                .map_err(|e| AppError::DatabaseParse(format!("{}: {}", path.display(), e)))?;
+            for (asn, names) in file.asns {
+                let number = parse_asn(&asn).ok_or_else(|| invalid(0, format!("invalid ASN: {}", asn)))?;
+                tags.tag_asn(number, names);
+            }
+            for entry in file.ranges {
+                tags.tag_range(entry.range.parse()?, entry.tags);
+            }
+            return Ok(tags);
+        }
+
+        for (index, line) in data.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (key, names) = line
+                .split_once(char::is_whitespace)
+                .ok_or_else(|| invalid(index + 1, "expected a key and tags".to_string()))?;
+            let names = names.split(',').map(|name| name.trim().to_string());
+
+            match parse_asn(key) {
+                Some(number) => tags.tag_asn(number, names),
+                None => {
+                    let range = key.parse().map_err(|e: AppError| invalid(index + 1, e.to_string()))?;
+                    tags.tag_range(range, names)
+                }
+            }
+        }
+        Ok(tags)
+    }
+
+    // Add tags to an ASN
+    pub fn tag_asn(&mut self, number: u32, names: impl IntoIterator<Item = String>) {
+        merge(self.asns.entry(number).or_default(), names.into_iter().map(Arc::from));
+    }
+
+    // Add tags to a range
+    pub fn tag_range(&mut self, range: IpRange, names: impl IntoIterator<Item = String>) {
+        let names: Vec<Arc<str>> = names.into_iter().map(Arc::from).collect();
+        self.ranges.insert((range.first, range.last));
+
+        // Walk the segments overlapping the range, splitting those that stick
+        // out of it and filling the gaps between them with new segments
+        let mut index = self.segments.partition_point(|(segment, _)| segment.last < range.first);
+        let mut next = range.first;
+        loop {
+            let overlapping = self.segments.get(index).filter(|(segment, _)| segment.first <= range.last);
+            let Some(&(segment, _)) = overlapping else {
+                self.segments.insert(index, (IpRange { first: next, last: range.last }, Vec::new()));
+                merge(&mut self.segments[index].1, names.iter().cloned());
+                return;
+            };
+            if next < segment.first {
+                self.segments.insert(index, (IpRange { first: next, last: prev_addr(segment.first) }, Vec::new()));
+                merge(&mut self.segments[index].1, names.iter().cloned());
+                index += 1;
+            } else if segment.first < next {
+                self.split(index, next);
+                index += 1;
+            }
+            if range.last < segment.last {
+                self.split(index, next_addr(range.last));
+            }
+            merge(&mut self.segments[index].1, names.iter().cloned());
+            if range.last <= segment.last {
+                return;
+            }
+            next = next_addr(segment.last);
+            index += 1;
+        }
+    }
+
+    // Split a segment in two, the second half starting at `at`
+    fn split(&mut self, index: usize, at: IpAddr) {
+        let (segment, tags) = self.segments[index].clone();
+        self.segments[index].0.last = prev_addr(at);
+        self.segments.insert(index + 1, (IpRange { first: at, last: segment.last }, tags));
+    }
+
+    // Tags for an address announced by `asn`: the ASN's tags, then those of matching ranges
+    pub fn lookup(&self, ip: IpAddr, asn: Option<u32>) -> Vec<Arc<str>> {
+        let mut tags = asn
+            .and_then(|number| self.asns.get(&number))
+            .cloned()
+            .unwrap_or_default();
+        let index = self.segments.partition_point(|(segment, _)| segment.last < ip);
+        if let Some((_, names)) = self.segments.get(index).filter(|(segment, _)| segment.first <= ip) {
+            merge(&mut tags, names.iter().cloned());
+        }
+        tags
+    }
+
+    // Number of tagged ASNs and ranges
+    pub fn len(&self) -> usize {
+        self.asns.len() + self.ranges.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+// Append tags that aren't already present, skipping empty names
+fn merge(tags: &mut Vec<Arc<str>>, names: impl IntoIterator<Item = Arc<str>>) {
+    for name in names {
+        if !name.is_empty() && !tags.contains(&name) {
+            tags.push(name);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(tags: Vec<Arc<str>>) -> Vec<String> {
+        tags.iter().map(|tag| tag.to_string()).collect()
+    }
+
+    #[test]
+    fn test_load_text() {
+        let dir = std::env::temp_dir().join(format!("iptoasn_test_tags_txt_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("tags.txt");
+        std::fs::write(
+            &path,
+            "# classification\n\
+             AS16509 cloud, hosting\n\
+             14061 hosting\n\
+             3.5.0.0/16 vpn   # exit nodes\n\
+             3.5.1.0/24 tor,vpn\n",
+        )
+        .unwrap();
+
+        let tags = TagSet::load_file(&path).unwrap();
+        assert_eq!(tags.len(), 4);
+        assert_eq!(
+            names(tags.lookup("3.5.1.1".parse().unwrap(), Some(16509))),
+            vec!["cloud", "hosting", "vpn", "tor"]
+        );
+        assert_eq!(names(tags.lookup("3.6.0.1".parse().unwrap(), Some(14061))), vec!["hosting"]);
+        assert!(tags.lookup("10.0.0.1".parse().unwrap(), None).is_empty());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_overlapping_ranges() {
+        let mut tags = TagSet::default();
+        tags.tag_range("10.0.1.0/24".parse().unwrap(), ["inner".to_string()]);
+        tags.tag_range("10.0.0.0/16".parse().unwrap(), ["outer".to_string()]);
+        tags.tag_range("10.0.1.128-10.0.2.255".parse().unwrap(), ["straddle".to_string()]);
+        tags.tag_range("10.0.0.0/16".parse().unwrap(), ["outer".to_string(), "again".to_string()]);
+        tags.tag_range("2001:db8::/32".parse().unwrap(), ["v6".to_string()]);
+        assert_eq!(tags.len(), 4);
+
+        let at = |ip: &str| names(tags.lookup(ip.parse().unwrap(), None));
+        assert_eq!(at("10.0.0.0"), vec!["outer", "again"]);
+        assert_eq!(at("10.0.1.0"), vec!["inner", "outer", "again"]);
+        assert_eq!(at("10.0.1.128"), vec!["inner", "outer", "straddle", "again"]);
+        assert_eq!(at("10.0.2.0"), vec!["outer", "straddle", "again"]);
+        assert_eq!(at("10.0.3.0"), vec!["outer", "again"]);
+        assert_eq!(at("10.0.255.255"), vec!["outer", "again"]);
+        assert!(at("10.1.0.0").is_empty());
+        assert!(at("9.255.255.255").is_empty());
+        assert_eq!(at("2001:db8::1"), vec!["v6"]);
+
+        // Segments stay sorted and disjoint
+        assert!(tags.segments.windows(2).all(|pair| pair[0].0.last < pair[1].0.first));
+    }
+
+    #[test]
+    fn test_load_json() {
+        let dir = std::env::temp_dir().join(format!("iptoasn_test_tags_json_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("tags.json");
+        std::fs::write(
+            &path,
+            r#"{ "asns": { "AS9009": ["vpn"] }, "ranges": [{ "range": "10.0.0.0/8", "tags": ["internal"] }] }"#,
+        )
+        .unwrap();
+
+        let tags = TagSet::load_file(&path).unwrap();
+        assert_eq!(names(tags.lookup("10.1.1.1".parse().unwrap(), Some(9009))), vec!["vpn", "internal"]);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,57 @@
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry_otlp::{ExporterBuildError, SpanExporter, WithExportConfig as _};
+use opentelemetry_otlp::Protocol;
+use opentelemetry_sdk::trace::SdkTracer;
+use opentelemetry_sdk::Resource;
+use tracing::Subscriber;
+use tracing_opentelemetry::OpenTelemetryLayer;
+use tracing_subscriber::registry::LookupSpan;
+
+pub use opentelemetry_sdk::trace::SdkTracerProvider;
+
+// Layer recording tracing spans as OpenTelemetry spans
+pub type OtlpLayer<S> = OpenTelemetryLayer<S, SdkTracer>;
+
+/**
+ * Tracing layer exporting spans over OTLP/HTTP, and the provider to shut down
+ *
+ * Keep the provider and call `shutdown()` before exiting so buffered spans
+ * are flushed.
+ */
+pub struct Otlp<S> {
+    pub layer: OtlpLayer<S>,
+    pub provider: SdkTracerProvider,
+}
+
+/**
+ * Build an OTLP exporter layer when an endpoint is configured
+ * Reads the standard `OTEL_EXPORTER_OTLP_ENDPOINT` (or `..._TRACES_ENDPOINT`)
+ * and `OTEL_SERVICE_NAME` variables.
+ * @param default_service_name - Service name when `OTEL_SERVICE_NAME` isn't set
+ * @returns None when no endpoint is configured
+ */
+pub fn otlp_layer<S>(default_service_name: &str) -> Result<Option<Otlp<S>>, ExporterBuildError>
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+{
+    let configured = ["OTEL_EXPORTER_OTLP_ENDPOINT", "OTEL_EXPORTER_OTLP_TRACES_ENDPOINT"]
+        .iter()
+        .any(|name| std::env::var_os(name).is_some_and(|value| !value.is_empty()));
+    if !configured {
+        return Ok(None);
+    }
+
+    let exporter = SpanExporter::builder()
+        .with_http()
+        .with_protocol(Protocol::HttpBinary)
+        .build()?;
+    let service_name =
+        std::env::var("OTEL_SERVICE_NAME").unwrap_or_else(|_| default_service_name.to_string());
+    let provider = SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_resource(Resource::builder().with_service_name(service_name).build())
+        .build();
+
+    let layer = tracing_opentelemetry::layer().with_tracer(provider.tracer("iptoasn-core"));
+    Ok(Some(Otlp { layer, provider }))
+}
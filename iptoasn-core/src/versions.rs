@@ -0,0 +1,279 @@
+use crate::error::{AppError, Result};
+use crate::fetcher::CacheMetadata;
+use crate::freshness;
+use crate::lock;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use tracing::{debug, info, warn};
+
+/**
+ * A database version kept in the cache directory
+ */
+#[derive(Debug, Clone)]
+pub struct VersionInfo {
+    pub id: String,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    pub downloaded_at: Option<SystemTime>,
+    pub size: u64,
+    // Whether this is the version in the main cache file
+    pub current: bool,
+    // Whether this version is pinned
+    pub pinned: bool,
+}
+
+/**
+ * Archive of downloaded database versions under `<cache_dir>/versions`
+ *
+ * Each version is stored as `<id>.tsv.gz` with its metadata in `<id>.json`,
+ * where the id is the Unix timestamp of the download. Ids come from callers,
+ * so anything but digits is rejected before it can name a path.
+ */
+pub(crate) struct VersionArchive {
+    dir: PathBuf,
+    pin_path: PathBuf,
+    // Number of previous versions to keep besides the current one
+    keep: usize,
+}
+
+impl VersionArchive {
+    pub(crate) fn new(cache_dir: &Path) -> Self {
+        let dir = cache_dir.join("versions");
+        Self {
+            pin_path: dir.join("pinned"),
+            dir,
+            keep: 0,
+        }
+    }
+
+    pub(crate) fn set_keep(&mut self, keep: usize) {
+        self.keep = keep;
+    }
+
+    pub(crate) fn enabled(&self) -> bool {
+        self.keep > 0
+    }
+
+    // Id for a version downloaded at the given time
+    pub(crate) fn id_for(metadata: &CacheMetadata) -> Option<String> {
+        metadata.downloaded_at.map(|secs| secs.to_string())
+    }
+
+    // Ids are Unix seconds; anything else could reach outside the archive
+    fn is_valid_id(id: &str) -> bool {
+        !id.is_empty() && id.bytes().all(|b| b.is_ascii_digit())
+    }
+
+    fn check_id(id: &str) -> Result<()> {
+        if Self::is_valid_id(id) {
+            Ok(())
+        } else {
+            Err(AppError::VersionNotFound(id.to_string()))
+        }
+    }
+
+    fn data_path(&self, id: &str) -> PathBuf {
+        self.dir.join(format!("{}.tsv.gz", id))
+    }
+
+    fn metadata_path(&self, id: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", id))
+    }
+
+    // Store a copy of a cache file and its metadata, unless already archived
+    pub(crate) fn archive(&self, data_path: &Path, metadata: &CacheMetadata) -> Result<()> {
+        let Some(id) = Self::id_for(metadata) else {
+            return Ok(());
+        };
+        if !data_path.exists() {
+            return Ok(());
+        }
+        if self.data_path(&id).exists() {
+            let archived = self.read_metadata(&id);
+            if archived.etag != metadata.etag || archived.last_modified != metadata.last_modified {
+                warn!("Version {} is already archived with different data, not archiving", id);
+            }
+            return Ok(());
+        }
+
+        std::fs::create_dir_all(&self.dir)?;
+        let json = serde_json::to_string_pretty(metadata)
+            .map_err(|e| AppError::HttpParse(format!("Failed to serialize metadata: {}", e)))?;
+
+        lock::write_atomic(&self.data_path(&id), &std::fs::read(data_path)?)?;
+        lock::write_atomic(&self.metadata_path(&id), json.as_bytes())?;
+        debug!("Archived database version {}", id);
+        Ok(())
+    }
+
+    // Remove the oldest versions beyond the current one plus `keep`, sparing the pin
+    pub(crate) fn prune(&self) -> Result<()> {
+        let pinned = self.pinned();
+        let mut ids = self.ids()?;
+        ids.sort_by_key(|id| std::cmp::Reverse(id.parse::<u64>().unwrap_or(0)));
+
+        for id in ids.iter().skip(self.keep + 1) {
+            if pinned.as_deref() == Some(id.as_str()) {
+                continue;
+            }
+            info!("Removing old database version {}", id);
+            if let Err(e) = std::fs::remove_file(self.data_path(id)) {
+                warn!("Failed to remove version {}: {}", id, e);
+            }
+            let _ = std::fs::remove_file(self.metadata_path(id));
+        }
+        Ok(())
+    }
+
+    fn ids(&self) -> Result<Vec<String>> {
+        if !self.dir.exists() {
+            return Ok(vec![]);
+        }
+
+        let mut ids = Vec::new();
+        for entry in std::fs::read_dir(&self.dir)? {
+            let name = entry?.file_name();
+            if let Some(id) = name.to_string_lossy().strip_suffix(".tsv.gz") {
+                if Self::is_valid_id(id) {
+                    ids.push(id.to_string());
+                }
+            }
+        }
+        Ok(ids)
+    }
+
+    // List archived versions, newest first
+    pub(crate) fn list(&self, current_id: Option<&str>) -> Result<Vec<VersionInfo>> {
+        let pinned = self.pinned();
+        let mut versions = Vec::new();
+
+        for id in self.ids()? {
+            let metadata = self.read_metadata(&id);
+            let size = std::fs::metadata(self.data_path(&id))?.len();
+            versions.push(VersionInfo {
+                current: current_id == Some(id.as_str()),
+                pinned: pinned.as_deref() == Some(id.as_str()),
+                downloaded_at: metadata.downloaded_at.map(freshness::from_unix_secs),
+                etag: metadata.etag,
+                last_modified: metadata.last_modified,
+                size,
+                id,
+            });
+        }
+
+        versions.sort_by_key(|v| std::cmp::Reverse(v.downloaded_at));
+        Ok(versions)
+    }
+
+    fn read_metadata(&self, id: &str) -> CacheMetadata {
+        std::fs::read_to_string(self.metadata_path(id))
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default()
+    }
+
+    // Read an archived version and its metadata
+    pub(crate) fn read(&self, id: &str) -> Result<(Vec<u8>, CacheMetadata)> {
+        Self::check_id(id)?;
+        let path = self.data_path(id);
+        if !path.exists() {
+            return Err(AppError::VersionNotFound(id.to_string()));
+        }
+        Ok((std::fs::read(path)?, self.read_metadata(id)))
+    }
+
+    // Currently pinned version, shared by every process using the cache directory
+    pub(crate) fn pinned(&self) -> Option<String> {
+        std::fs::read_to_string(&self.pin_path)
+            .ok()
+            .map(|id| id.trim().to_string())
+            .filter(|id| Self::is_valid_id(id))
+    }
+
+    pub(crate) fn set_pinned(&self, id: Option<&str>) -> Result<()> {
+        match id {
+            Some(id) => {
+                Self::check_id(id)?;
+                if !self.data_path(id).exists() {
+                    return Err(AppError::VersionNotFound(id.to_string()));
+                }
+                lock::write_atomic(&self.pin_path, id.as_bytes())?;
+                info!("Pinned database version {}", id);
+            }
+            None => {
+                if self.pin_path.exists() {
+                    std::fs::remove_file(&self.pin_path)?;
+                    info!("Unpinned database version");
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metadata(downloaded_at: u64) -> CacheMetadata {
+        CacheMetadata {
+            etag: Some(format!("\"{}\"", downloaded_at)),
+            last_modified: None,
+            downloaded_at: Some(downloaded_at),
+        }
+    }
+
+    #[test]
+    fn test_archive_prune_and_pin() {
+        let dir = std::env::temp_dir().join(format!("iptoasn_test_versions_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let data_path = dir.join("current.tsv.gz");
+
+        let mut archive = VersionArchive::new(&dir);
+        archive.set_keep(1);
+
+        for downloaded_at in [100, 200, 300] {
+            std::fs::write(&data_path, downloaded_at.to_string()).unwrap();
+            archive.archive(&data_path, &metadata(downloaded_at)).unwrap();
+            if downloaded_at == 100 {
+                archive.set_pinned(Some("100")).unwrap();
+            }
+            archive.prune().unwrap();
+        }
+
+        // Current plus one previous, and the pinned version survives pruning
+        let ids: Vec<String> = archive
+            .list(Some("300"))
+            .unwrap()
+            .into_iter()
+            .map(|v| v.id)
+            .collect();
+        assert_eq!(ids, vec!["300", "200", "100"]);
+
+        let (data, meta) = archive.read("100").unwrap();
+        assert_eq!(data, b"100");
+        assert_eq!(meta.downloaded_at, Some(100));
+
+        archive.set_pinned(None).unwrap();
+        archive.prune().unwrap();
+        assert!(matches!(archive.read("100"), Err(AppError::VersionNotFound(_))));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_rejects_ids_outside_the_archive() {
+        let dir = std::env::temp_dir().join(format!("iptoasn_test_version_ids_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("versions")).unwrap();
+        // Would be reachable as "../outside" if ids were pasted into paths
+        std::fs::write(dir.join("outside.tsv.gz"), b"secret").unwrap();
+
+        let archive = VersionArchive::new(&dir);
+        for id in ["../outside", "..", "", "1/2", "12a"] {
+            assert!(matches!(archive.read(id), Err(AppError::VersionNotFound(_))), "{:?}", id);
+            assert!(matches!(archive.set_pinned(Some(id)), Err(AppError::VersionNotFound(_))));
+        }
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
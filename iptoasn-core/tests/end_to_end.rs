@@ -0,0 +1,167 @@
+//! End-to-end tests on synthetic datasets, served through file:// sources
+//! and pre-seeded caches so no network is needed.
+
+use iptoasn_core::{
+    AppError, AsnStore, Database, DatabaseFetcher, DbEvent, IpToAsnDb, SyntheticDataset,
+};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::path::{Path, PathBuf};
+
+// A fresh directory for one test
+fn test_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("iptoasn_e2e_{}_{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn open(dir: &Path, source: &Path) -> IpToAsnDb {
+    IpToAsnDb::new(
+        format!("file://{}", source.display()),
+        dir.join("cache").display().to_string(),
+    )
+    .unwrap()
+}
+
+fn dataset(seed: u64) -> SyntheticDataset {
+    SyntheticDataset {
+        seed,
+        malformed_lines: 10,
+        ..SyntheticDataset::new(5_000, 1_000)
+    }
+}
+
+// First address after a record that no record covers
+fn gap_address(store: &AsnStore) -> IpAddr {
+    store
+        .records()
+        .windows(2)
+        .find_map(|pair| {
+            let next = next_address(pair[0].last_ip);
+            (next < pair[1].first_ip && next.is_ipv4() == pair[1].first_ip.is_ipv4()).then_some(next)
+        })
+        .expect("dataset has gaps")
+}
+
+fn next_address(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V4(ip) => Ipv4Addr::from(u32::from(ip) + 1).into(),
+        IpAddr::V6(ip) => Ipv6Addr::from(u128::from(ip) + 1).into(),
+    }
+}
+
+#[test]
+fn test_parse_skips_malformed_lines() {
+    let dataset = dataset(1);
+
+    let database = Database::parse(dataset.gzip()).unwrap();
+    assert_eq!(database.len(), 6_000);
+
+    let (_, report) = Database::parse_tsv_with_report(&dataset.tsv()).unwrap();
+    assert_eq!(report.issues.len(), 10);
+    assert_eq!((report.ipv4_records, report.ipv6_records), (5_000, 1_000));
+}
+
+#[tokio::test]
+async fn test_lookup_matches_every_range() {
+    let dir = test_dir("lookup");
+    let source = dir.join("ip2asn-combined.tsv.gz");
+    let dataset = dataset(1);
+    dataset.write_gzip(&source).unwrap();
+
+    let db = open(&dir, &source);
+    db.load().await.unwrap();
+    assert_eq!(db.stats().record_count, 6_000);
+
+    let expected = AsnStore::new(dataset.database().unwrap());
+    for record in expected.records().iter().step_by(37) {
+        for ip in [record.first_ip, record.last_ip] {
+            let info = db.lookup(&ip.to_string()).unwrap();
+            assert!(info.announced, "{} should be announced", ip);
+            assert_eq!(info.as_number, Some(record.number), "ASN of {}", ip);
+            assert_eq!(info.first_ip, Some(record.first_ip.to_string()));
+        }
+    }
+
+    let gap = gap_address(&expected);
+    assert!(!db.lookup(&gap.to_string()).unwrap().announced);
+    assert!(!db.lookup("240.0.0.1").unwrap().announced);
+    assert!(!db.lookup("fc00::1").unwrap().announced);
+    assert!(matches!(db.lookup("300.1.1.1"), Err(AppError::InvalidIp(_))));
+}
+
+#[tokio::test]
+async fn test_refresh_swaps_in_new_data() {
+    let dir = test_dir("refresh");
+    let source = dir.join("ip2asn-combined.tsv.gz");
+    dataset(1).write_gzip(&source).unwrap();
+
+    let db = open(&dir, &source);
+    db.load().await.unwrap();
+    let mut plain = db.subscribe();
+    let mut events = db.subscribe_with_diffs();
+
+    let updated = SyntheticDataset {
+        ipv4_ranges: 6_000,
+        ..dataset(2)
+    };
+    updated.write_gzip(&source).unwrap();
+    assert!(db.refresh().await.unwrap());
+
+    match events.recv().await.unwrap() {
+        DbEvent::Updated { record_count, diff } => {
+            assert_eq!(record_count, 7_000);
+            assert!(!diff.unwrap().is_empty());
+        }
+        other => panic!("unexpected event {:?}", other),
+    }
+
+    let expected = AsnStore::new(updated.database().unwrap());
+    let record = &expected.records()[4_321];
+    assert_eq!(db.lookup(&record.first_ip.to_string()).unwrap().as_number, Some(record.number));
+
+    // Without a receiver asking for them, updates carry no diff
+    drop(events);
+    dataset(3).write_gzip(&source).unwrap();
+    assert!(db.refresh().await.unwrap());
+    plain.recv().await.unwrap();
+    assert!(matches!(plain.recv().await.unwrap(), DbEvent::Updated { diff: None, .. }));
+}
+
+#[tokio::test]
+async fn test_falls_back_to_cache() {
+    let dir = test_dir("cache");
+    let missing = dir.join("missing.tsv.gz");
+    let cache_dir = dir.join("cache");
+    let cache_path = DatabaseFetcher::new(format!("file://{}", missing.display()), &cache_dir.display().to_string())
+        .unwrap()
+        .cache_path()
+        .to_path_buf();
+    dataset(1).write_gzip(&cache_path).unwrap();
+
+    // The source can't be read, so the cached copy is served
+    let db = open(&dir, &missing);
+    db.load().await.unwrap();
+    assert_eq!(db.stats().record_count, 6_000);
+
+    let db = open(&dir, &missing);
+    db.load_cached_first().await.unwrap();
+    assert_eq!(db.stats().record_count, 6_000);
+
+    // Refreshing never falls back, so the error surfaces
+    assert!(db.refresh().await.is_err());
+    assert_eq!(db.stats().record_count, 6_000);
+}
+
+#[tokio::test]
+async fn test_corrupt_source_without_cache_fails() {
+    let dir = test_dir("corrupt");
+    let source = dir.join("ip2asn-combined.tsv.gz");
+    let mut truncated = dataset(1).gzip();
+    truncated.truncate(truncated.len() / 2);
+    std::fs::write(&source, truncated).unwrap();
+
+    let db = open(&dir, &source);
+    assert!(db.load().await.is_err());
+    assert!(matches!(db.lookup("1.1.1.1"), Ok(info) if !info.announced));
+}
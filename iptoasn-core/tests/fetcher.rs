@@ -0,0 +1,366 @@
+//! Fetcher and updater tests against an in-process HTTP server.
+
+mod support;
+
+use iptoasn_core::{AppError, DatabaseFetcher, DbEvent, IpToAsnDb, SyntheticDataset};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use support::{Fault, MockServer};
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Instrument, Subscriber};
+use tracing_subscriber::layer::{Context, SubscriberExt};
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::Layer;
+
+const MODIFIED: &str = "Tue, 01 Sep 2026 00:00:00 GMT";
+
+// A fresh cache directory for one test
+fn cache_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("iptoasn_fetcher_{}_{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn fetcher(server: &MockServer, dir: &Path) -> DatabaseFetcher {
+    DatabaseFetcher::new(server.url(), &dir.display().to_string()).unwrap()
+}
+
+fn version(seed: u64) -> Vec<u8> {
+    SyntheticDataset {
+        seed,
+        ..SyntheticDataset::new(500, 100)
+    }
+    .gzip()
+}
+
+#[tokio::test]
+async fn test_conditional_request_not_modified() {
+    let server = MockServer::start().await;
+    let dir = cache_dir("conditional");
+    server.publish(version(1), Some("\"v1\""), Some(MODIFIED));
+
+    let mut fetcher = fetcher(&server, &dir);
+    assert_eq!(fetcher.fetch().await.unwrap(), Some(version(1)));
+    let first = server.last_request();
+    assert_eq!(first.method, "GET");
+    assert!(first.header("if-none-match").is_none());
+    assert!(first.header("user-agent").unwrap().starts_with("iptoasn-core/"));
+
+    assert_eq!(fetcher.fetch().await.unwrap(), None);
+    let second = server.last_request();
+    assert_eq!(second.header("if-none-match"), Some("\"v1\""));
+    assert_eq!(second.header("if-modified-since"), Some(MODIFIED));
+
+    server.publish(version(2), Some("\"v2\""), None);
+    assert_eq!(fetcher.fetch().await.unwrap(), Some(version(2)));
+    assert_eq!(std::fs::read(fetcher.cache_path()).unwrap(), version(2));
+}
+
+#[tokio::test]
+async fn test_etag_persisted_across_instances() {
+    let server = MockServer::start().await;
+    let dir = cache_dir("persisted");
+    server.publish(version(1), Some("\"v1\""), None);
+
+    fetcher(&server, &dir).fetch().await.unwrap().unwrap();
+
+    // A new process picks the validators up from the cache metadata
+    let mut restarted = fetcher(&server, &dir);
+    assert_eq!(restarted.fetch().await.unwrap(), None);
+    assert_eq!(server.last_request().header("if-none-match"), Some("\"v1\""));
+    assert_eq!(restarted.load_from_cache().unwrap(), version(1));
+}
+
+#[tokio::test]
+async fn test_last_modified_without_etag() {
+    let server = MockServer::start().await;
+    let dir = cache_dir("last_modified");
+    server.publish(version(1), None, Some(MODIFIED));
+
+    let mut fetcher = fetcher(&server, &dir);
+    fetcher.fetch().await.unwrap().unwrap();
+    assert_eq!(fetcher.fetch().await.unwrap(), None);
+    let request = server.last_request();
+    assert!(request.header("if-none-match").is_none());
+    assert_eq!(request.header("if-modified-since"), Some(MODIFIED));
+}
+
+#[tokio::test]
+async fn test_server_error_keeps_cache() {
+    let server = MockServer::start().await;
+    let dir = cache_dir("server_error");
+    server.publish(version(1), Some("\"v1\""), None);
+
+    let mut fetcher = fetcher(&server, &dir);
+    fetcher.fetch().await.unwrap().unwrap();
+
+    server.publish(version(2), Some("\"v2\""), None);
+    server.push_fault(Fault::Status(503));
+    let err = fetcher.fetch().await.unwrap_err();
+    assert!(matches!(err, AppError::HttpStatus { status: 503, .. }), "{}", err);
+    assert_eq!(err.code(), "ERR_HTTP_STATUS");
+    assert_eq!(fetcher.load_from_cache().unwrap(), version(1));
+
+    // The failure didn't clobber the stored validators
+    assert_eq!(fetcher.fetch().await.unwrap(), Some(version(2)));
+    assert_eq!(server.last_request().header("if-none-match"), Some("\"v1\""));
+}
+
+#[tokio::test]
+async fn test_load_falls_back_to_cache_on_error() {
+    let server = MockServer::start().await;
+    let dir = cache_dir("fallback");
+    server.publish(version(1), Some("\"v1\""), None);
+    fetcher(&server, &dir).fetch().await.unwrap().unwrap();
+
+    server.push_fault(Fault::Status(500));
+    let db = IpToAsnDb::new(server.url(), dir.display().to_string()).unwrap();
+    db.load().await.unwrap();
+    assert_eq!(db.stats().record_count, 600);
+}
+
+#[tokio::test]
+async fn test_load_without_cache_reports_fetch_error() {
+    let server = MockServer::start().await;
+    let dir = cache_dir("no_cache");
+    server.push_fault(Fault::Status(500));
+
+    let db = IpToAsnDb::new(server.url(), dir.display().to_string()).unwrap();
+    let err = db.load().await.unwrap_err();
+    assert!(matches!(err, AppError::HttpStatus { status: 500, .. }), "{}", err);
+}
+
+#[tokio::test]
+async fn test_corrupt_cache() {
+    let server = MockServer::start().await;
+    let dir = cache_dir("corrupt");
+    server.publish(version(1), Some("\"v1\""), None);
+    let fetcher = fetcher(&server, &dir);
+    std::fs::write(fetcher.cache_path(), b"\x1f\x8bnot really gzip").unwrap();
+
+    server.push_fault(Fault::Status(500));
+    let db = IpToAsnDb::new(server.url(), dir.display().to_string()).unwrap();
+    let err = db.load().await.unwrap_err();
+    assert!(matches!(err, AppError::CacheCorrupt { .. }), "{}", err);
+    assert_eq!(err.code(), "ERR_CACHE_CORRUPT");
+
+    // A fresh download replaces it
+    db.load().await.unwrap();
+    assert_eq!(db.stats().record_count, 600);
+}
+
+#[tokio::test]
+async fn test_downloads_in_one_second_keep_distinct_versions() {
+    let server = MockServer::start().await;
+    let dir = cache_dir("same_second");
+    let mut fetcher = fetcher(&server, &dir);
+    fetcher.set_keep_versions(5);
+
+    server.publish(version(1), Some("\"v1\""), None);
+    fetcher.fetch().await.unwrap().unwrap();
+    server.publish(version(2), Some("\"v2\""), None);
+    fetcher.fetch().await.unwrap().unwrap();
+
+    let versions = fetcher.list_versions().unwrap();
+    assert_eq!(versions.len(), 2);
+    assert_eq!(fetcher.read_version(&versions[0].id).unwrap(), version(2));
+    assert_eq!(fetcher.read_version(&versions[1].id).unwrap(), version(1));
+}
+
+#[tokio::test]
+async fn test_request_timeout() {
+    let server = MockServer::start().await;
+    let dir = cache_dir("timeout");
+    server.publish(version(1), Some("\"v1\""), None);
+
+    let mut fetcher = fetcher(&server, &dir);
+    fetcher.set_request_timeout(Duration::from_millis(200));
+    server.push_fault(Fault::Delay(Duration::from_secs(5)));
+
+    let started = std::time::Instant::now();
+    let err = fetcher.fetch().await.unwrap_err();
+    assert!(matches!(err, AppError::HttpTimeout(_)), "{}", err);
+    assert!(started.elapsed() < Duration::from_secs(5));
+    assert!(!fetcher.cache_path().exists());
+
+    // The next attempt isn't affected
+    assert_eq!(fetcher.fetch().await.unwrap(), Some(version(1)));
+}
+
+#[tokio::test]
+async fn test_truncated_body_keeps_cache() {
+    let server = MockServer::start().await;
+    let dir = cache_dir("truncated");
+    server.publish(version(1), Some("\"v1\""), None);
+
+    let mut fetcher = fetcher(&server, &dir);
+    fetcher.fetch().await.unwrap().unwrap();
+
+    let body = version(2);
+    server.publish(body.clone(), Some("\"v2\""), None);
+    server.push_fault(Fault::Truncate(body.len() / 2));
+    assert!(fetcher.fetch().await.is_err());
+    assert_eq!(fetcher.load_from_cache().unwrap(), version(1));
+}
+
+#[tokio::test]
+async fn test_updater_installs_new_versions() {
+    let server = MockServer::start().await;
+    let dir = cache_dir("updater");
+    server.publish(version(1), Some("\"v1\""), None);
+
+    let db = IpToAsnDb::new(server.url(), dir.display().to_string()).unwrap();
+    db.load().await.unwrap();
+    let mut events = db.subscribe();
+
+    // Failed checks leave the loaded data in place
+    server.push_fault(Fault::Status(500));
+    server.push_fault(Fault::Truncate(10));
+    let updater = db.start_updater_every(Duration::from_millis(50)).await;
+    while server.requests().len() < 4 {
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    assert_eq!(db.stats().record_count, 600);
+    assert!(events.try_recv().is_err());
+
+    let updated = SyntheticDataset {
+        seed: 2,
+        ..SyntheticDataset::new(700, 100)
+    };
+    server.publish(updated.gzip(), Some("\"v2\""), None);
+    let event = tokio::time::timeout(Duration::from_secs(10), events.recv())
+        .await
+        .expect("updater installed the new version")
+        .unwrap();
+    assert!(matches!(event, DbEvent::Updated { record_count: 800, .. }));
+    assert_eq!(db.stats().record_count, 800);
+
+    updater.abort();
+}
+
+// A span name with its fields as "name=value"
+type CapturedSpan = (String, Vec<String>);
+
+// Spans in creation order
+#[derive(Clone, Default)]
+struct SpanCapture(Arc<Mutex<Vec<CapturedSpan>>>);
+
+impl SpanCapture {
+    fn fields(&self, span: &str) -> Vec<String> {
+        let spans = self.0.lock().unwrap();
+        let (_, fields) = spans.iter().find(|(name, _)| name == span).expect(span);
+        fields.clone()
+    }
+}
+
+struct FieldVisitor<'a>(&'a mut Vec<String>);
+
+impl Visit for FieldVisitor<'_> {
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        self.0.push(format!("{}={:?}", field.name(), value));
+    }
+}
+
+impl<S: Subscriber + for<'a> LookupSpan<'a>> Layer<S> for SpanCapture {
+    fn on_new_span(&self, attrs: &Attributes<'_>, _: &Id, _: Context<'_, S>) {
+        let mut fields = Vec::new();
+        attrs.record(&mut FieldVisitor(&mut fields));
+        self.0.lock().unwrap().push((attrs.metadata().name().to_string(), fields));
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+        let name = ctx.span(id).unwrap().name();
+        let mut spans = self.0.lock().unwrap();
+        if let Some((_, fields)) = spans.iter_mut().rev().find(|(span, _)| span == name) {
+            values.record(&mut FieldVisitor(fields));
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_refresh_spans() {
+    let server = MockServer::start().await;
+    let dir = cache_dir("spans");
+    let body = version(1);
+    server.publish(body.clone(), Some("\"v1\""), None);
+
+    let capture = SpanCapture::default();
+    let _guard = tracing_subscriber::registry().with(capture.clone()).set_default();
+
+    let db = IpToAsnDb::new(server.url(), dir.display().to_string()).unwrap();
+    assert!(db.refresh().await.unwrap());
+
+    let names: Vec<String> = capture.0.lock().unwrap().iter().map(|(name, _)| name.clone()).collect();
+    assert_eq!(names, ["refresh", "fetch", "decompress", "parse", "validate", "swap"]);
+
+    let fetch = capture.fields("fetch");
+    assert!(fetch.contains(&format!("url={}", server.url())), "{:?}", fetch);
+    assert!(fetch.contains(&"status=200".to_string()));
+    assert!(fetch.contains(&format!("bytes={}", body.len())));
+    assert!(fetch.iter().any(|field| field.starts_with("duration_ms=")));
+    assert!(capture.fields("parse").contains(&"records=600".to_string()));
+    assert!(capture.fields("swap").contains(&"records=600".to_string()));
+    assert!(capture.fields("refresh").contains(&"outcome=\"updated\"".to_string()));
+    for span in &names {
+        assert!(capture.fields(span).iter().any(|field| field.starts_with("duration_ms=")), "{}", span);
+    }
+}
+
+// A span's id and name, its parent's name and the names of the spans it follows from
+type LinkedSpan = (Id, String, Option<String>, Vec<String>);
+
+#[derive(Clone, Default)]
+struct SpanLinks(Arc<Mutex<Vec<LinkedSpan>>>);
+
+impl<S: Subscriber + for<'a> LookupSpan<'a>> Layer<S> for SpanLinks {
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        let parent = ctx.span(id).unwrap().parent().map(|span| span.name().to_string());
+        let name = attrs.metadata().name().to_string();
+        self.0.lock().unwrap().push((id.clone(), name, parent, Vec::new()));
+    }
+
+    fn on_follows_from(&self, id: &Id, follows: &Id, ctx: Context<'_, S>) {
+        let follows = ctx.span(follows).unwrap().name().to_string();
+        let mut spans = self.0.lock().unwrap();
+        if let Some((.., links)) = spans.iter_mut().rev().find(|(span, ..)| span == id) {
+            links.push(follows);
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_updater_checks_are_separate_traces() {
+    let server = MockServer::start().await;
+    let dir = cache_dir("traces");
+    server.publish(version(1), Some("\"v1\""), None);
+
+    let links = SpanLinks::default();
+    let _guard = tracing_subscriber::registry().with(links.clone()).set_default();
+
+    let db = IpToAsnDb::new(server.url(), dir.display().to_string()).unwrap();
+    db.load().await.unwrap();
+    let updater = db
+        .start_updater_every(Duration::from_millis(50))
+        .instrument(tracing::info_span!("caller"))
+        .await;
+    while server.requests().len() < 3 {
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    updater.abort();
+
+    // Each check is a root linked to the caller, rather than nested in it
+    let spans = links.0.lock().unwrap();
+    let checks: Vec<&LinkedSpan> = spans.iter().filter(|(_, name, ..)| name == "auto_update").collect();
+    assert!(checks.len() >= 2, "{:?}", spans);
+    for (_, _, parent, follows) in checks {
+        assert_eq!((parent.as_deref(), follows.as_slice()), (None, ["caller".to_string()].as_slice()));
+    }
+    assert!(spans
+        .iter()
+        .filter(|(_, name, ..)| name == "refresh")
+        .all(|(_, _, parent, _)| parent.as_deref() == Some("auto_update")));
+}
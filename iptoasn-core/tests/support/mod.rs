@@ -0,0 +1,204 @@
+//! In-process HTTP server standing in for iptoasn.com in tests.
+//!
+//! It serves the currently published version of a dataset, answering
+//! conditional requests with 304 like the real server, and can be scripted
+//! to misbehave for the next requests: fail with a status, stall, or cut
+//! the body short.
+
+#![allow(dead_code)]
+
+use std::collections::{HashMap, VecDeque};
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinHandle;
+
+// One-off behaviour for the next request
+#[derive(Debug, Clone)]
+pub enum Fault {
+    // Respond with this status and an empty body
+    Status(u16),
+    // Wait before responding, e.g. longer than the client's timeout
+    Delay(Duration),
+    // Announce the full body but close the connection after this many bytes
+    Truncate(usize),
+}
+
+// A request as the server saw it
+#[derive(Debug, Clone)]
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    // Lowercase header names
+    pub headers: HashMap<String, String>,
+}
+
+impl Request {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name).map(String::as_str)
+    }
+}
+
+#[derive(Default)]
+struct State {
+    body: Vec<u8>,
+    etag: Option<String>,
+    last_modified: Option<String>,
+    faults: VecDeque<Fault>,
+    requests: Vec<Request>,
+}
+
+pub struct MockServer {
+    addr: SocketAddr,
+    state: Arc<Mutex<State>>,
+    task: JoinHandle<()>,
+}
+
+impl MockServer {
+    pub async fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let state = Arc::new(Mutex::new(State::default()));
+
+        let shared = state.clone();
+        let task = tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(handle(stream, shared.clone()));
+            }
+        });
+
+        Self { addr, state, task }
+    }
+
+    // URL of the dataset
+    pub fn url(&self) -> String {
+        format!("http://{}/data/ip2asn-combined.tsv.gz", self.addr)
+    }
+
+    // Serve a new version of the dataset
+    pub fn publish(&self, body: Vec<u8>, etag: Option<&str>, last_modified: Option<&str>) {
+        let mut state = self.state.lock().unwrap();
+        state.body = body;
+        state.etag = etag.map(str::to_string);
+        state.last_modified = last_modified.map(str::to_string);
+    }
+
+    // Misbehave on the next request that isn't already scripted
+    pub fn push_fault(&self, fault: Fault) {
+        self.state.lock().unwrap().faults.push_back(fault);
+    }
+
+    // Requests received so far
+    pub fn requests(&self) -> Vec<Request> {
+        self.state.lock().unwrap().requests.clone()
+    }
+
+    pub fn last_request(&self) -> Request {
+        self.requests().pop().expect("no requests received")
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+async fn handle(mut stream: TcpStream, state: Arc<Mutex<State>>) {
+    let Some(request) = read_request(&mut stream).await else {
+        return;
+    };
+
+    let (fault, body, etag, last_modified) = {
+        let mut state = state.lock().unwrap();
+        state.requests.push(request.clone());
+        (
+            state.faults.pop_front(),
+            state.body.clone(),
+            state.etag.clone(),
+            state.last_modified.clone(),
+        )
+    };
+
+    let mut send = body.len();
+    match fault {
+        Some(Fault::Status(status)) => {
+            let _ = respond(&mut stream, status, &[], &[], 0).await;
+            return;
+        }
+        Some(Fault::Delay(delay)) => tokio::time::sleep(delay).await,
+        Some(Fault::Truncate(bytes)) => send = bytes.min(body.len()),
+        None => {}
+    }
+
+    let unchanged = match (request.header("if-none-match"), &etag) {
+        (Some(theirs), Some(ours)) => theirs == ours,
+        _ => matches!(
+            (request.header("if-modified-since"), &last_modified),
+            (Some(theirs), Some(ours)) if theirs == ours
+        ),
+    };
+    if unchanged {
+        let _ = respond(&mut stream, 304, &[], &[], 0).await;
+        return;
+    }
+
+    let mut headers = vec![("Content-Type", "application/gzip".to_string())];
+    headers.extend(etag.map(|etag| ("ETag", etag)));
+    headers.extend(last_modified.map(|modified| ("Last-Modified", modified)));
+    let _ = respond(&mut stream, 200, &headers, &body, send).await;
+}
+
+async fn read_request(stream: &mut TcpStream) -> Option<Request> {
+    let mut buffer = Vec::new();
+    let mut chunk = [0u8; 1024];
+    while !buffer.windows(4).any(|w| w == b"\r\n\r\n") {
+        let read = stream.read(&mut chunk).await.ok()?;
+        if read == 0 {
+            return None;
+        }
+        buffer.extend_from_slice(&chunk[..read]);
+    }
+
+    let text = String::from_utf8_lossy(&buffer);
+    let mut lines = text.lines();
+    let mut request_line = lines.next()?.split_whitespace();
+    let method = request_line.next()?.to_string();
+    let path = request_line.next()?.to_string();
+    let headers = lines
+        .take_while(|line| !line.is_empty())
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim().to_ascii_lowercase(), value.trim().to_string()))
+        .collect();
+
+    Some(Request {
+        method,
+        path,
+        headers,
+    })
+}
+
+// Write a response, sending only the first `send` bytes of the body
+async fn respond(
+    stream: &mut TcpStream,
+    status: u16,
+    headers: &[(&str, String)],
+    body: &[u8],
+    send: usize,
+) -> std::io::Result<()> {
+    let mut head = format!(
+        "HTTP/1.1 {} Mock\r\nContent-Length: {}\r\nConnection: close\r\n",
+        status,
+        body.len()
+    );
+    for (name, value) in headers {
+        head.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    head.push_str("\r\n");
+
+    stream.write_all(head.as_bytes()).await?;
+    stream.write_all(&body[..send]).await?;
+    stream.shutdown().await
+}
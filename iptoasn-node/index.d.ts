@@ -1,5 +1,19 @@
 /* auto-generated by NAPI-RS */
 /* eslint-disable */
+/** * Enricher appends AS number, country and description columns to delimited records.
+ */
+export declare class Enricher {
+  /** * Append the column names to a header line
+   * @param line - Header line without its line terminator
+   */
+  enrichHeader(line: string): string
+  /** * Enrich a batch of lines off the main thread
+   * @param lines - Lines without their line terminators
+   * @returns Enriched lines in the same order
+   */
+  enrichLines(lines: Array<string>): Promise<Array<string>>
+}
+
 /** * IpToAsn class provides IP to ASN lookup functionality with automatic database updates.
  */
 export declare class IpToAsn {
@@ -12,15 +26,176 @@ export declare class IpToAsn {
    * @returns Promise that resolves when loading is complete
    */
   load(): Promise<void>
+  /** * Load the cached database immediately and refresh it in the background
+   * @returns Promise that resolves as soon as lookups are available
+   */
+  loadCachedFirst(): Promise<void>
   /** * Look up an IP address
    * @param ip - IP address to look up (IPv4 or IPv6)
    * @returns ASN information or null if not found
    */
   lookup(ip: string): AsnResult
+  /** * Look up an IPv4 address given as a 32-bit integer
+   * @param ip - Address in host order, e.g. 0x01010101 for 1.1.1.1
+   * @returns ASN information
+   */
+  lookupV4(ip: number): AsnResult
+  /** * Look up an address given as raw bytes
+   * @param ip - 4-byte IPv4 or 16-byte IPv6 address in network order
+   * @returns ASN information
+   */
+  lookupBytes(ip: Buffer): AsnResult
+  /** * Look up packed addresses in one call
+   * @param ips - Concatenated addresses in network order
+   * @param address_size - 4 for IPv4 or 16 for IPv6 addresses (default 4)
+   * @returns The ASN of each address in order, 0 when not announced; records
+   * and overrides for AS0 ("Not routed") also read as 0
+   */
+  lookupBuffer(ips: Buffer, addressSize?: number | undefined | null): Uint32Array
+  /** * Find every record overlapping a CIDR prefix or address range
+   *
+   * Only upstream records are reported; overrides are not merged in, so an
+   * address covered by an override can resolve differently in `lookup`.
+   * @param range - "203.0.113.0/22", "10.0.0.0-10.255.255.255" or a single address
+   * @returns Overlapping records with coverage percentages
+   */
+  lookupRange(range: string): RangeResult
+  /** * List every ASN in the database
+   * @returns ASNs sorted by number
+   */
+  listAsns(): Array<AsnDetails>
+  /** * Get catalogue details for one ASN
+   * @param as_number - ASN number
+   * @returns ASN details or null if the ASN isn't in the database
+   */
+  getAsn(asNumber: number): AsnDetails | null
+  /** * Search ASN descriptions, case-insensitively
+   * @param query - Words that must all appear in the description, e.g. "AMAZON"
+   * @param limit - Maximum number of results
+   * @returns Matching ASNs, best matches first
+   */
+  searchAsns(query: string, limit?: number | undefined | null): Array<AsnDetails>
+  /** * List the ASNs registered in a country
+   * @param country - ISO country code, case-insensitive
+   */
+  asnsByCountry(country: string): Array<AsnDetails>
+  /** * List the country codes present in the database
+   */
+  countries(): Array<string>
+  /** * List the ranges announced by an ASN
+   * @param as_number - ASN number
+   */
+  rangesForAsn(asNumber: number): Array<AsnRange>
+  /** * List the ranges registered in a country
+   * @param country - ISO country code, case-insensitive
+   */
+  rangesByCountry(country: string): Array<AsnRange>
+  /** * Get address space totals per country and the largest ASNs
+   * @param top - Number of ASNs in each top list (default 10)
+   */
+  aggregates(top?: number | undefined | null): AggregatesResult
+  /** * Get the address space announced by one ASN
+   * @param as_number - ASN number
+   * @returns Address space or null if the ASN isn't in the database
+   */
+  asnSpace(asNumber: number): AddressSpace | null
+  /** * Replace the overrides with the records of a file
+   * @param path - JSON array of { range, as_number, as_country_code, as_description }, or iptoasn TSV
+   * @returns Number of overrides loaded
+   */
+  loadOverrides(path: string): Promise<number>
+  /** * Add an override that takes priority over upstream data
+   * @param range - CIDR prefix, "first-last" range or single address
+   * @param as_number - ASN number to report
+   * @param country_code - Country code to report
+   * @param description - Description to report
+   */
+  addOverride(range: string, asNumber: number, countryCode?: string | undefined | null, description?: string | undefined | null): Promise<void>
+  /** * Remove the override for exactly this range
+   * @param range - Range the override was added with
+   * @returns true if an override was removed
+   */
+  removeOverride(range: string): Promise<boolean>
+  /** * Remove all overrides
+   */
+  clearOverrides(): Promise<void>
+  /** * List the overrides
+   * @returns Overrides sorted by range
+   */
+  listOverrides(): Array<AsnRange>
+  /** * Replace the tags with those of a file
+   * @param path - JSON { asns, ranges } or text lines of "<ASN or range> <tag>,<tag>"
+   * @returns Number of tagged ASNs and ranges
+   */
+  loadTags(path: string): Promise<number>
+  /** * Reload the tags file if it changed since it was loaded
+   * @returns true if the tags were reloaded
+   */
+  reloadTags(): boolean
+  /** * Reload the tags file automatically when it changes
+   * @param interval_seconds - How often to check the file (default 30)
+   */
+  watchTags(intervalSeconds?: number | undefined | null): Promise<void>
+  /** * Stop watching the tags file
+   */
+  stopWatchingTags(): void
+  /** * Merge a cloud provider range list into lookups
+   * @param path - AWS ip-ranges.json, GCP cloud.json/goog.json, Azure Service Tags
+   *               or generic [{ range, provider, service?, region? }] JSON
+   * @param format - "aws", "gcp", "azure" or "generic"; detected when omitted
+   * @returns Number of new prefixes
+   */
+  loadProviderRanges(path: string, format?: string | undefined | null): Promise<number>
+  /** * Remove all cloud provider ranges
+   */
+  clearProviderRanges(): void
   /** * Get database statistics
    * @returns Statistics including record count and last update time
    */
   stats(): DatabaseStats
+  /** * Render lookup, update and data metrics for Prometheus
+   * @returns The text exposition format, ready to serve from a scrape endpoint
+   */
+  metrics(): string
+  /** * Forward this instance's Rust log events to a JS callback
+   * @param callback - Receives each event; null removes the logger
+   * @param level - Most verbose level to forward: "error", "warn", "info", "debug" or "trace" (default: "info")
+   */
+  setLogger(callback: ((entry: LogEntry) => void) | null, level?: string | undefined | null): void
+  /** * Set how many previous database versions to keep in the cache directory
+   * @param keep - Number of versions besides the current one (0 disables the archive)
+   */
+  setKeepVersions(keep: number): Promise<void>
+  /** * List the database versions kept in the cache directory
+   * @returns Versions, newest first
+   */
+  listVersions(): Promise<Array<DatabaseVersion>>
+  /** * Roll back to a previously downloaded version
+   * @param id - Version id from listVersions()
+   */
+  rollback(id: string): Promise<void>
+  /** * Pin a version so the updater won't replace it
+   * @param id - Version id from listVersions(), or null to unpin
+   */
+  pinVersion(id?: string | undefined | null): Promise<void>
+  /** * Cache lookup results for the most recently looked up addresses
+   * @param capacity - Maximum number of cached results; 0 disables the cache
+   */
+  setLookupCache(capacity: number): void
+  /** * Set the maximum data age policy
+   * @param max_age_seconds - Maximum age of the loaded data, or null to disable the policy
+   * @param action - "warn" to log, "event" to emit a stale event, "fail" to fail lookups
+   */
+  setMaxAgePolicy(maxAgeSeconds?: number | undefined | null, action?: string | undefined | null): void
+  /** * Create an enricher over the currently loaded database
+   * @param options - Where to find the IP address and how columns are delimited
+   */
+  createEnricher(options?: EnrichOptions | undefined | null): Enricher
+  /** * Register a callback for database events
+   * @param callback - Called with each DatabaseEvent
+   * @param with_diff - Include the diff in updated events (default true)
+   */
+  onEvent(callback: ((arg: DatabaseEvent) => void), withDiff?: boolean | undefined | null): void
   /** * Start automatic database updates
    * @param interval_minutes - How often to check for updates (in minutes)
    */
@@ -41,8 +216,16 @@ export declare class IpToAsn {
  * @property {string | null} first_ip - First IP in the ASN range (null if not announced)
  * @property {string | null} last_ip - Last IP in the ASN range (null if not announced)
  * @property {number | null} as_number - ASN number (null if not announced)
- * @property {string | null} as_country_code - ASN country code (null if not announced)
+ * @property {string | null} as_country_code - ASN country code (null if not announced or unknown)
  * @property {string | null} as_description - ASN description (null if not announced)
+ * @property {string | null} country_name - Country name (null if the code is unknown)
+ * @property {string | null} continent - Continent name (null if the code is unknown)
+ * @property {string | null} region - UN M49 sub-region, e.g. "Western Europe" (null if the code is unknown)
+ * @property {boolean} is_override - Whether the result came from a user-supplied override
+ * @property {string[]} tags - User tags of the ASN and of ranges containing the address
+ * @property {string | null} cloud_provider - Cloud or hosting provider publishing the address, e.g. "AWS"
+ * @property {string | null} cloud_service - Provider service, e.g. "EC2" (null if not published)
+ * @property {string | null} cloud_region - Provider region, e.g. "us-east-1" (null if not published)
  */
 export interface AsnResult {
   ip: string
@@ -52,18 +235,297 @@ export interface AsnResult {
   asNumber?: number
   asCountryCode?: string
   asDescription?: string
+  countryName?: string
+  continent?: string
+  region?: string
+  isOverride: boolean
+  tags: Array<string>
+  cloudProvider?: string
+  cloudService?: string
+  cloudRegion?: string
+}
+
+/** * Address space announced by an ASN or country
+ * @property {number} ipv4_addresses - IPv4 addresses announced
+ * @property {number} ipv6_slash48s - Distinct IPv6 /48 networks touched by any range
+ * @property {number} ipv4_ranges - Number of IPv4 ranges
+ * @property {number} ipv6_ranges - Number of IPv6 ranges
+ */
+export interface AddressSpace {
+  ipv4Addresses: number
+  ipv6Slash48s: number
+  ipv4Ranges: number
+  ipv6Ranges: number
+}
+
+/** * Address space totals for reporting
+ * @property {AddressSpace} total - Totals over all announced ranges
+ * @property {CountrySpace[]} countries - Per-country totals, most IPv4 addresses first
+ * @property {AsnSpace[]} top_asns_by_ipv4 - ASNs announcing the most IPv4 addresses
+ * @property {AsnSpace[]} top_asns_by_ipv6 - ASNs announcing the most IPv6 /48s
+ */
+export interface AggregatesResult {
+  total: AddressSpace
+  countries: Array<CountrySpace>
+  topAsnsByIpv4: Array<AsnSpace>
+  topAsnsByIpv6: Array<AsnSpace>
+}
+
+/** * ASN catalogue entry
+ * @property {number} as_number - ASN number
+ * @property {string} as_country_code - Country code of the ASN's first range
+ * @property {string} as_description - Description of the ASN's first range
+ * @property {string[]} as_country_codes - Every distinct country code of the ASN's ranges
+ * @property {string[]} as_descriptions - Every distinct description of the ASN's ranges
+ * @property {number} ipv4_ranges - Number of IPv4 ranges announced
+ * @property {number} ipv6_ranges - Number of IPv6 ranges announced
+ */
+export interface AsnDetails {
+  asNumber: number
+  asCountryCode: string
+  asDescription: string
+  asCountryCodes: Array<string>
+  asDescriptions: Array<string>
+  ipv4Ranges: number
+  ipv6Ranges: number
+}
+
+/** * An ASN range record
+ * @property {string} first_ip - First IP in the range
+ * @property {string} last_ip - Last IP in the range
+ * @property {number} as_number - ASN number
+ * @property {string} as_country_code - ASN country code
+ * @property {string} as_description - ASN description
+ */
+export interface AsnRange {
+  firstIp: string
+  lastIp: string
+  asNumber: number
+  asCountryCode: string
+  asDescription: string
+}
+
+/** * Record-level differences between two database versions
+ * @property {AsnRange[]} added - Ranges only in the new database
+ * @property {AsnRange[]} removed - Ranges only in the old database
+ * @property {RangeChange[]} changed - Ranges whose ASN, country or description changed
+ * @property {number[]} asns_added - ASNs only in the new database
+ * @property {number[]} asns_removed - ASNs only in the old database
+ */
+export interface DatabaseDiffResult {
+  added: Array<AsnRange>
+  removed: Array<AsnRange>
+  changed: Array<RangeChange>
+  asnsAdded: Array<number>
+  asnsRemoved: Array<number>
+}
+
+/** * Database event delivered to Node.js
+ * @property {string} kind - Event type ("updated" or "stale")
+ * @property {number | null} record_count - Number of records loaded (updated events)
+ * @property {DatabaseDiffResult | null} diff - Changes from the previous database (updated events)
+ * @property {number | null} age_seconds - Age of the loaded data (stale events)
+ * @property {number | null} max_age_seconds - Configured maximum age (stale events)
+ */
+export interface DatabaseEvent {
+  kind: string
+  recordCount?: number
+  diff?: DatabaseDiffResult
+  ageSeconds?: number
+  maxAgeSeconds?: number
+}
+
+/** * Database version kept in the cache directory
+ * @property {string} id - Version id (Unix timestamp of the download)
+ * @property {string | null} etag - ETag of the download
+ * @property {string | null} last_modified - Upstream Last-Modified of the download
+ * @property {number | null} downloaded_timestamp - When the version was downloaded
+ * @property {number} size - Size of the compressed file in bytes
+ * @property {boolean} current - Whether this is the current cache file
+ * @property {boolean} pinned - Whether this version is pinned
+ */
+export interface DatabaseVersion {
+  id: string
+  etag?: string
+  lastModified?: string
+  downloadedTimestamp?: number
+  size: number
+  current: boolean
+  pinned: boolean
+}
+
+/** * Options for enriching delimited records
+ * @property {number | null} field - Zero-based column holding the IP address (default 0)
+ * @property {string | null} pattern - Regular expression locating the IP address instead of a column
+ * @property {string | null} delimiter - Column delimiter (default ",")
+ */
+export interface EnrichOptions {
+  field?: number
+  pattern?: string
+  delimiter?: string
+}
+
+/** * Address space registered in one country
+ * @property {string} country_code - Country code
+ * @property {number} asns - Distinct ASNs with ranges in the country
+ * @property {AddressSpace} space - Address space totals
+ */
+export interface CountrySpace {
+  countryCode: string
+  asns: number
+  space: AddressSpace
 }
 
 /** * Database statistics
  * @property {number} record_count - Number of records in the database
  * @property {number | null} last_update_timestamp - Timestamp of the last update (null if unknown)
+ * @property {number | null} data_modified_timestamp - Upstream Last-Modified of the loaded data (null if unknown)
+ * @property {number | null} downloaded_timestamp - When the loaded data was downloaded (null if unknown)
+ * @property {number | null} data_age_seconds - Age of the loaded data in seconds (null if unknown)
+ * @property {boolean} stale - Whether the data exceeds the configured maximum age
+ * @property {LookupCacheStats | null} cache - Lookup cache counters (null if the cache is disabled)
  */
 export interface DatabaseStats {
   recordCount: number
   lastUpdateTimestamp?: number
+  dataModifiedTimestamp?: number
+  downloadedTimestamp?: number
+  dataAgeSeconds?: number
+  stale: boolean
+  cache?: LookupCacheStats
+}
+
+/** * Lookup cache counters
+ * @property {number} capacity - Maximum number of cached results
+ * @property {number} entries - Results currently cached
+ * @property {number} hits - Lookups answered from the cache
+ * @property {number} misses - Lookups that searched the database
+ */
+export interface LookupCacheStats {
+  capacity: number
+  entries: number
+  hits: number
+  misses: number
 }
 
+/** * Address space announced by one ASN
+ * @property {number} as_number - ASN number
+ * @property {AddressSpace} space - Address space totals
+ */
+export interface AsnSpace {
+  asNumber: number
+  space: AddressSpace
+}
+
+/** * Compare two database files (gzipped or plain TSV)
+ * @param old_path - Path to the old database file
+ * @param new_path - Path to the new database file
+ * @returns Record-level differences
+ */
+export declare function diffFiles(oldPath: string, newPath: string): Promise<DatabaseDiffResult>
+
 /** * Get the package version
  * @returns The current version of the iptoasn-node package
  */
 export declare function getVersion(): string
+
+/** * A Rust log event forwarded to a JS logger
+ * @property {string} level - "error", "warn", "info", "debug" or "trace"
+ * @property {string} target - Rust module that logged it, e.g. "iptoasn_core::fetcher"
+ * @property {string} message - The formatted message
+ * @property {Record<string, string | number | boolean>} fields - Structured fields of the event and its enclosing spans
+ */
+export interface LogEntry {
+  level: string
+  target: string
+  message: string
+  fields: Record<string, string | number | boolean>
+}
+
+/** * Turn Rust's own log output on or off
+ *
+ * Loggers registered with `setLogger` keep receiving events either way.
+ * @param enabled - false to stop printing Rust logs to stdout
+ */
+export declare function setRustLogging(enabled: boolean): void
+
+/** * A range whose ASN, country or description changed between versions
+ * @property {AsnRange} old - The range in the old database
+ * @property {AsnRange} new - The range in the new database
+ */
+export interface RangeChange {
+  old: AsnRange
+  new: AsnRange
+}
+
+/** * A record overlapping a queried range
+ * @property {AsnRange} range - The overlapping record
+ * @property {string} overlap_first_ip - First address shared with the query
+ * @property {string} overlap_last_ip - Last address shared with the query
+ * @property {number} coverage - Percentage of the queried range covered by this record
+ * @property {number} record_coverage - Percentage of the record inside the queried range
+ */
+export interface RangeMatchResult {
+  range: AsnRange
+  overlapFirstIp: string
+  overlapLastIp: string
+  coverage: number
+  recordCoverage: number
+}
+
+/** * Result of a range or CIDR query
+ * @property {string} first_ip - First address of the queried range
+ * @property {string} last_ip - Last address of the queried range
+ * @property {number} coverage - Percentage of the queried range covered by any record
+ * @property {RangeMatchResult[]} matches - Overlapping records, sorted by range
+ */
+export interface RangeResult {
+  firstIp: string
+  lastIp: string
+  coverage: number
+  matches: Array<RangeMatchResult>
+}
+
+/** * Stable code of an error thrown or rejected by the addon */
+export type ErrorCode =
+  | 'ERR_INVALID_IP'
+  | 'ERR_INVALID_RANGE'
+  | 'ERR_INVALID_PATTERN'
+  | 'ERR_INVALID_ARG'
+  | 'ERR_INVALID_STATE'
+  | 'ERR_NOT_LOADED'
+  | 'ERR_STALE'
+  | 'ERR_HTTP'
+  | 'ERR_HTTP_TIMEOUT'
+  | 'ERR_HTTP_STATUS'
+  | 'ERR_HTTP_RESPONSE'
+  | 'ERR_DECOMPRESS'
+  | 'ERR_PARSE'
+  | 'ERR_CACHE_CORRUPT'
+  | 'ERR_CACHE_LOCKED'
+  | 'ERR_VERSION_NOT_FOUND'
+  | 'ERR_IO'
+  | 'ERR_INTERNAL'
+
+/** * Error thrown or rejected by the addon; fields besides `code` depend on the code
+ * @property {ErrorCode} code - Stable error code
+ * @property {number} statusCode - HTTP status (ERR_HTTP_STATUS)
+ * @property {string} url - Database URL (ERR_HTTP_TIMEOUT)
+ * @property {string} path - Cache file (ERR_CACHE_CORRUPT, ERR_CACHE_LOCKED)
+ * @property {string} input - Rejected input (ERR_INVALID_IP, ERR_INVALID_RANGE)
+ * @property {string} versionId - Requested version (ERR_VERSION_NOT_FOUND)
+ * @property {number} errno - OS error number (ERR_IO)
+ * @property {number} ageSeconds - Age of the data (ERR_STALE)
+ * @property {number} maxAgeSeconds - Maximum allowed age (ERR_STALE)
+ */
+export interface IpToAsnError extends Error {
+  code: ErrorCode
+  statusCode?: number
+  url?: string
+  path?: string
+  input?: string
+  versionId?: string
+  errno?: number
+  ageSeconds?: number
+  maxAgeSeconds?: number
+}
@@ -0,0 +1,103 @@
+use iptoasn_core::AppError;
+use napi::bindgen_prelude::*;
+use std::future::Future;
+
+// Bad arguments from JS, caught before reaching the database
+pub const INVALID_ARG: &str = "ERR_INVALID_ARG";
+// Calls that don't fit the instance's state, e.g. starting a running updater
+pub const INVALID_STATE: &str = "ERR_INVALID_STATE";
+// Failures of the binding itself, e.g. a panicked background task
+pub const INTERNAL: &str = "ERR_INTERNAL";
+
+type FieldValue = Either<String, f64>;
+
+/**
+ * An error on its way to JS
+ *
+ * Thrown as an `Error` with a stable `code` and, depending on the code,
+ * structured fields such as `statusCode`. It holds no JS values, so async
+ * methods can carry it off the JS thread and convert it when settling.
+ */
+pub struct Failure {
+    code: &'static str,
+    message: String,
+    fields: Vec<(&'static str, FieldValue)>,
+}
+
+impl Failure {
+    pub fn new(code: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+            fields: Vec::new(),
+        }
+    }
+
+    pub fn invalid_arg(message: impl Into<String>) -> Self {
+        Self::new(INVALID_ARG, message)
+    }
+
+    pub fn invalid_state(message: impl Into<String>) -> Self {
+        Self::new(INVALID_STATE, message)
+    }
+
+    pub fn internal(message: impl Into<String>) -> Self {
+        Self::new(INTERNAL, message)
+    }
+
+    // A core error, with what was being done prefixed to its message
+    pub fn app(context: &str, e: AppError) -> Self {
+        let failure = Self::new(e.code(), format!("{}: {}", context, e));
+        match e {
+            AppError::HttpTimeout(url) => failure.field("url", Either::A(url)),
+            AppError::HttpStatus { status, .. } => failure.field("statusCode", Either::B(status.into())),
+            AppError::CacheCorrupt { path, .. } => failure.field("path", Either::A(path)),
+            AppError::CacheLockTimeout(path) => failure.field("path", Either::A(path)),
+            AppError::InvalidIp(input) => failure.field("input", Either::A(input)),
+            AppError::InvalidRange(input) => failure.field("input", Either::A(input)),
+            AppError::VersionNotFound(id) => failure.field("versionId", Either::A(id)),
+            AppError::Io(e) => match e.raw_os_error() {
+                Some(errno) => failure.field("errno", Either::B(errno.into())),
+                None => failure,
+            },
+            AppError::StaleDatabase { age_secs, max_age_secs } => failure
+                .field("ageSeconds", Either::B(age_secs as f64))
+                .field("maxAgeSeconds", Either::B(max_age_secs as f64)),
+            _ => failure,
+        }
+    }
+
+    fn field(mut self, name: &'static str, value: FieldValue) -> Self {
+        self.fields.push((name, value));
+        self
+    }
+
+    /**
+     * Build the JS error; must run on the JS thread
+     */
+    pub fn into_error(self, env: &Env) -> Error {
+        let build = || -> Result<Error> {
+            let mut error = env.create_error(Error::from_reason(self.message.clone()))?;
+            error.set("code", self.code)?;
+            for (name, value) in self.fields {
+                error.set(name, value)?;
+            }
+            Ok(Error::from(error.to_unknown()))
+        };
+        // Still throw something meaningful if the env can't build the object
+        build().unwrap_or_else(|_| Error::from_reason(self.message))
+    }
+}
+
+/**
+ * Run a future on the tokio runtime, rejecting with a coded JS error on failure
+ */
+pub fn spawn<'env, T, F>(env: &'env Env, future: F) -> Result<PromiseRaw<'env, T>>
+where
+    T: ToNapiValue + Send + 'static,
+    F: Future<Output = std::result::Result<T, Failure>> + Send + 'static,
+{
+    env.spawn_future_with_callback(async move { Ok(future.await) }, |env, result| {
+        result.map_err(|failure| failure.into_error(env))
+    })
+}
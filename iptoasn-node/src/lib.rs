@@ -1,24 +1,119 @@
-use iptoasn_core::{AsnInfo, DbStats, IpToAsnDb};
+mod errors;
+mod logging;
+
+use iptoasn_core::{
+    override_record, Aggregates, AsnAggregate, AsnEntry, AsnInfo, AsnRecord, CountryAggregate,
+    Database, DatabaseDiff, DbEvent, DbStats, IpField, IpRange, IpToAsnDb, MaxAgePolicy, ProviderFormat,
+    RangeLookup, RangeMatch, RecordChange, SpaceTotals, StaleAction, VersionInfo,
+};
 use napi::bindgen_prelude::*;
+use napi::threadsafe_function::{ThreadsafeFunction, ThreadsafeFunctionCallMode};
+use errors::Failure;
 use napi_derive::napi;
+use std::net::{IpAddr, Ipv4Addr};
+use std::sync::atomic::{AtomicU32, Ordering};
 use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+use tokio::sync::broadcast;
 use tokio::task::JoinHandle;
+use tracing::Instrument;
 
 /**
  *  Initialize tracing for the Rust side (called once)
  */ 
-fn init_tracing() {
+#[cfg_attr(not(feature = "otlp"), allow(unused_variables))]
+fn init_tracing(env: &Env) {
     use std::sync::Once;
+    use tracing_subscriber::filter::{filter_fn, FilterExt};
+    use tracing_subscriber::layer::SubscriberExt;
+    use tracing_subscriber::util::SubscriberInitExt;
+    use tracing_subscriber::Layer;
     static INIT: Once = Once::new();
     
+    // Each layer filters on its own, so JS loggers can see more than RUST_LOG
+    let env_filter = || {
+        tracing_subscriber::EnvFilter::try_from_default_env().unwrap_or_else(|_| "info".into())
+    };
+
     INIT.call_once(|| {
-        tracing_subscriber::fmt()
-            .with_env_filter(
-                tracing_subscriber::EnvFilter::try_from_default_env()
-                    .unwrap_or_else(|_| "info".into()),
+        let registry = tracing_subscriber::registry()
+            .with(
+                tracing_subscriber::fmt::layer()
+                    .with_filter(env_filter().and(filter_fn(logging::stdout_enabled))),
             )
-            .init();
+            .with(logging::JsLogLayer.with_filter(filter_fn(logging::js_enabled)));
+
+        #[cfg(feature = "otlp")]
+        let registry = registry.with(otlp_layer().with_filter(env_filter()));
+
+        registry.init();
     });
+
+    #[cfg(feature = "otlp")]
+    flush_spans_on_exit(env);
+}
+
+// Kept for the process; dropping the provider would shut the exporter down
+#[cfg(feature = "otlp")]
+static OTLP_PROVIDER: std::sync::OnceLock<iptoasn_core::telemetry::SdkTracerProvider> =
+    std::sync::OnceLock::new();
+
+/**
+ * Flush buffered spans when the process exits
+ *
+ * Uses an `exit` listener, which also runs for `process.exit()`, unlike env
+ * cleanup hooks. Flushes rather than shutting the exporter down, since
+ * worker threads share it with the main thread.
+ */
+#[cfg(feature = "otlp")]
+fn flush_spans_on_exit(env: &Env) {
+    thread_local! {
+        static HOOKED: std::cell::Cell<bool> = const { std::cell::Cell::new(false) };
+    }
+    if HOOKED.replace(true) {
+        return;
+    }
+    let listen = || -> Result<()> {
+        let process: Object = env.get_global()?.get_named_property("process")?;
+        type Listener<'env> = Function<'env, (), ()>;
+        let on: Function<FnArgs<(String, Listener)>, Unknown> = process.get_named_property("on")?;
+        let flush = env.create_function_from_closure::<(), (), _>("flushSpans", |_| {
+            if let Some(provider) = OTLP_PROVIDER.get() {
+                if let Err(e) = provider.force_flush() {
+                    eprintln!("iptoasn: failed to flush OTLP spans: {}", e);
+                }
+            }
+            Ok(())
+        })?;
+        on.apply(process, ("exit".to_string(), flush).into())?;
+        Ok(())
+    };
+    if let Err(e) = listen() {
+        eprintln!("iptoasn: spans may be lost at exit: {}", e);
+    }
+}
+
+/**
+ * Span exporter layer when built with the `otlp` feature and an endpoint is set
+ */
+#[cfg(feature = "otlp")]
+fn otlp_layer<S>() -> Option<iptoasn_core::telemetry::OtlpLayer<S>>
+where
+    S: tracing::Subscriber + for<'span> tracing_subscriber::registry::LookupSpan<'span>,
+{
+    use iptoasn_core::telemetry::otlp_layer;
+
+    match otlp_layer("iptoasn-node") {
+        Ok(Some(otlp)) => {
+            let _ = OTLP_PROVIDER.set(otlp.provider);
+            Some(otlp.layer)
+        }
+        Ok(None) => None,
+        Err(e) => {
+            eprintln!("iptoasn: failed to start OTLP exporter: {}", e);
+            None
+        }
+    }
 }
 /**
  * ASN lookup result returned to Node.js
@@ -27,8 +122,16 @@ fn init_tracing() {
  * @property {string | null} first_ip - First IP in the ASN range (null if not announced)
  * @property {string | null} last_ip - Last IP in the ASN range (null if not announced)
  * @property {number | null} as_number - ASN number (null if not announced)
- * @property {string | null} as_country_code - ASN country code (null if not announced)
+ * @property {string | null} as_country_code - ASN country code (null if not announced or unknown)
  * @property {string | null} as_description - ASN description (null if not announced)
+ * @property {string | null} country_name - Country name (null if the code is unknown)
+ * @property {string | null} continent - Continent name (null if the code is unknown)
+ * @property {string | null} region - UN M49 sub-region, e.g. "Western Europe" (null if the code is unknown)
+ * @property {boolean} is_override - Whether the result came from a user-supplied override
+ * @property {string[]} tags - User tags of the ASN and of ranges containing the address
+ * @property {string | null} cloud_provider - Cloud or hosting provider publishing the address, e.g. "AWS"
+ * @property {string | null} cloud_service - Provider service, e.g. "EC2" (null if not published)
+ * @property {string | null} cloud_region - Provider region, e.g. "us-east-1" (null if not published)
  */
 #[napi(object)]
 pub struct AsnResult {
@@ -39,6 +142,14 @@ pub struct AsnResult {
     pub as_number: Option<u32>,
     pub as_country_code: Option<String>,
     pub as_description: Option<String>,
+    pub country_name: Option<String>,
+    pub continent: Option<String>,
+    pub region: Option<String>,
+    pub is_override: bool,
+    pub tags: Vec<String>,
+    pub cloud_provider: Option<String>,
+    pub cloud_service: Option<String>,
+    pub cloud_region: Option<String>,
 }
 /**
  * Convert from internal AsnInfo to AsnResult
@@ -53,6 +164,14 @@ impl From<AsnInfo> for AsnResult {
             as_number: info.as_number,
             as_country_code: info.as_country_code,
             as_description: info.as_description,
+            country_name: info.country_name,
+            continent: info.continent,
+            region: info.region,
+            is_override: info.is_override,
+            tags: info.tags,
+            cloud_provider: info.cloud_provider,
+            cloud_service: info.cloud_service,
+            cloud_region: info.cloud_region,
         }
     }
 }
@@ -60,11 +179,35 @@ impl From<AsnInfo> for AsnResult {
  * Database statistics
  * @property {number} record_count - Number of records in the database
  * @property {number | null} last_update_timestamp - Timestamp of the last update (null if unknown)
+ * @property {number | null} data_modified_timestamp - Upstream Last-Modified of the loaded data (null if unknown)
+ * @property {number | null} downloaded_timestamp - When the loaded data was downloaded (null if unknown)
+ * @property {number | null} data_age_seconds - Age of the loaded data in seconds (null if unknown)
+ * @property {boolean} stale - Whether the data exceeds the configured maximum age
+ * @property {LookupCacheStats | null} cache - Lookup cache counters (null if the cache is disabled)
  */
 #[napi(object)]
 pub struct DatabaseStats {
     pub record_count: i64,
     pub last_update_timestamp: Option<i64>,
+    pub data_modified_timestamp: Option<i64>,
+    pub downloaded_timestamp: Option<i64>,
+    pub data_age_seconds: Option<i64>,
+    pub stale: bool,
+    pub cache: Option<LookupCacheStats>,
+}
+/**
+ * Lookup cache counters
+ * @property {number} capacity - Maximum number of cached results
+ * @property {number} entries - Results currently cached
+ * @property {number} hits - Lookups answered from the cache
+ * @property {number} misses - Lookups that searched the database
+ */
+#[napi(object)]
+pub struct LookupCacheStats {
+    pub capacity: i64,
+    pub entries: i64,
+    pub hits: i64,
+    pub misses: i64,
 }
 /**
  * Convert from internal DbStats to DatabaseStats
@@ -73,14 +216,430 @@ impl From<DbStats> for DatabaseStats {
     fn from(stats: DbStats) -> Self {
         Self {
             record_count: stats.record_count as i64,
-            last_update_timestamp: stats.last_update.and_then(|t| {
-                t.duration_since(std::time::UNIX_EPOCH)
-                    .ok()
-                    .map(|d| d.as_secs() as i64)
+            last_update_timestamp: stats.last_update.and_then(unix_timestamp),
+            data_modified_timestamp: stats.data_modified.and_then(unix_timestamp),
+            downloaded_timestamp: stats.downloaded_at.and_then(unix_timestamp),
+            data_age_seconds: stats.data_age.map(|d| d.as_secs() as i64),
+            stale: stats.stale,
+            cache: stats.cache.map(|cache| LookupCacheStats {
+                capacity: cache.capacity as i64,
+                entries: cache.entries as i64,
+                hits: cache.hits as i64,
+                misses: cache.misses as i64,
             }),
         }
     }
 }
+/**
+ * Read a 4-byte IPv4 or 16-byte IPv6 address in network order
+ */
+fn address_from_bytes(bytes: &[u8]) -> Option<IpAddr> {
+    match bytes.len() {
+        4 => <[u8; 4]>::try_from(bytes).ok().map(IpAddr::from),
+        16 => <[u8; 16]>::try_from(bytes).ok().map(IpAddr::from),
+        _ => None,
+    }
+}
+/**
+ * Convert a timestamp to Unix seconds
+ */
+fn unix_timestamp(time: SystemTime) -> Option<i64> {
+    time.duration_since(std::time::UNIX_EPOCH)
+        .ok()
+        .map(|d| d.as_secs() as i64)
+}
+/**
+ * Database version kept in the cache directory
+ * @property {string} id - Version id (Unix timestamp of the download)
+ * @property {string | null} etag - ETag of the download
+ * @property {string | null} last_modified - Upstream Last-Modified of the download
+ * @property {number | null} downloaded_timestamp - When the version was downloaded
+ * @property {number} size - Size of the compressed file in bytes
+ * @property {boolean} current - Whether this is the current cache file
+ * @property {boolean} pinned - Whether this version is pinned
+ */
+#[napi(object)]
+pub struct DatabaseVersion {
+    pub id: String,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    pub downloaded_timestamp: Option<i64>,
+    pub size: i64,
+    pub current: bool,
+    pub pinned: bool,
+}
+/**
+ * Convert from internal VersionInfo to DatabaseVersion
+ */
+impl From<VersionInfo> for DatabaseVersion {
+    fn from(version: VersionInfo) -> Self {
+        Self {
+            id: version.id,
+            etag: version.etag,
+            last_modified: version.last_modified,
+            downloaded_timestamp: version.downloaded_at.and_then(unix_timestamp),
+            size: version.size as i64,
+            current: version.current,
+            pinned: version.pinned,
+        }
+    }
+}
+/**
+ * An ASN range record
+ * @property {string} first_ip - First IP in the range
+ * @property {string} last_ip - Last IP in the range
+ * @property {number} as_number - ASN number
+ * @property {string} as_country_code - ASN country code
+ * @property {string} as_description - ASN description
+ */
+#[napi(object)]
+pub struct AsnRange {
+    pub first_ip: String,
+    pub last_ip: String,
+    pub as_number: u32,
+    pub as_country_code: String,
+    pub as_description: String,
+}
+/**
+ * Convert from internal AsnRecord to AsnRange
+ */
+impl From<&AsnRecord> for AsnRange {
+    fn from(record: &AsnRecord) -> Self {
+        Self {
+            first_ip: record.first_ip.to_string(),
+            last_ip: record.last_ip.to_string(),
+            as_number: record.number,
+            as_country_code: record.country.to_string(),
+            as_description: record.description.to_string(),
+        }
+    }
+}
+/**
+ * ASN catalogue entry
+ * @property {number} as_number - ASN number
+ * @property {string} as_country_code - Country code of the ASN's first range
+ * @property {string} as_description - Description of the ASN's first range
+ * @property {string[]} as_country_codes - Every distinct country code of the ASN's ranges
+ * @property {string[]} as_descriptions - Every distinct description of the ASN's ranges
+ * @property {number} ipv4_ranges - Number of IPv4 ranges announced
+ * @property {number} ipv6_ranges - Number of IPv6 ranges announced
+ */
+#[napi(object)]
+pub struct AsnDetails {
+    pub as_number: u32,
+    pub as_country_code: String,
+    pub as_description: String,
+    pub as_country_codes: Vec<String>,
+    pub as_descriptions: Vec<String>,
+    pub ipv4_ranges: u32,
+    pub ipv6_ranges: u32,
+}
+/**
+ * Convert from internal AsnEntry to AsnDetails
+ */
+impl From<AsnEntry> for AsnDetails {
+    fn from(entry: AsnEntry) -> Self {
+        Self {
+            as_number: entry.number,
+            as_country_code: entry.country.to_string(),
+            as_description: entry.description.to_string(),
+            as_country_codes: entry.countries.iter().map(|c| c.to_string()).collect(),
+            as_descriptions: entry.descriptions.iter().map(|d| d.to_string()).collect(),
+            ipv4_ranges: entry.ipv4_ranges as u32,
+            ipv6_ranges: entry.ipv6_ranges as u32,
+        }
+    }
+}
+/**
+ * Address space announced by an ASN or country
+ * @property {number} ipv4_addresses - IPv4 addresses announced
+ * @property {number} ipv6_slash48s - Distinct IPv6 /48 networks touched by any range
+ * @property {number} ipv4_ranges - Number of IPv4 ranges
+ * @property {number} ipv6_ranges - Number of IPv6 ranges
+ */
+#[napi(object)]
+pub struct AddressSpace {
+    pub ipv4_addresses: i64,
+    pub ipv6_slash48s: i64,
+    pub ipv4_ranges: u32,
+    pub ipv6_ranges: u32,
+}
+/**
+ * Convert from internal SpaceTotals to AddressSpace
+ */
+impl From<&SpaceTotals> for AddressSpace {
+    fn from(totals: &SpaceTotals) -> Self {
+        Self {
+            ipv4_addresses: totals.ipv4_addresses as i64,
+            ipv6_slash48s: totals.ipv6_48s as i64,
+            ipv4_ranges: totals.ipv4_ranges as u32,
+            ipv6_ranges: totals.ipv6_ranges as u32,
+        }
+    }
+}
+/**
+ * Address space announced by one ASN
+ * @property {number} as_number - ASN number
+ * @property {AddressSpace} space - Address space totals
+ */
+#[napi(object)]
+pub struct AsnSpace {
+    pub as_number: u32,
+    pub space: AddressSpace,
+}
+/**
+ * Convert from internal AsnAggregate to AsnSpace
+ */
+impl From<&AsnAggregate> for AsnSpace {
+    fn from(aggregate: &AsnAggregate) -> Self {
+        Self {
+            as_number: aggregate.number,
+            space: AddressSpace::from(&aggregate.totals),
+        }
+    }
+}
+/**
+ * Address space registered in one country
+ * @property {string} country_code - Country code
+ * @property {number} asns - Distinct ASNs with ranges in the country
+ * @property {AddressSpace} space - Address space totals
+ */
+#[napi(object)]
+pub struct CountrySpace {
+    pub country_code: String,
+    pub asns: u32,
+    pub space: AddressSpace,
+}
+/**
+ * Convert from internal CountryAggregate to CountrySpace
+ */
+impl From<&CountryAggregate> for CountrySpace {
+    fn from(aggregate: &CountryAggregate) -> Self {
+        Self {
+            country_code: aggregate.country.to_string(),
+            asns: aggregate.asns as u32,
+            space: AddressSpace::from(&aggregate.totals),
+        }
+    }
+}
+/**
+ * Address space totals for reporting
+ * @property {AddressSpace} total - Totals over all announced ranges
+ * @property {CountrySpace[]} countries - Per-country totals, most IPv4 addresses first
+ * @property {AsnSpace[]} top_asns_by_ipv4 - ASNs announcing the most IPv4 addresses
+ * @property {AsnSpace[]} top_asns_by_ipv6 - ASNs announcing the most IPv6 /48s
+ */
+#[napi(object)]
+pub struct AggregatesResult {
+    pub total: AddressSpace,
+    pub countries: Vec<CountrySpace>,
+    pub top_asns_by_ipv4: Vec<AsnSpace>,
+    pub top_asns_by_ipv6: Vec<AsnSpace>,
+}
+impl AggregatesResult {
+    fn new(aggregates: &Aggregates, top: usize) -> Self {
+        Self {
+            total: AddressSpace::from(&aggregates.total),
+            countries: aggregates.countries.iter().map(CountrySpace::from).collect(),
+            top_asns_by_ipv4: aggregates.top_asns_by_ipv4(top).into_iter().map(AsnSpace::from).collect(),
+            top_asns_by_ipv6: aggregates.top_asns_by_ipv6(top).into_iter().map(AsnSpace::from).collect(),
+        }
+    }
+}
+/**
+ * A record overlapping a queried range
+ * @property {AsnRange} range - The overlapping record
+ * @property {string} overlap_first_ip - First address shared with the query
+ * @property {string} overlap_last_ip - Last address shared with the query
+ * @property {number} coverage - Percentage of the queried range covered by this record
+ * @property {number} record_coverage - Percentage of the record inside the queried range
+ */
+#[napi(object)]
+pub struct RangeMatchResult {
+    pub range: AsnRange,
+    pub overlap_first_ip: String,
+    pub overlap_last_ip: String,
+    pub coverage: f64,
+    pub record_coverage: f64,
+}
+/**
+ * Convert from internal RangeMatch to RangeMatchResult
+ */
+impl From<&RangeMatch> for RangeMatchResult {
+    fn from(m: &RangeMatch) -> Self {
+        Self {
+            range: AsnRange::from(&m.record),
+            overlap_first_ip: m.overlap.first.to_string(),
+            overlap_last_ip: m.overlap.last.to_string(),
+            coverage: m.coverage,
+            record_coverage: m.record_coverage,
+        }
+    }
+}
+/**
+ * Result of a range or CIDR query
+ * @property {string} first_ip - First address of the queried range
+ * @property {string} last_ip - Last address of the queried range
+ * @property {number} coverage - Percentage of the queried range covered by any record
+ * @property {RangeMatchResult[]} matches - Overlapping records, sorted by range
+ */
+#[napi(object)]
+pub struct RangeResult {
+    pub first_ip: String,
+    pub last_ip: String,
+    pub coverage: f64,
+    pub matches: Vec<RangeMatchResult>,
+}
+/**
+ * Convert from internal RangeLookup to RangeResult
+ */
+impl From<RangeLookup> for RangeResult {
+    fn from(lookup: RangeLookup) -> Self {
+        Self {
+            first_ip: lookup.range.first.to_string(),
+            last_ip: lookup.range.last.to_string(),
+            coverage: lookup.coverage,
+            matches: lookup.matches.iter().map(RangeMatchResult::from).collect(),
+        }
+    }
+}
+/**
+ * A range whose ASN, country or description changed between versions
+ * @property {AsnRange} old - The range in the old database
+ * @property {AsnRange} new - The range in the new database
+ */
+#[napi(object)]
+pub struct RangeChange {
+    pub old: AsnRange,
+    pub new: AsnRange,
+}
+/**
+ * Convert from internal RecordChange to RangeChange
+ */
+impl From<&RecordChange> for RangeChange {
+    fn from(change: &RecordChange) -> Self {
+        Self {
+            old: (&change.old).into(),
+            new: (&change.new).into(),
+        }
+    }
+}
+/**
+ * Record-level differences between two database versions
+ * @property {AsnRange[]} added - Ranges only in the new database
+ * @property {AsnRange[]} removed - Ranges only in the old database
+ * @property {RangeChange[]} changed - Ranges whose ASN, country or description changed
+ * @property {number[]} asns_added - ASNs only in the new database
+ * @property {number[]} asns_removed - ASNs only in the old database
+ */
+#[napi(object)]
+pub struct DatabaseDiffResult {
+    pub added: Vec<AsnRange>,
+    pub removed: Vec<AsnRange>,
+    pub changed: Vec<RangeChange>,
+    pub asns_added: Vec<u32>,
+    pub asns_removed: Vec<u32>,
+}
+/**
+ * Convert from internal DatabaseDiff to DatabaseDiffResult
+ */
+impl From<&DatabaseDiff> for DatabaseDiffResult {
+    fn from(diff: &DatabaseDiff) -> Self {
+        Self {
+            added: diff.added.iter().map(AsnRange::from).collect(),
+            removed: diff.removed.iter().map(AsnRange::from).collect(),
+            changed: diff.changed.iter().map(RangeChange::from).collect(),
+            asns_added: diff.asns_added.clone(),
+            asns_removed: diff.asns_removed.clone(),
+        }
+    }
+}
+/**
+ * Database event delivered to Node.js
+ * @property {string} kind - Event type ("updated" or "stale")
+ * @property {number | null} record_count - Number of records loaded (updated events)
+ * @property {DatabaseDiffResult | null} diff - Changes from the previous database (updated events)
+ * @property {number | null} age_seconds - Age of the loaded data (stale events)
+ * @property {number | null} max_age_seconds - Configured maximum age (stale events)
+ */
+#[napi(object)]
+pub struct DatabaseEvent {
+    pub kind: String,
+    pub record_count: Option<i64>,
+    pub diff: Option<DatabaseDiffResult>,
+    pub age_seconds: Option<i64>,
+    pub max_age_seconds: Option<i64>,
+}
+/**
+ * Convert from internal DbEvent to DatabaseEvent
+ */
+impl From<DbEvent> for DatabaseEvent {
+    fn from(event: DbEvent) -> Self {
+        match event {
+            DbEvent::Updated { record_count, diff } => Self {
+                kind: "updated".to_string(),
+                record_count: Some(record_count as i64),
+                diff: diff.as_deref().map(DatabaseDiffResult::from),
+                age_seconds: None,
+                max_age_seconds: None,
+            },
+            DbEvent::Stale { age, max_age } => Self {
+                kind: "stale".to_string(),
+                record_count: None,
+                diff: None,
+                age_seconds: Some(age.as_secs() as i64),
+                max_age_seconds: Some(max_age.as_secs() as i64),
+            },
+        }
+    }
+}
+
+/**
+ * Options for enriching delimited records
+ * @property {number | null} field - Zero-based column holding the IP address (default 0)
+ * @property {string | null} pattern - Regular expression locating the IP address instead of a column
+ * @property {string | null} delimiter - Column delimiter (default ",")
+ */
+#[napi(object)]
+pub struct EnrichOptions {
+    pub field: Option<u32>,
+    pub pattern: Option<String>,
+    pub delimiter: Option<String>,
+}
+
+/**
+ * Enricher appends AS number, country and description columns to delimited records.
+ */
+#[napi]
+pub struct Enricher {
+    inner: Arc<iptoasn_core::Enricher>,
+}
+
+#[napi]
+impl Enricher {
+    /**
+     * Append the column names to a header line
+     * @param line - Header line without its line terminator
+     */
+    #[napi]
+    pub fn enrich_header(&self, line: String) -> String {
+        self.inner.enrich_header(&line)
+    }
+    /**
+     * Enrich a batch of lines off the main thread
+     * @param lines - Lines without their line terminators
+     * @returns Enriched lines in the same order
+     */
+    #[napi]
+    pub fn enrich_lines<'env>(&self, env: &'env Env, lines: Vec<String>) -> Result<PromiseRaw<'env, Vec<String>>> {
+        let inner = self.inner.clone();
+        errors::spawn(env, async move {
+            tokio::task::spawn_blocking(move || inner.enrich_lines(&lines))
+                .await
+                .map_err(|e| Failure::internal(format!("Enrichment failed: {}", e)))
+        })
+    }
+}
 
 /**
  * IpToAsn class provides IP to ASN lookup functionality with automatic database updates.
@@ -89,6 +648,17 @@ impl From<DbStats> for DatabaseStats {
 pub struct IpToAsn {
     db: Arc<IpToAsnDb>,
     updater_handle: Arc<Mutex<Option<JoinHandle<()>>>>,
+    tags_watcher: Arc<Mutex<Option<JoinHandle<()>>>>,
+    // Identifies this instance's logs for its logger
+    id: u32,
+}
+
+static NEXT_INSTANCE: AtomicU32 = AtomicU32::new(1);
+
+impl Drop for IpToAsn {
+    fn drop(&mut self) {
+        logging::remove_logger(self.id);
+    }
 }
 
 #[napi]
@@ -99,16 +669,22 @@ impl IpToAsn {
      * @param cache_dir - Directory for caching downloaded databases
      */
     #[napi(constructor)]
-    pub fn new(url: String, cache_dir: String) -> Result<Self> {
-        init_tracing();
-        tracing::info!("Creating IpToAsn instance: url={}, cache_dir={}", url, cache_dir);
+    pub fn new(env: &Env, url: String, cache_dir: String) -> Result<Self> {
+        init_tracing(env);
+        let id = NEXT_INSTANCE.fetch_add(1, Ordering::Relaxed);
+        let span = tracing::info_span!(parent: None, logging::INSTANCE_SPAN, instance = id);
+        span.in_scope(|| {
+            tracing::info!("Creating IpToAsn instance: url={}, cache_dir={}", url, cache_dir)
+        });
         
         let db = IpToAsnDb::new(url, cache_dir)
-            .map_err(|e| Error::from_reason(format!("Failed to create database: {}", e)))?;
+            .map_err(|e| Failure::app("Failed to create database", e).into_error(env))?;
         
         Ok(Self {
             db: Arc::new(db),
             updater_handle: Arc::new(Mutex::new(None)),
+            tags_watcher: Arc::new(Mutex::new(None)),
+            id,
         })
     }
     /**
@@ -116,16 +692,31 @@ impl IpToAsn {
      * @returns Promise that resolves when loading is complete
      */
     #[napi]
-    pub async fn load(&self) -> Result<()> {
-        tracing::info!("Loading database...");
-        
-        self.db
-            .load()
-            .await
-            .map_err(|e| Error::from_reason(format!("Failed to load database: {}", e)))?;
-        
-        tracing::info!("Database loaded successfully");
-        Ok(())
+    pub fn load<'env>(&self, env: &'env Env) -> Result<PromiseRaw<'env, ()>> {
+        let db = self.db.clone();
+        self.spawn(env, async move {
+            tracing::info!("Loading database...");
+
+            db.load()
+                .await
+                .map_err(|e| Failure::app("Failed to load database", e))?;
+
+            tracing::info!("Database loaded successfully");
+            Ok(())
+        })
+    }
+    /**
+     * Load the cached database immediately and refresh it in the background
+     * @returns Promise that resolves as soon as lookups are available
+     */
+    #[napi]
+    pub fn load_cached_first<'env>(&self, env: &'env Env) -> Result<PromiseRaw<'env, ()>> {
+        let db = self.db.clone();
+        self.spawn(env, async move {
+            db.load_cached_first()
+                .await
+                .map_err(|e| Failure::app("Failed to load database", e))
+        })
     }
     /**
      * Look up an IP address
@@ -133,11 +724,358 @@ impl IpToAsn {
      * @returns ASN information or null if not found
      */
     #[napi]
-    pub fn lookup(&self, ip: String) -> Result<AsnResult> {
+    pub fn lookup(&self, env: &Env, ip: String) -> Result<AsnResult> {
         self.db
             .lookup(&ip)
             .map(AsnResult::from)
-            .map_err(|e| Error::from_reason(format!("Lookup failed: {}", e)))
+            .map_err(|e| Failure::app("Lookup failed", e).into_error(env))
+    }
+    /**
+     * Look up an IPv4 address given as a 32-bit integer
+     * @param ip - Address in host order, e.g. 0x01010101 for 1.1.1.1
+     * @returns ASN information
+     */
+    #[napi]
+    pub fn lookup_v4(&self, env: &Env, ip: u32) -> Result<AsnResult> {
+        self.lookup_addr(env, IpAddr::V4(Ipv4Addr::from(ip)))
+    }
+    /**
+     * Look up an address given as raw bytes
+     * @param ip - 4-byte IPv4 or 16-byte IPv6 address in network order
+     * @returns ASN information
+     */
+    #[napi]
+    pub fn lookup_bytes(&self, env: &Env, ip: Buffer) -> Result<AsnResult> {
+        let addr = address_from_bytes(&ip).ok_or_else(|| {
+            Failure::invalid_arg(format!("Lookup failed: expected 4 or 16 bytes, got {}", ip.len()))
+                .into_error(env)
+        })?;
+        self.lookup_addr(env, addr)
+    }
+    /**
+     * Look up packed addresses in one call
+     * @param ips - Concatenated addresses in network order
+     * @param address_size - 4 for IPv4 or 16 for IPv6 addresses (default 4)
+     * @returns The ASN of each address in order, 0 when not announced; records
+     * and overrides for AS0 ("Not routed") also read as 0
+     */
+    #[napi]
+    pub fn lookup_buffer(&self, env: &Env, ips: Buffer, address_size: Option<u32>) -> Result<Uint32Array> {
+        let size = address_size.unwrap_or(4) as usize;
+        if size != 4 && size != 16 {
+            return Err(Failure::invalid_arg(format!("Address size must be 4 or 16, got {}", size)).into_error(env));
+        }
+        if !ips.len().is_multiple_of(size) {
+            return Err(Failure::invalid_arg(format!(
+                "Buffer length {} is not a multiple of {}",
+                ips.len(),
+                size
+            ))
+            .into_error(env));
+        }
+
+        self.db
+            // Chunks are 4 or 16 bytes, checked above, so every one converts and
+            // the output lines up with the input
+            .lookup_numbers(
+                ips.chunks_exact(size)
+                    .map(|chunk| address_from_bytes(chunk).expect("chunk is 4 or 16 bytes")),
+            )
+            .map(Uint32Array::new)
+            .map_err(|e| Failure::app("Lookup failed", e).into_error(env))
+    }
+    fn lookup_addr(&self, env: &Env, ip: IpAddr) -> Result<AsnResult> {
+        self.db
+            .lookup_addr(ip)
+            .map(|result| result.to_info(&ip.to_string()).into())
+            .map_err(|e| Failure::app("Lookup failed", e).into_error(env))
+    }
+    // Run a future in this instance's span, settling a promise with its result
+    fn spawn<'env, T, F>(&self, env: &'env Env, future: F) -> Result<PromiseRaw<'env, T>>
+    where
+        T: ToNapiValue + Send + 'static,
+        F: std::future::Future<Output = std::result::Result<T, Failure>> + Send + 'static,
+    {
+        errors::spawn(env, future.instrument(self.span()))
+    }
+    // A root span per call, so each call is its own trace tagged with the instance
+    fn span(&self) -> tracing::Span {
+        tracing::info_span!(parent: None, logging::INSTANCE_SPAN, instance = self.id)
+    }
+    /**
+     * Find every record overlapping a CIDR prefix or address range
+     *
+     * Only upstream records are reported; overrides are not merged in, so an
+     * address covered by an override can resolve differently in `lookup`.
+     * @param range - "203.0.113.0/22", "10.0.0.0-10.255.255.255" or a single address
+     * @returns Overlapping records with coverage percentages
+     */
+    #[napi]
+    pub fn lookup_range(&self, env: &Env, range: String) -> Result<RangeResult> {
+        self.db
+            .lookup_range(&range)
+            .map(RangeResult::from)
+            .map_err(|e| Failure::app("Range lookup failed", e).into_error(env))
+    }
+    /**
+     * List every ASN in the database
+     * @returns ASNs sorted by number
+     */
+    #[napi]
+    pub fn list_asns(&self) -> Vec<AsnDetails> {
+        self.db.list_asns().into_iter().map(AsnDetails::from).collect()
+    }
+    /**
+     * Get catalogue details for one ASN
+     * @param as_number - ASN number
+     * @returns ASN details or null if the ASN isn't in the database
+     */
+    #[napi]
+    pub fn get_asn(&self, as_number: u32) -> Option<AsnDetails> {
+        self.db.asn(as_number).map(AsnDetails::from)
+    }
+    /**
+     * Search ASN descriptions, case-insensitively
+     * @param query - Words that must all appear in the description, e.g. "AMAZON"
+     * @param limit - Maximum number of results
+     * @returns Matching ASNs, best matches first
+     */
+    #[napi]
+    pub fn search_asns(&self, query: String, limit: Option<u32>) -> Vec<AsnDetails> {
+        let limit = limit.map_or(usize::MAX, |l| l as usize);
+        self.db
+            .search_asns(&query)
+            .into_iter()
+            .take(limit)
+            .map(AsnDetails::from)
+            .collect()
+    }
+    /**
+     * List the ASNs registered in a country
+     * @param country - ISO country code, case-insensitive
+     */
+    #[napi]
+    pub fn asns_by_country(&self, country: String) -> Vec<AsnDetails> {
+        self.db.asns_by_country(&country).into_iter().map(AsnDetails::from).collect()
+    }
+    /**
+     * List the country codes present in the database
+     */
+    #[napi]
+    pub fn countries(&self) -> Vec<String> {
+        self.db.countries()
+    }
+    /**
+     * List the ranges announced by an ASN
+     * @param as_number - ASN number
+     */
+    #[napi]
+    pub fn ranges_for_asn(&self, as_number: u32) -> Vec<AsnRange> {
+        self.db.ranges_for_asn(as_number).iter().map(AsnRange::from).collect()
+    }
+    /**
+     * List the ranges registered in a country
+     * @param country - ISO country code, case-insensitive
+     */
+    #[napi]
+    pub fn ranges_by_country(&self, country: String) -> Vec<AsnRange> {
+        self.db.ranges_by_country(&country).iter().map(AsnRange::from).collect()
+    }
+    /**
+     * Get address space totals per country and the largest ASNs
+     * @param top - Number of ASNs in each top list (default 10)
+     */
+    #[napi]
+    pub fn aggregates(&self, top: Option<u32>) -> AggregatesResult {
+        AggregatesResult::new(&self.db.aggregates(), top.unwrap_or(10) as usize)
+    }
+    /**
+     * Get the address space announced by one ASN
+     * @param as_number - ASN number
+     * @returns Address space or null if the ASN isn't in the database
+     */
+    #[napi]
+    pub fn asn_space(&self, as_number: u32) -> Option<AddressSpace> {
+        self.db
+            .aggregates()
+            .asn(as_number)
+            .map(|aggregate| AddressSpace::from(&aggregate.totals))
+    }
+    /**
+     * Replace the overrides with the records of a file
+     * @param path - JSON array of { range, as_number, as_country_code, as_description }, or iptoasn TSV
+     * @returns Number of overrides loaded
+     */
+    #[napi]
+    pub fn load_overrides<'env>(&self, env: &'env Env, path: String) -> Result<PromiseRaw<'env, u32>> {
+        let db = self.db.clone();
+        self.spawn(env, async move {
+            db.load_overrides(std::path::Path::new(&path))
+                .await
+                .map(|count| count as u32)
+                .map_err(|e| Failure::app("Failed to load overrides", e))
+        })
+    }
+    /**
+     * Add an override that takes priority over upstream data
+     * @param range - CIDR prefix, "first-last" range or single address
+     * @param as_number - ASN number to report
+     * @param country_code - Country code to report
+     * @param description - Description to report
+     */
+    #[napi]
+    pub fn add_override<'env>(
+        &self,
+        env: &'env Env,
+        range: String,
+        as_number: u32,
+        country_code: Option<String>,
+        description: Option<String>,
+    ) -> Result<PromiseRaw<'env, ()>> {
+        let db = self.db.clone();
+        self.spawn(env, async move {
+            let range: IpRange = range
+                .parse()
+                .map_err(|e| Failure::app("Failed to add override", e))?;
+            db.add_override(override_record(
+                range,
+                as_number,
+                country_code.as_deref().unwrap_or(""),
+                description.as_deref().unwrap_or(""),
+            ))
+            .await;
+            Ok(())
+        })
+    }
+    /**
+     * Remove the override for exactly this range
+     * @param range - Range the override was added with
+     * @returns true if an override was removed
+     */
+    #[napi]
+    pub fn remove_override<'env>(&self, env: &'env Env, range: String) -> Result<PromiseRaw<'env, bool>> {
+        let db = self.db.clone();
+        self.spawn(env, async move {
+            let range: IpRange = range
+                .parse()
+                .map_err(|e| Failure::app("Failed to remove override", e))?;
+            Ok(db.remove_override(&range).await)
+        })
+    }
+    /**
+     * Remove all overrides
+     */
+    #[napi]
+    pub fn clear_overrides<'env>(&self, env: &'env Env) -> Result<PromiseRaw<'env, ()>> {
+        let db = self.db.clone();
+        self.spawn(env, async move {
+            db.clear_overrides().await;
+            Ok(())
+        })
+    }
+    /**
+     * List the overrides
+     * @returns Overrides sorted by range
+     */
+    #[napi]
+    pub fn list_overrides(&self) -> Vec<AsnRange> {
+        self.db.overrides().iter().map(AsnRange::from).collect()
+    }
+    /**
+     * Replace the tags with those of a file
+     * @param path - JSON { asns, ranges } or text lines of "<ASN or range> <tag>,<tag>"
+     * @returns Number of tagged ASNs and ranges
+     */
+    #[napi]
+    pub fn load_tags<'env>(&self, env: &'env Env, path: String) -> Result<PromiseRaw<'env, u32>> {
+        let (db, span) = (self.db.clone(), self.span());
+        errors::spawn(env, async move {
+            tokio::task::spawn_blocking(move || span.in_scope(|| db.load_tags(std::path::Path::new(&path))))
+                .await
+                .map_err(|e| Failure::internal(format!("Failed to load tags: {}", e)))?
+                .map(|count| count as u32)
+                .map_err(|e| Failure::app("Failed to load tags", e))
+        })
+    }
+    /**
+     * Reload the tags file if it changed since it was loaded
+     * @returns true if the tags were reloaded
+     */
+    #[napi]
+    pub fn reload_tags(&self, env: &Env) -> Result<bool> {
+        self.span()
+            .in_scope(|| self.db.reload_tags())
+            .map_err(|e| Failure::app("Failed to reload tags", e).into_error(env))
+    }
+    /**
+     * Reload the tags file automatically when it changes
+     * @param interval_seconds - How often to check the file (default 30)
+     */
+    #[napi]
+    pub fn watch_tags<'env>(&self, env: &'env Env, interval_seconds: Option<u32>) -> Result<PromiseRaw<'env, ()>> {
+        let (db, tags_watcher) = (self.db.clone(), self.tags_watcher.clone());
+        self.spawn(env, async move {
+            let mut watcher = tags_watcher.lock()
+                .map_err(|e| Failure::internal(format!("Failed to acquire lock: {}", e)))?;
+            if watcher.is_some() {
+                return Err(Failure::invalid_state("Tags are already being watched"));
+            }
+
+            let interval = Duration::from_secs(interval_seconds.unwrap_or(30).max(1) as u64);
+            *watcher = Some(db.start_tags_watcher(interval));
+            Ok(())
+        })
+    }
+    /**
+     * Stop watching the tags file
+     */
+    #[napi]
+    pub fn stop_watching_tags(&self, env: &Env) -> Result<()> {
+        let mut watcher = self.tags_watcher.lock()
+            .map_err(|e| Failure::internal(format!("Failed to acquire lock: {}", e)).into_error(env))?;
+        match watcher.take() {
+            Some(handle) => {
+                handle.abort();
+                Ok(())
+            }
+            None => Err(Failure::invalid_state("Tags are not being watched").into_error(env)),
+        }
+    }
+    /**
+     * Merge a cloud provider range list into lookups
+     * @param path - AWS ip-ranges.json, GCP cloud.json/goog.json, Azure Service Tags
+     *               or generic [{ range, provider, service?, region? }] JSON
+     * @param format - "aws", "gcp", "azure" or "generic"; detected when omitted
+     * @returns Number of new prefixes
+     */
+    #[napi]
+    pub fn load_provider_ranges<'env>(
+        &self,
+        env: &'env Env,
+        path: String,
+        format: Option<String>,
+    ) -> Result<PromiseRaw<'env, u32>> {
+        let (db, span) = (self.db.clone(), self.span());
+        errors::spawn(env, async move {
+            let format = format
+                .map(|f| f.parse::<ProviderFormat>())
+                .transpose()
+                .map_err(|e| Failure::invalid_arg(e.to_string()))?;
+            tokio::task::spawn_blocking(move || {
+                span.in_scope(|| db.load_provider_ranges(std::path::Path::new(&path), format))
+            })
+                .await
+                .map_err(|e| Failure::internal(format!("Failed to load provider ranges: {}", e)))?
+                .map(|count| count as u32)
+                .map_err(|e| Failure::app("Failed to load provider ranges", e))
+        })
+    }
+    /**
+     * Remove all cloud provider ranges
+     */
+    #[napi]
+    pub fn clear_provider_ranges(&self) {
+        self.db.clear_provider_ranges();
     }
     /**
      * Get database statistics
@@ -147,54 +1085,244 @@ impl IpToAsn {
     pub fn stats(&self) -> DatabaseStats {
         self.db.stats().into()
     }
+    /**
+     * Render lookup, update and data metrics for Prometheus
+     * @returns The text exposition format, ready to serve from a scrape endpoint
+     */
+    #[napi]
+    pub fn metrics(&self) -> String {
+        self.db.metrics()
+    }
+    /**
+     * Forward this instance's Rust log events to a JS callback
+     * @param callback - Receives each event; null removes the logger
+     * @param level - Most verbose level to forward: "error", "warn", "info", "debug" or "trace" (default: "info")
+     */
+    #[napi]
+    pub fn set_logger(&self, env: &Env, callback: Option<logging::LogCallback>, level: Option<String>) -> Result<()> {
+        let Some(callback) = callback else {
+            logging::remove_logger(self.id);
+            return Ok(());
+        };
+        let level = level.as_deref().unwrap_or("info");
+        let level = level
+            .parse::<tracing::Level>()
+            .map_err(|_| Failure::invalid_arg(format!("Unknown log level: {}", level)).into_error(env))?;
+        logging::set_logger(self.id, callback, level);
+        Ok(())
+    }
+    /**
+     * Set how many previous database versions to keep in the cache directory
+     * @param keep - Number of versions besides the current one (0 disables the archive)
+     */
+    #[napi]
+    pub fn set_keep_versions<'env>(&self, env: &'env Env, keep: u32) -> Result<PromiseRaw<'env, ()>> {
+        let db = self.db.clone();
+        self.spawn(env, async move {
+            db.set_keep_versions(keep as usize).await;
+            Ok(())
+        })
+    }
+    /**
+     * List the database versions kept in the cache directory
+     * @returns Versions, newest first
+     */
+    #[napi]
+    pub fn list_versions<'env>(&self, env: &'env Env) -> Result<PromiseRaw<'env, Vec<DatabaseVersion>>> {
+        let db = self.db.clone();
+        self.spawn(env, async move {
+            db.list_versions()
+                .await
+                .map(|versions| versions.into_iter().map(DatabaseVersion::from).collect())
+                .map_err(|e| Failure::app("Failed to list versions", e))
+        })
+    }
+    /**
+     * Roll back to a previously downloaded version
+     * @param id - Version id from listVersions()
+     */
+    #[napi]
+    pub fn rollback<'env>(&self, env: &'env Env, id: String) -> Result<PromiseRaw<'env, ()>> {
+        let db = self.db.clone();
+        self.spawn(env, async move {
+            db.rollback(&id)
+                .await
+                .map_err(|e| Failure::app("Rollback failed", e))
+        })
+    }
+    /**
+     * Pin a version so the updater won't replace it
+     * @param id - Version id from listVersions(), or null to unpin
+     */
+    #[napi]
+    pub fn pin_version<'env>(&self, env: &'env Env, id: Option<String>) -> Result<PromiseRaw<'env, ()>> {
+        let db = self.db.clone();
+        self.spawn(env, async move {
+            db.pin_version(id.as_deref())
+                .await
+                .map_err(|e| Failure::app("Failed to pin version", e))
+        })
+    }
+    /**
+     * Cache lookup results for the most recently looked up addresses
+     * @param capacity - Maximum number of cached results; 0 disables the cache
+     */
+    #[napi]
+    pub fn set_lookup_cache(&self, capacity: u32) {
+        self.db.set_lookup_cache(capacity as usize);
+    }
+    /**
+     * Set the maximum data age policy
+     * @param max_age_seconds - Maximum age of the loaded data, or null to disable the policy
+     * @param action - "warn" to log, "event" to emit a stale event, "fail" to fail lookups
+     */
+    #[napi]
+    pub fn set_max_age_policy(&self, env: &Env, max_age_seconds: Option<i64>, action: Option<String>) -> Result<()> {
+        let Some(max_age_seconds) = max_age_seconds else {
+            self.db.set_max_age_policy(None);
+            return Ok(());
+        };
+
+        if max_age_seconds <= 0 {
+            return Err(Failure::invalid_arg("Max age must be greater than 0").into_error(env));
+        }
+
+        let action = match action.as_deref().unwrap_or("warn") {
+            "warn" => StaleAction::Warn,
+            "event" => StaleAction::Event,
+            "fail" => StaleAction::Fail,
+            other => return Err(Failure::invalid_arg(format!("Unknown stale action: {}", other)).into_error(env)),
+        };
+
+        self.db.set_max_age_policy(Some(MaxAgePolicy::new(
+            Duration::from_secs(max_age_seconds as u64),
+            action,
+        )));
+        Ok(())
+    }
+    /**
+     * Create an enricher over the currently loaded database
+     * @param options - Where to find the IP address and how columns are delimited
+     */
+    #[napi]
+    pub fn create_enricher(&self, env: &Env, options: Option<EnrichOptions>) -> Result<Enricher> {
+        let options = options.unwrap_or(EnrichOptions {
+            field: None,
+            pattern: None,
+            delimiter: None,
+        });
+
+        let field = match options.pattern {
+            Some(pattern) => IpField::pattern(&pattern)
+                .map_err(|e| Failure::app("Invalid enrich options", e).into_error(env))?,
+            None => IpField::Column(options.field.unwrap_or(0) as usize),
+        };
+        let delimiter = match options.delimiter.as_deref() {
+            None => ',',
+            Some(d) if d.chars().count() == 1 => d.chars().next().unwrap_or(','),
+            Some(d) => {
+                return Err(Failure::invalid_arg(format!(
+                    "Invalid enrich options: delimiter must be a single character, got {:?}",
+                    d
+                ))
+                .into_error(env))
+            }
+        };
+
+        let inner = self.db.enricher(iptoasn_core::EnrichOptions {
+            field,
+            delimiter,
+            header: false,
+        });
+        Ok(Enricher {
+            inner: Arc::new(inner),
+        })
+    }
+    /**
+     * Register a callback for database events
+     * @param callback - Called with each DatabaseEvent
+     * @param with_diff - Include the diff in updated events (default true)
+     */
+    #[napi]
+    pub fn on_event(
+        &self,
+        // Weak, so a registered listener doesn't keep the process alive
+        callback: ThreadsafeFunction<DatabaseEvent, (), DatabaseEvent, Status, false, true>,
+        with_diff: Option<bool>,
+    ) {
+        // Diffs are only computed while a receiver that asked for them is alive
+        let (mut diffs, mut plain) = match with_diff.unwrap_or(true) {
+            true => (Some(self.db.subscribe_with_diffs()), None),
+            false => (None, Some(self.db.subscribe())),
+        };
+
+        napi::bindgen_prelude::spawn(async move {
+            let Some(events) = diffs.as_deref_mut().or(plain.as_mut()) else {
+                return;
+            };
+            loop {
+                match events.recv().await {
+                    Ok(event) => {
+                        callback.call(event.into(), ThreadsafeFunctionCallMode::NonBlocking);
+                    }
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        tracing::warn!("Event listener lagged, {} events dropped", skipped);
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                }
+            }
+        }.instrument(self.span()));
+    }
     /**
      * Start automatic database updates
      * @param interval_minutes - How often to check for updates (in minutes)
      */
     #[napi]
-    pub async fn start_auto_update(&self, interval_minutes: i64) -> Result<()> {
-        // Check if already running
-        {
-            let handle_guard = self.updater_handle.lock()
-                .map_err(|e| Error::from_reason(format!("Failed to acquire lock: {}", e)))?;
-            
-            if handle_guard.is_some() {
-                return Err(Error::from_reason("Auto-update is already running"));
+    pub fn start_auto_update<'env>(&self, env: &'env Env, interval_minutes: i64) -> Result<PromiseRaw<'env, ()>> {
+        let (db, updater_handle) = (self.db.clone(), self.updater_handle.clone());
+        self.spawn(env, async move {
+            // Check if already running
+            {
+                let handle_guard = updater_handle.lock()
+                    .map_err(|e| Failure::internal(format!("Failed to acquire lock: {}", e)))?;
+
+                if handle_guard.is_some() {
+                    return Err(Failure::invalid_state("Auto-update is already running"));
+                }
             }
-        }
 
-        if interval_minutes <= 0 {
-            return Err(Error::from_reason("Interval must be greater than 0"));
-        }
+            if interval_minutes <= 0 {
+                return Err(Failure::invalid_arg("Interval must be greater than 0"));
+            }
 
-        tracing::info!("Starting auto-update with interval: {} minutes", interval_minutes);
-        
-        let handle = self.db.start_updater(interval_minutes as u64).await;
-        
-        // Store the handle
-        {
-            let mut handle_guard = self.updater_handle.lock()
-                .map_err(|e| Error::from_reason(format!("Failed to acquire lock: {}", e)))?;
-            *handle_guard = Some(handle);
-        }
-        
-        Ok(())
+            tracing::info!("Starting auto-update with interval: {} minutes", interval_minutes);
+            let handle = db.start_updater(interval_minutes as u64).await;
+
+            // Store the handle
+            {
+                let mut handle_guard = updater_handle.lock()
+                    .map_err(|e| Failure::internal(format!("Failed to acquire lock: {}", e)))?;
+                *handle_guard = Some(handle);
+            }
+
+            Ok(())
+        })
     }
     /**
      * Stop automatic database updates
      * @returns Promise that resolves when auto-update is stopped
      */
     #[napi]
-    pub fn stop_auto_update(&self) -> Result<()> {
+    pub fn stop_auto_update(&self, env: &Env) -> Result<()> {
         let mut handle_guard = self.updater_handle.lock()
-            .map_err(|e| Error::from_reason(format!("Failed to acquire lock: {}", e)))?;
+            .map_err(|e| Failure::internal(format!("Failed to acquire lock: {}", e)).into_error(env))?;
         
         if let Some(handle) = handle_guard.take() {
             handle.abort();
-            tracing::info!("Auto-update stopped");
+            self.span().in_scope(|| tracing::info!("Auto-update stopped"));
             Ok(())
         } else {
-            Err(Error::from_reason("Auto-update is not running"))
+            Err(Failure::invalid_state("Auto-update is not running").into_error(env))
         }
     }
     /**
@@ -202,18 +1330,20 @@ impl IpToAsn {
      * @returns true if database was updated, false if already up-to-date
      */
     #[napi]
-    pub async fn force_update(&self) -> Result<bool> {
-        tracing::info!("Forcing database update check...");
-        
-        // Simply reload the database
-        self.db
-            .load()
-            .await
-            .map_err(|e| Error::from_reason(format!("Update failed: {}", e)))?;
-        
-        // We don't have a way to know if data changed vs cached,
-        // so we'll return true for simplicity
-        Ok(true)
+    pub fn force_update<'env>(&self, env: &'env Env) -> Result<PromiseRaw<'env, bool>> {
+        let db = self.db.clone();
+        self.spawn(env, async move {
+            tracing::info!("Forcing database update check...");
+
+            // Simply reload the database
+            db.load()
+                .await
+                .map_err(|e| Failure::app("Update failed", e))?;
+
+            // We don't have a way to know if data changed vs cached,
+            // so we'll return true for simplicity
+            Ok(true)
+        })
     }
 }
 /**
@@ -224,3 +1354,33 @@ impl IpToAsn {
 pub fn get_version() -> String {
     env!("CARGO_PKG_VERSION").to_string()
 }
+/**
+ * Turn Rust's own log output on or off
+ *
+ * Loggers registered with `setLogger` keep receiving events either way.
+ * @param enabled - false to stop printing Rust logs to stdout
+ */
+#[napi]
+pub fn set_rust_logging(enabled: bool) {
+    logging::set_stdout(enabled);
+}
+/**
+ * Compare two database files (gzipped or plain TSV)
+ * @param old_path - Path to the old database file
+ * @param new_path - Path to the new database file
+ * @returns Record-level differences
+ */
+#[napi]
+pub fn diff_files(env: &Env, old_path: String, new_path: String) -> Result<PromiseRaw<'_, DatabaseDiffResult>> {
+    errors::spawn(env, async move {
+        tokio::task::spawn_blocking(move || {
+            let old = Database::load_file(std::path::Path::new(&old_path))?;
+            let new = Database::load_file(std::path::Path::new(&new_path))?;
+            Ok(DatabaseDiff::compute(&old.records, &new.records))
+        })
+        .await
+        .map_err(|e| Failure::internal(format!("Diff task failed: {}", e)))?
+        .map(|diff: DatabaseDiff| DatabaseDiffResult::from(&diff))
+        .map_err(|e: iptoasn_core::AppError| Failure::app("Diff failed", e))
+    })
+}
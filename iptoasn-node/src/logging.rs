@@ -0,0 +1,259 @@
+use napi::bindgen_prelude::Either3;
+use napi::threadsafe_function::{ThreadsafeFunction, ThreadsafeFunctionCallMode};
+use napi::Status;
+use napi_derive::napi;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+use std::sync::{Arc, RwLock};
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Level, Metadata, Subscriber};
+use tracing_subscriber::layer::{Context, Layer};
+use tracing_subscriber::registry::LookupSpan;
+
+// Name of the span tying events to an IpToAsn instance
+pub const INSTANCE_SPAN: &str = "iptoasn";
+
+/**
+ * A Rust log event forwarded to a JS logger
+ * @property {string} level - "error", "warn", "info", "debug" or "trace"
+ * @property {string} target - Rust module that logged it, e.g. "iptoasn_core::fetcher"
+ * @property {string} message - The formatted message
+ * @property {Record<string, string | number | boolean>} fields - Structured fields of the event and its enclosing spans
+ */
+#[napi(object)]
+#[derive(Clone)]
+pub struct LogEntry {
+    pub level: String,
+    pub target: String,
+    pub message: String,
+    pub fields: HashMap<String, FieldValue>,
+}
+
+pub type FieldValue = Either3<String, f64, bool>;
+
+// Weak, so a registered logger doesn't keep the process alive
+pub type LogCallback = ThreadsafeFunction<LogEntry, (), LogEntry, Status, false, true>;
+
+struct Logger {
+    instance: u32,
+    level: Level,
+    callback: Arc<LogCallback>,
+}
+
+static LOGGERS: RwLock<Vec<Logger>> = RwLock::new(Vec::new());
+// Most verbose level any logger accepts, 0 when there are none
+static MAX_LEVEL: AtomicU8 = AtomicU8::new(0);
+// Whether Rust writes its own log lines to stdout
+static STDOUT: AtomicBool = AtomicBool::new(true);
+
+fn rank(level: &Level) -> u8 {
+    match *level {
+        Level::ERROR => 1,
+        Level::WARN => 2,
+        Level::INFO => 3,
+        Level::DEBUG => 4,
+        Level::TRACE => 5,
+    }
+}
+
+/**
+ * Register the logger of an instance, replacing any previous one
+ */
+pub fn set_logger(instance: u32, callback: LogCallback, level: Level) {
+    let mut loggers = LOGGERS.write().unwrap();
+    loggers.retain(|logger| logger.instance != instance);
+    loggers.push(Logger {
+        instance,
+        level,
+        callback: Arc::new(callback),
+    });
+    update_max_level(&loggers);
+}
+
+/**
+ * Remove the logger of an instance, if any
+ */
+pub fn remove_logger(instance: u32) {
+    let mut loggers = LOGGERS.write().unwrap();
+    loggers.retain(|logger| logger.instance != instance);
+    update_max_level(&loggers);
+}
+
+fn update_max_level(loggers: &[Logger]) {
+    let max = loggers.iter().map(|logger| rank(&logger.level)).max().unwrap_or(0);
+    MAX_LEVEL.store(max, Ordering::Relaxed);
+}
+
+/**
+ * Turn Rust's own stdout logging on or off
+ */
+pub fn set_stdout(enabled: bool) {
+    STDOUT.store(enabled, Ordering::Relaxed);
+}
+
+// Filter for the stdout layer, on top of RUST_LOG
+pub fn stdout_enabled(_: &Metadata<'_>) -> bool {
+    STDOUT.load(Ordering::Relaxed)
+}
+
+// Filter for the JS layer: our spans always, so events can be attributed to
+// an instance, and events no more verbose than some logger wants
+pub fn js_enabled(metadata: &Metadata<'_>) -> bool {
+    if metadata.is_span() {
+        return metadata.target().starts_with("iptoasn");
+    }
+    rank(metadata.level()) <= MAX_LEVEL.load(Ordering::Relaxed)
+}
+
+// Fields recorded on a span, kept in its extensions
+struct SpanFields(Vec<(&'static str, FieldValue)>);
+
+// Instance id of an instance span
+struct Instance(u32);
+
+#[derive(Default)]
+struct FieldVisitor {
+    message: Option<String>,
+    fields: Vec<(&'static str, FieldValue)>,
+}
+
+impl FieldVisitor {
+    fn push(&mut self, field: &Field, value: FieldValue) {
+        self.fields.retain(|(name, _)| *name != field.name());
+        self.fields.push((field.name(), value));
+    }
+}
+
+impl Visit for FieldVisitor {
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.push(field, Either3::B(value));
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.push(field, Either3::B(value as f64));
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.push(field, Either3::B(value as f64));
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.push(field, Either3::C(value));
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.push(field, Either3::A(value.to_string()));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        if field.name() == "message" {
+            self.message = Some(format!("{:?}", value));
+        } else {
+            self.push(field, Either3::A(format!("{:?}", value)));
+        }
+    }
+}
+
+/**
+ * Layer forwarding events to the JS loggers
+ *
+ * Events inside an instance span go to that instance's logger; events
+ * outside any instance, e.g. from a shared HTTP client, go to every logger.
+ * Background tasks run in root spans that follow from the call that started
+ * them, and inherit its instance through that link.
+ */
+pub struct JsLogLayer;
+
+impl<S> Layer<S> for JsLogLayer
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        let mut visitor = FieldVisitor::default();
+        attrs.record(&mut visitor);
+
+        let mut extensions = span.extensions_mut();
+        if attrs.metadata().name() == INSTANCE_SPAN {
+            if let Some((_, Either3::B(instance))) = visitor.fields.iter().find(|(name, _)| *name == "instance") {
+                extensions.insert(Instance(*instance as u32));
+            }
+            return;
+        }
+        extensions.insert(SpanFields(visitor.fields));
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        let mut extensions = span.extensions_mut();
+        if let Some(SpanFields(fields)) = extensions.get_mut::<SpanFields>() {
+            let mut visitor = FieldVisitor {
+                message: None,
+                fields: std::mem::take(fields),
+            };
+            values.record(&mut visitor);
+            *fields = visitor.fields;
+        }
+    }
+
+    fn on_follows_from(&self, id: &Id, follows: &Id, ctx: Context<'_, S>) {
+        let (Some(span), Some(origin)) = (ctx.span(id), ctx.span(follows)) else {
+            return;
+        };
+        let instance = origin
+            .scope()
+            .find_map(|span| span.extensions().get::<Instance>().map(|Instance(id)| *id));
+        if let Some(instance) = instance {
+            span.extensions_mut().insert(Instance(instance));
+        }
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        let metadata = event.metadata();
+        let mut instance = None;
+        let mut visitor = FieldVisitor::default();
+
+        // Outermost span first, so inner spans and the event win on clashes
+        if let Some(scope) = ctx.event_scope(event) {
+            for span in scope.from_root() {
+                let extensions = span.extensions();
+                if let Some(Instance(id)) = extensions.get::<Instance>() {
+                    instance = Some(*id);
+                }
+                if let Some(SpanFields(fields)) = extensions.get::<SpanFields>() {
+                    visitor.fields.extend(fields.iter().cloned());
+                }
+            }
+        }
+        event.record(&mut visitor);
+
+        let loggers = LOGGERS.read().unwrap();
+        let recipients: Vec<&Logger> = loggers
+            .iter()
+            .filter(|logger| instance.is_none_or(|id| id == logger.instance))
+            .filter(|logger| metadata.level() <= &logger.level)
+            .collect();
+        if recipients.is_empty() {
+            return;
+        }
+
+        let entry = LogEntry {
+            level: metadata.level().as_str().to_ascii_lowercase(),
+            target: metadata.target().to_string(),
+            message: visitor.message.unwrap_or_default(),
+            fields: visitor
+                .fields
+                .into_iter()
+                .map(|(name, value)| (name.to_string(), value))
+                .collect(),
+        };
+        for logger in recipients {
+            logger.callback.call(entry.clone(), ThreadsafeFunctionCallMode::NonBlocking);
+        }
+    }
+}
@@ -0,0 +1,16 @@
+import { Transform } from 'stream'
+import { EnrichOptions, IpToAsn } from './index'
+
+/** * Options for createEnrichStream
+ * @property {boolean} header - Whether the first line is a header
+ */
+export interface EnrichStreamOptions extends EnrichOptions {
+  header?: boolean
+}
+
+/** * Create a Transform stream that appends AS number, country and description
+ * columns to each line of delimited text
+ * @param db - Loaded database
+ * @param options - Where to find the IP address, the delimiter and whether there is a header
+ */
+export declare function createEnrichStream(db: IpToAsn, options?: EnrichStreamOptions): Transform
@@ -0,0 +1,49 @@
+const { Transform } = require('stream')
+const { StringDecoder } = require('string_decoder')
+
+/**
+ * Create a Transform stream that appends AS number, country and description
+ * columns to each line of delimited text.
+ * @param {import('./index').IpToAsn} db - Loaded database
+ * @param {object} [options]
+ * @param {number} [options.field] - Zero-based column holding the IP address (default 0)
+ * @param {string} [options.pattern] - Regular expression locating the IP address instead of a column
+ * @param {string} [options.delimiter] - Column delimiter (default ",")
+ * @param {boolean} [options.header] - Whether the first line is a header
+ * @returns {Transform}
+ */
+function createEnrichStream(db, options = {}) {
+  const { header = false, ...enrichOptions } = options
+  const enricher = db.createEnricher(enrichOptions)
+  const decoder = new StringDecoder('utf8')
+  let pending = ''
+  let headerDone = !header
+
+  const enrich = async (lines) => {
+    lines = lines.map((line) => (line.endsWith('\r') ? line.slice(0, -1) : line))
+    const out = []
+    if (!headerDone && lines.length > 0) {
+      out.push(enricher.enrichHeader(lines.shift()))
+      headerDone = true
+    }
+    if (lines.length > 0) {
+      out.push(...(await enricher.enrichLines(lines)))
+    }
+    return out.length > 0 ? out.join('\n') + '\n' : ''
+  }
+
+  return new Transform({
+    transform(chunk, encoding, callback) {
+      const lines = (pending + decoder.write(chunk)).split('\n')
+      pending = lines.pop()
+      enrich(lines).then((text) => callback(null, text), callback)
+    },
+    flush(callback) {
+      const rest = pending + decoder.end()
+      pending = ''
+      enrich(rest ? [rest] : []).then((text) => callback(null, text), callback)
+    },
+  })
+}
+
+module.exports = { createEnrichStream }
@@ -0,0 +1,150 @@
+mod routes;
+
+use clap::Parser;
+use iptoasn_core::IpToAsnDb;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::info;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+
+/**
+ * Standalone IP to ASN lookup server
+ */
+#[derive(Parser)]
+#[command(name = "iptoasn-server", version, about)]
+struct Config {
+    /// Database URL (HTTP/HTTPS or file://)
+    #[arg(
+        long,
+        env = "DATABASE_URL",
+        default_value = "https://iptoasn.com/data/ip2asn-combined.tsv.gz"
+    )]
+    database_url: String,
+
+    /// Directory for caching downloaded databases
+    #[arg(long, env = "CACHE_DIR", default_value = "./cache")]
+    cache_dir: String,
+
+    /// How often to check for updates, in minutes (0 disables updates)
+    #[arg(long, env = "UPDATE_INTERVAL", default_value_t = 60)]
+    update_interval: u64,
+
+    /// Overrides file (JSON or iptoasn TSV) whose records take priority over upstream data
+    #[arg(long, env = "OVERRIDES_FILE")]
+    overrides: Option<PathBuf>,
+
+    /// Tags file (JSON or text) classifying ASNs and ranges; reloaded when it changes
+    #[arg(long, env = "TAGS_FILE")]
+    tags: Option<PathBuf>,
+
+    /// Cloud provider range lists (AWS, GCP, Azure or generic JSON), comma-separated
+    #[arg(long, env = "PROVIDER_FILES", value_delimiter = ',')]
+    providers: Vec<PathBuf>,
+
+    /// Number of lookup results to cache for hot addresses (0 disables the cache)
+    #[arg(long, env = "LOOKUP_CACHE_SIZE", default_value_t = 0)]
+    lookup_cache: usize,
+
+    /// Address to listen on
+    #[arg(long, env = "LISTEN", default_value = "127.0.0.1:3000")]
+    listen: SocketAddr,
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let registry = tracing_subscriber::registry()
+        .with(
+            tracing_subscriber::EnvFilter::try_from_default_env()
+                .unwrap_or_else(|_| "info".into()),
+        )
+        .with(tracing_subscriber::fmt::layer());
+
+    #[cfg(feature = "otlp")]
+    let (registry, tracer_provider) = {
+        let otlp = iptoasn_core::telemetry::otlp_layer("iptoasn-server")?;
+        let (layer, provider) = otlp.map(|otlp| (otlp.layer, otlp.provider)).unzip();
+        (registry.with(layer), provider)
+    };
+
+    registry.init();
+
+    let config = Config::parse();
+
+    let db = Arc::new(IpToAsnDb::new(config.database_url, config.cache_dir)?);
+
+    db.set_lookup_cache(config.lookup_cache);
+
+    if let Some(path) = &config.overrides {
+        db.load_overrides(path).await?;
+    }
+
+    for path in &config.providers {
+        db.load_provider_ranges(path, None)?;
+    }
+
+    let tags_watcher = match &config.tags {
+        Some(path) => {
+            db.load_tags(path)?;
+            Some(db.start_tags_watcher(Duration::from_secs(30)))
+        }
+        None => None,
+    };
+
+    // Serve from the cache right away; the source is checked in the background
+    db.load_cached_first().await?;
+
+    let updater = if config.update_interval > 0 {
+        Some(db.start_updater(config.update_interval).await)
+    } else {
+        None
+    };
+
+    let listener = tokio::net::TcpListener::bind(config.listen).await?;
+    info!("Listening on http://{}", config.listen);
+
+    axum::serve(listener, routes::router(db))
+        .with_graceful_shutdown(shutdown_signal())
+        .await?;
+
+    for handle in [updater, tags_watcher].into_iter().flatten() {
+        handle.abort();
+    }
+    info!("Server stopped");
+
+    // Flush buffered spans
+    #[cfg(feature = "otlp")]
+    if let Some(provider) = tracer_provider {
+        provider.shutdown()?;
+    }
+    Ok(())
+}
+
+// Resolve on Ctrl+C or SIGTERM
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("Failed to install Ctrl+C handler");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("Failed to install SIGTERM handler")
+            .recv()
+            .await;
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+
+    info!("Shutdown signal received, draining connections...");
+}
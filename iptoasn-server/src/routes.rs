@@ -0,0 +1,172 @@
+use axum::extract::{Path, State};
+use axum::http::{header, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::{Json, Router};
+use iptoasn_core::{AppError, AsnInfo, IpToAsnDb};
+use serde_json::{json, Value};
+use std::sync::Arc;
+use std::time::SystemTime;
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+
+/**
+ * Build the HTTP routes
+ */
+pub fn router(db: Arc<IpToAsnDb>) -> Router {
+    Router::new()
+        .route("/v1/as/ip/{ip}", get(lookup))
+        .route("/health", get(health))
+        .route("/stats", get(stats))
+        .route("/metrics", get(metrics))
+        .with_state(db)
+}
+
+// Format a timestamp as RFC 3339, or null
+fn timestamp(time: Option<SystemTime>) -> Value {
+    time.and_then(|t| OffsetDateTime::from(t).format(&Rfc3339).ok())
+        .map(Value::String)
+        .unwrap_or(Value::Null)
+}
+
+// JSON error body with the given status
+fn error(status: StatusCode, message: &str, e: &AppError) -> Response {
+    (status, Json(json!({ "error": message, "code": e.code(), "message": e.to_string() }))).into_response()
+}
+
+// Provider annotation of a lookup, or null
+fn cloud(info: &AsnInfo) -> Value {
+    match &info.cloud_provider {
+        Some(provider) => json!({
+            "provider": provider,
+            "service": info.cloud_service,
+            "region": info.cloud_region,
+        }),
+        None => Value::Null,
+    }
+}
+
+// iptoasn.com-compatible lookup endpoint
+async fn lookup(State(db): State<Arc<IpToAsnDb>>, Path(ip): Path<String>) -> Response {
+    match db.lookup(&ip) {
+        Ok(info) if info.announced => Json(json!({
+            "ip": info.ip,
+            "announced": true,
+            "first_ip": info.first_ip,
+            "last_ip": info.last_ip,
+            "as_number": info.as_number,
+            "as_country_code": info.as_country_code,
+            "as_description": info.as_description,
+            "override": info.is_override,
+            "tags": info.tags,
+            "cloud": cloud(&info),
+        }))
+        .into_response(),
+        Ok(info) => (
+            StatusCode::NOT_FOUND,
+            Json(json!({ "ip": info.ip, "announced": false, "tags": info.tags, "cloud": cloud(&info) })),
+        )
+            .into_response(),
+        Err(e @ AppError::InvalidIp(_)) => error(StatusCode::BAD_REQUEST, "Invalid IP address", &e),
+        Err(e @ AppError::StaleDatabase { .. }) => {
+            error(StatusCode::SERVICE_UNAVAILABLE, "Database is stale", &e)
+        }
+        Err(e) => error(StatusCode::INTERNAL_SERVER_ERROR, "Lookup failed", &e),
+    }
+}
+
+// Healthy once a database with records is loaded
+async fn health(State(db): State<Arc<IpToAsnDb>>) -> Response {
+    let stats = db.stats();
+
+    if stats.record_count == 0 {
+        return (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(json!({ "status": "initializing" })),
+        )
+            .into_response();
+    }
+
+    Json(json!({
+        "status": if stats.stale { "stale" } else { "healthy" },
+        "records": stats.record_count,
+        "lastUpdate": timestamp(stats.last_update),
+    }))
+    .into_response()
+}
+
+async fn stats(State(db): State<Arc<IpToAsnDb>>) -> Json<Value> {
+    let stats = db.stats();
+
+    Json(json!({
+        "record_count": stats.record_count,
+        "last_update": timestamp(stats.last_update),
+        "data_modified": timestamp(stats.data_modified),
+        "downloaded_at": timestamp(stats.downloaded_at),
+        "data_age_seconds": stats.data_age.map(|d| d.as_secs()),
+        "stale": stats.stale,
+        "cache": stats.cache.map(|cache| json!({
+            "capacity": cache.capacity,
+            "entries": cache.entries,
+            "hits": cache.hits,
+            "misses": cache.misses,
+        })),
+    }))
+}
+
+// Prometheus scrape endpoint
+async fn metrics(State(db): State<Arc<IpToAsnDb>>) -> Response {
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        db.metrics(),
+    )
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use axum::http::Request;
+    use flate2::write::GzEncoder;
+    use std::io::Write;
+    use tower::ServiceExt;
+
+    // A database in a fresh directory unique to the test and this process
+    async fn test_db(name: &str) -> Arc<IpToAsnDb> {
+        let dir = std::env::temp_dir().join(format!("iptoasn_test_server_{}_{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder
+            .write_all(b"8.8.8.0\t8.8.8.255\t15169\tUS\tGOOGLE\n")
+            .unwrap();
+        let source = dir.join("source.tsv.gz");
+        std::fs::write(&source, encoder.finish().unwrap()).unwrap();
+
+        let db = IpToAsnDb::new(
+            format!("file://{}", source.display()),
+            dir.join("cache").to_string_lossy().to_string(),
+        )
+        .unwrap();
+        db.load().await.unwrap();
+        Arc::new(db)
+    }
+
+    async fn status(app: Router, uri: &str) -> StatusCode {
+        let request = Request::builder().uri(uri).body(Body::empty()).unwrap();
+        app.oneshot(request).await.unwrap().status()
+    }
+
+    #[tokio::test]
+    async fn test_lookup_status_codes() {
+        let app = router(test_db("status_codes").await);
+
+        assert_eq!(status(app.clone(), "/v1/as/ip/8.8.8.8").await, StatusCode::OK);
+        assert_eq!(status(app.clone(), "/v1/as/ip/9.9.9.9").await, StatusCode::NOT_FOUND);
+        assert_eq!(status(app.clone(), "/v1/as/ip/not-an-ip").await, StatusCode::BAD_REQUEST);
+        assert_eq!(status(app.clone(), "/health").await, StatusCode::OK);
+        assert_eq!(status(app, "/metrics").await, StatusCode::OK);
+    }
+}